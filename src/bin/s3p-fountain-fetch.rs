@@ -5,26 +5,43 @@ use std::{
     fs::{self, File, OpenOptions},
     io::{BufWriter, Write},
     net::UdpSocket,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
     time::{Duration, Instant},
 };
 
-use s3p_core::fountain::{join_blocks, peel_decode, Packet};
+use s3p_cli::fountain::{decode, packet_fits, Gf2Solver, Row};
+use s3p_core::fountain::{join_blocks, Packet};
 
 fn usage() -> ! {
     eprintln!(
         "Usage:
-  s3p-fountain-fetch <out_dir> --bind=<IP:port> [--timeout-ms=<N>]
+  s3p-fountain-fetch <out_dir> --bind=<IP:port> [--timeout-ms=<N>] [--stats-every-s=<N>]
 
 Behavior:
   - Ждёт UDP-кадры:
       'M\\n' + JSON(meta) и 'P\\n' + JSON(packet)
   - Сохраняет:
-      <out_dir>/fountain_meta.json           (первая принятая мета)
+      <out_dir>/fountain_meta.json           (последняя принятая мета)
       <out_dir>/fountain_packets.jsonl       (дописывается по мере приёма; формат: {{ids, body_hex}})
-  - На успешном peel-декоде:
+  - Смена меты = новый поток: пакеты прежней сбрасываются из памяти и из jsonl, ранг
+      считается заново — в декодер попадают только пакеты текущей меты.
+  - Ранг принятого набора над GF(2) считается инкрементально; декод (peel, при застревании —
+      Гаусс) запускается только когда ранг достиг k, и тогда гарантированно успешен:
       <out_dir>/recovered_ct.bin             (ciphertext, обрезанный до ct_len)
-      и завершает работу (exit 0)."
+      и завершает работу (exit 0).
+  - По --timeout-ms: exit 3 (ранг всё ещё < k; текущий ранг — в сообщении).
+  - По SIGINT (Ctrl+C): сбрасывает fountain_packets.jsonl на диск и выходит с exit 0
+      (накопленные пакеты пригодны для последующего 's3p unpack-fountain').
+  - В конце (успех/таймаут/SIGINT) печатает в stderr сводку: байты, пакеты, время, pps,
+      при декоде — overhead = пакетов/k; --stats-every-s=N — промежуточные строки с pps и ETA.
+  - 'R\\n'-пробы ('s3p-fountain-serve --probe') возвращаются отправителю как есть — по ним
+      serve оценивает RTT и стартовый pps.
+  - P-кадры с битым JSON, пустыми/повторными ids, ids вне 0..k или телом не block_len байт
+      отбрасываются до декодера и не пишутся в jsonl; их число — rejected= в сводке.
+  - Пакет, тело которого противоречит уже принятым (те же блоки — другой XOR), тоже отброшен
+      (inconsistent= в сводке); какая из копий испорчена, не узнать, поэтому при полном ранге
+      такой поток не декодируется: exit 2 без recovered_ct.bin."
     );
     std::process::exit(1)
 }
@@ -98,6 +115,95 @@ fn parse_packet_json(v: &Value) -> Option<WirePacket> {
     None
 }
 
+// Счётчики приёма для сводки (на горячий путь — только пара сложений)
+struct FetchStats {
+    start: Instant,
+    bytes: u64,        // все принятые датаграммы, включая мету и мусор
+    packets: u64,      // принятые P-кадры
+    rejected: u64, // P-кадры, отброшенные до декодера (битый JSON, ids вне 0..k, длина тела ≠ block_len)
+    dependent: u64, // принятые, но линейно зависимые (ранг не вырос)
+    inconsistent: u64, // зависимые, но с телом, противоречащим принятым (в декодер не идут)
+    decode_attempts: u64,
+    last_report: Instant,
+    last_packets: u64,
+}
+
+impl FetchStats {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            bytes: 0,
+            packets: 0,
+            rejected: 0,
+            dependent: 0,
+            inconsistent: 0,
+            decode_attempts: 0,
+            last_report: now,
+            last_packets: 0,
+        }
+    }
+
+    /// Промежуточная строка: pps за последний интервал и грубая оценка времени до k пакетов
+    fn report_progress(&mut self, k: Option<usize>) {
+        let dt = self.last_report.elapsed().as_secs_f64();
+        let pps = (self.packets - self.last_packets) as f64 / dt.max(1e-3);
+        let eta = match k {
+            Some(k) if pps > 0.0 && (self.packets as usize) < k => {
+                format!(", eta≥{:.1}s", (k - self.packets as usize) as f64 / pps)
+            }
+            _ => String::new(),
+        };
+        eprintln!(
+            "stats: {} packets, {} bytes, {:.0} pps{eta}",
+            self.packets, self.bytes, pps
+        );
+        self.last_report = Instant::now();
+        self.last_packets = self.packets;
+    }
+
+    fn summary(&self, outcome: &str, k: Option<usize>, decoded: bool) {
+        let secs = self.start.elapsed().as_secs_f64();
+        let overhead = match k {
+            Some(k) if decoded && k > 0 => {
+                format!(", overhead={:.3}", self.packets as f64 / k as f64)
+            }
+            _ => String::new(),
+        };
+        eprintln!(
+            "summary: {outcome}, packets={}, rejected={}, dependent={}, inconsistent={}, decode_attempts={}, bytes={}, elapsed={:.2}s, {:.0} pps, {:.1} KiB/s{overhead}",
+            self.packets,
+            self.rejected,
+            self.dependent,
+            self.inconsistent,
+            self.decode_attempts,
+            self.bytes,
+            secs,
+            self.packets as f64 / secs.max(1e-3),
+            self.bytes as f64 / 1024.0 / secs.max(1e-3)
+        );
+    }
+}
+
+fn finish_decoded(
+    out_dir: &Path,
+    decoded: &[Vec<u8>],
+    ct_len: usize,
+    total: usize,
+    stats: &FetchStats,
+) -> ! {
+    let ct = join_blocks(decoded, ct_len);
+    fs::write(out_dir.join("recovered_ct.bin"), &ct).expect("write ct");
+    eprintln!(
+        "DECODED: {total} packets → recovered_ct.bin ({} bytes)",
+        ct.len()
+    );
+    stats.summary("decoded", Some(decoded.len()), true);
+    std::process::exit(0)
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
 // MSRV 1.74: используем % с точечным allow, чтобы не ловить clippy::manual_is_multiple_of
 #[inline]
 #[allow(clippy::manual_is_multiple_of)]
@@ -116,10 +222,16 @@ fn main() {
         .unwrap_or_else(|| "0".into())
         .parse()
         .unwrap_or(0);
+    let stats_every = flag(&args, "stats-every-s")
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|&s| s > 0)
+        .map(Duration::from_secs);
 
     let _ = fs::create_dir_all(&out_dir);
     let sock = UdpSocket::bind(&bind).expect("bind");
     let _ = sock.set_read_timeout(Some(Duration::from_millis(500)));
+    // recv просыпается не реже раза в 500 мс, так что флаг проверяется своевременно
+    ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst)).expect("set SIGINT handler");
     eprintln!("listening on {bind}, writing to {}", out_dir.display());
 
     // Пути
@@ -135,6 +247,8 @@ fn main() {
     let mut meta: Option<RecvMeta> = None;
     let mut meta_raw_cache: Option<Vec<u8>> = None;
     let mut recv_packets_mem: Vec<WirePacket> = Vec::new();
+    let mut rank = Gf2Solver::new(0);
+    let mut conflicts = 0u64; // противоречивые пакеты текущего потока
 
     let mut jsonl_writer: Option<BufWriter<File>> = OpenOptions::new()
         .create(true)
@@ -145,57 +259,135 @@ fn main() {
 
     let mut buf = vec![0u8; 64 * 1024];
     let start = Instant::now();
+    let mut stats = FetchStats::new();
 
     loop {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            if let Some(mut w) = jsonl_writer.take() {
+                let _ = w.flush();
+            }
+            eprintln!(
+                "interrupted: {} packets saved to {}",
+                recv_packets_mem.len(),
+                jsonl_path.display()
+            );
+            stats.summary("interrupted", meta.as_ref().map(|m| m.k), false);
+            std::process::exit(0);
+        }
+
         if timeout_ms > 0 && start.elapsed() > Duration::from_millis(timeout_ms) {
-            eprintln!("timeout, no solution");
+            if let Some(mut w) = jsonl_writer.take() {
+                let _ = w.flush();
+            }
+            // набор полного ранга декодируется сразу при приёме, так что здесь ранг < k
+            match meta.as_ref() {
+                Some(m) => eprintln!("timeout, no solution (rank {}/{})", rank.rank(), m.k),
+                None => eprintln!("timeout, no solution"),
+            }
+            stats.summary("timeout", meta.as_ref().map(|m| m.k), false);
             std::process::exit(3);
         }
 
-        match sock.recv(&mut buf[..]) {
+        if let Some(every) = stats_every {
+            if stats.last_report.elapsed() >= every {
+                stats.report_progress(meta.as_ref().map(|m| m.k));
+            }
+        }
+
+        let recv = sock.recv_from(&mut buf[..]);
+        if let Ok((n, _)) = recv {
+            stats.bytes += n as u64;
+        }
+        match recv.map(|(n, from)| {
+            // проба канала: эхо отправителю, в статистику пакетов не идёт
+            if n >= 2 && &buf[..2] == b"R\n" {
+                let _ = sock.send_to(&buf[..n], from);
+            }
+            n
+        }) {
             Ok(n) if n >= 2 && &buf[1..2] == b"\n" => match buf[0] {
-                b'M' => {
-                    // META (дебаунс по байтам)
-                    if meta.is_none()
-                        || meta_raw_cache
-                            .as_ref()
-                            .map(|m| m.as_slice() != &buf[2..n])
-                            .unwrap_or(true)
-                    {
-                        let v: Value = match serde_json::from_slice(&buf[2..n]) {
-                            Ok(v) => v,
-                            Err(e) => {
-                                eprintln!("meta parse err: {e}");
-                                continue;
-                            }
-                        };
-                        let k = v.get("k").and_then(|x| x.as_u64()).unwrap_or(0) as usize;
-                        let block_len =
-                            v.get("block_len").and_then(|x| x.as_u64()).unwrap_or(0) as usize;
-                        let ct_len = v.get("ct_len").and_then(|x| x.as_u64()).unwrap_or(0) as usize;
-                        if k == 0 || block_len == 0 || ct_len == 0 {
-                            eprintln!("meta missing k/block_len/ct_len");
+                // META (дебаунс по байтам)
+                b'M' if meta.is_none()
+                    || meta_raw_cache
+                        .as_ref()
+                        .map(|m| m.as_slice() != &buf[2..n])
+                        .unwrap_or(true) =>
+                {
+                    let v: Value = match serde_json::from_slice(&buf[2..n]) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            eprintln!("meta parse err: {e}");
                             continue;
                         }
-                        meta = Some(RecvMeta {
-                            k,
-                            block_len,
-                            ct_len,
-                        });
-                        let _ = fs::write(&meta_path, &buf[2..n]);
-                        meta_raw_cache = Some(buf[2..n].to_vec());
-                        eprintln!("meta received: k={k}, block_len={block_len}, ct_len={ct_len}");
+                    };
+                    let k = v.get("k").and_then(|x| x.as_u64()).unwrap_or(0) as usize;
+                    let block_len =
+                        v.get("block_len").and_then(|x| x.as_u64()).unwrap_or(0) as usize;
+                    let ct_len = v.get("ct_len").and_then(|x| x.as_u64()).unwrap_or(0) as usize;
+                    if k == 0 || block_len == 0 || ct_len == 0 {
+                        eprintln!("meta missing k/block_len/ct_len");
+                        continue;
                     }
+                    meta = Some(RecvMeta {
+                        k,
+                        block_len,
+                        ct_len,
+                    });
+                    // новая мета — новый поток: пакеты прежней по форме могут и подойти,
+                    // но XOR-ятся из чужих блоков и дали бы мусор вместо ct. Сбрасываем их
+                    // из памяти, ранг и jsonl (он должен соответствовать fountain_meta.json)
+                    if !recv_packets_mem.is_empty() {
+                        eprintln!(
+                            "meta changed: dropping {} packet(s) of the previous stream",
+                            recv_packets_mem.len()
+                        );
+                        recv_packets_mem.clear();
+                        jsonl_writer = OpenOptions::new()
+                            .create(true)
+                            .write(true)
+                            .truncate(true)
+                            .open(&jsonl_path)
+                            .ok()
+                            .map(BufWriter::new);
+                    }
+                    rank = Gf2Solver::new(k);
+                    conflicts = 0;
+                    let _ = fs::write(&meta_path, &buf[2..n]);
+                    meta_raw_cache = Some(buf[2..n].to_vec());
+                    eprintln!("meta received: k={k}, block_len={block_len}, ct_len={ct_len}");
                 }
                 b'P' => {
                     if meta.is_none() {
                         continue;
                     }
-                    let v: Value = match serde_json::from_slice(&buf[2..n]) {
-                        Ok(v) => v,
-                        Err(_) => continue,
-                    };
-                    if let Some(wp) = parse_packet_json(&v) {
+                    let m = meta.as_ref().unwrap();
+                    let wp = serde_json::from_slice::<Value>(&buf[2..n])
+                        .ok()
+                        .and_then(|v| parse_packet_json(&v))
+                        .filter(|wp| packet_fits(&wp.ids, wp.body.len(), m.k, m.block_len));
+                    if wp.is_none() {
+                        stats.rejected += 1;
+                        if stats.rejected.is_power_of_two() {
+                            eprintln!("rejected {} malformed packet(s)", stats.rejected);
+                        }
+                    }
+                    if let Some(wp) = wp {
+                        stats.packets += 1;
+                        // тело — в решатель: зависимый пакет с ненулевым остатком противоречит принятым
+                        match rank.insert_row(&wp.ids, &wp.body) {
+                            Row::Independent => {}
+                            Row::Redundant => stats.dependent += 1,
+                            Row::Inconsistent => {
+                                stats.inconsistent += 1;
+                                conflicts += 1;
+                                eprintln!(
+                                    "inconsistent packet ids={:?}: body contradicts earlier packets, dropped",
+                                    wp.ids
+                                );
+                                continue;
+                            }
+                        }
+
                         // Копим в памяти для декодера
                         recv_packets_mem.push(WirePacket {
                             ids: wp.ids.clone(),
@@ -218,8 +410,22 @@ fn main() {
                             eprintln!("received {total} packets...");
                         }
 
-                        // Пробуем декодировать
-                        let m = meta.as_ref().unwrap();
+                        // до полного ранга декод заведомо не сойдётся — не тратим на него CPU
+                        if !rank.is_full() {
+                            continue;
+                        }
+                        // решение единственно, но испорченная копия могла прийти первой
+                        if conflicts > 0 {
+                            if let Some(mut w) = jsonl_writer.take() {
+                                let _ = w.flush();
+                            }
+                            eprintln!(
+                                "error: {conflicts} packet(s) contradict the accepted set; cannot tell which copy is corrupt, recovered_ct.bin not written"
+                            );
+                            stats.summary("inconsistent", Some(m.k), false);
+                            std::process::exit(2);
+                        }
+                        stats.decode_attempts += 1;
                         let packets: Vec<Packet> = recv_packets_mem
                             .iter()
                             .map(|w| Packet {
@@ -227,18 +433,12 @@ fn main() {
                                 body: w.body.clone(),
                             })
                             .collect();
-
-                        if let Some(decoded) = peel_decode(m.k, m.block_len, packets) {
-                            let ct = join_blocks(&decoded, m.ct_len);
-                            fs::write(out_dir.join("recovered_ct.bin"), &ct).expect("write ct");
-                            eprintln!(
-                                "DECODED: {total} packets → recovered_ct.bin ({} bytes)",
-                                ct.len()
-                            );
+                        // peel быстрее, но может застрять и на наборе полного ранга — тогда Гаусс
+                        if let Some(decoded) = decode(m.k, m.block_len, &packets) {
                             if let Some(mut w) = jsonl_writer.take() {
                                 let _ = w.flush();
                             }
-                            std::process::exit(0);
+                            finish_decoded(&out_dir, &decoded, m.ct_len, total, &stats);
                         }
                     }
                 }
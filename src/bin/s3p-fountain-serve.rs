@@ -1,23 +1,38 @@
 use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::{
     env,
     fs::File,
-    io::{BufRead, BufReader},
+    io::{self, BufRead, BufReader},
     net::UdpSocket,
+    path::PathBuf,
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+use s3p_cli::fountain::robust_soliton;
+use s3p_core::fountain::{partition_into_blocks, FountainEncoder, FountainParams, Packet};
+
 fn usage() -> ! {
     eprintln!(
 "Usage:
   s3p-fountain-serve <fountain_dir> --to=<IP:port> [--bind=<IP:port>] [--loss=<0.0..1.0>] [--pps=<N>] [--loop]
+                     [--generate [--ct=<file>]] [--retries=<N>] [--probe]
 
 Notes:
   - <fountain_dir> должен содержать fountain_meta.json и fountain_packets.jsonl (из 's3p pack-fountain')
   - --pps   : пакетов в секунду (по умолчанию 500)
   - --loss  : искусственная вероятность дропа пакета на передающей стороне (по умолчанию 0.0)
-  - --loop  : по достижении конца файла пакетов — начать заново"
+  - --loop  : по достижении конца файла пакетов — начать заново
+  - --retries : повторов send при переполнении сокета (WouldBlock/ENOBUFS) с экспоненциальной
+      паузой от 200 мкс (по умолчанию 3); реальные потери считаются отдельно от имитации --loss
+  - --generate : не читать fountain_packets.jsonl, а порождать пакеты на лету из шифртекста
+      (--ct, по умолчанию <fountain_dir>/recovered_ct.bin; см. 's3p pack-fountain --ct-only')
+      с seed/c/delta/systematic(_count) из fountain_meta.json — те же пакеты, что дал бы pack-fountain;
+      с --loop энкодер не перезапускается, а продолжает выдавать новые пакеты без конца
+  - --probe : перед потоком послать несколько проб ('R\\n' + JSON), дождаться эха от
+      s3p-fountain-fetch и выставить pps = окно / медианный RTT с поправкой на долю вернувшихся
+      проб (в пределах 50..20000); без эха за 1 с остаётся --pps"
     );
     std::process::exit(1)
 }
@@ -34,27 +49,225 @@ fn flag(args: &[String], name: &str) -> Option<String> {
     None
 }
 
+// Поля fountain_meta.json, нужные для генерации
+#[derive(Deserialize)]
+struct GenMeta {
+    ct_len: usize,
+    k: usize,
+    block_len: usize,
+    packets: usize,
+    seed: u64,
+    c: f64,
+    delta: f64,
+    #[serde(default = "default_true")]
+    systematic: bool,
+    #[serde(default)]
+    systematic_count: Option<usize>, // нет в старых meta — k при systematic, иначе 0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize)]
+struct JsonPkt<'a> {
+    ids: &'a [usize],
+    body_hex: String,
+}
+
+// ENOBUFS: очередь отправки ядра переполнена — стоит подождать и повторить
+#[cfg(target_os = "linux")]
+const ENOBUFS: i32 = 105;
+#[cfg(windows)]
+const ENOBUFS: i32 = 10055; // WSAENOBUFS
+#[cfg(not(any(target_os = "linux", windows)))]
+const ENOBUFS: i32 = 55;
+
+fn is_transient(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock || e.raw_os_error() == Some(ENOBUFS)
+}
+
+// Счётчики отправки: имитированные потери (--loss) и реальные ошибки send — раздельно
+struct SendStats {
+    retries: u32,
+    sent: usize,
+    simulated_drops: usize,
+    real_drops: usize,
+    retried: usize,
+    last_log: Instant,
+}
+
+impl SendStats {
+    fn new(retries: u32) -> Self {
+        Self {
+            retries,
+            sent: 0,
+            simulated_drops: 0,
+            real_drops: 0,
+            retried: 0,
+            last_log: Instant::now(),
+        }
+    }
+
+    fn send(&mut self, sock: &UdpSocket, frame: &[u8]) {
+        let mut backoff = Duration::from_micros(200);
+        let mut attempt = 0u32;
+        loop {
+            match sock.send(frame) {
+                Ok(_) => {
+                    self.sent += 1;
+                    break;
+                }
+                Err(e) if is_transient(&e) && attempt < self.retries => {
+                    attempt += 1;
+                    self.retried += 1;
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                Err(_) => {
+                    self.real_drops += 1;
+                    break;
+                }
+            }
+        }
+        // раз в 5 с — сводка, если были реальные потери
+        if self.real_drops > 0 && self.last_log.elapsed() >= Duration::from_secs(5) {
+            self.log("progress");
+            self.last_log = Instant::now();
+        }
+    }
+
+    fn log(&self, what: &str) {
+        eprintln!(
+            "{what}: sent={}, simulated_drops={}, real_drops={}, retried={}",
+            self.sent, self.simulated_drops, self.real_drops, self.retried
+        );
+    }
+}
+
+//==================== Проба канала ====================//
+
+const PROBE_COUNT: u64 = 8;
+const PROBE_SPACING: Duration = Duration::from_millis(20);
+const PROBE_WAIT: Duration = Duration::from_secs(1);
+const PROBE_WINDOW: f64 = 32.0; // пакетов «в полёте» на один RTT
+const PROBE_PPS_MIN: u64 = 50;
+const PROBE_PPS_MAX: u64 = 20_000;
+
+#[derive(Serialize, Deserialize)]
+struct ProbeFrame {
+    seq: u64,
+}
+
+/// Стартовый pps по измеренным RTT: окно на медианный RTT, урезанное пропорционально
+/// потерянным пробам; None — ни одного эха
+fn probe_rate(rtts: &[Duration], sent: u64) -> Option<u64> {
+    if sent == 0 {
+        return None;
+    }
+    let median = median_rtt(rtts)?.as_secs_f64().max(1e-6);
+    let delivered = rtts.len() as f64 / sent as f64;
+    let pps = PROBE_WINDOW / median * delivered * delivered;
+    Some((pps as u64).clamp(PROBE_PPS_MIN, PROBE_PPS_MAX))
+}
+
+fn median_rtt(rtts: &[Duration]) -> Option<Duration> {
+    let mut sorted = rtts.to_vec();
+    sorted.sort();
+    sorted.get(sorted.len() / 2).copied()
+}
+
+/// Посылает PROBE_COUNT проб и собирает эхо от fetch; чужие кадры игнорируются
+fn probe_link(sock: &UdpSocket) -> Vec<Duration> {
+    let mut sent_at: Vec<Option<Instant>> = Vec::with_capacity(PROBE_COUNT as usize);
+    let mut rtts = Vec::new();
+    let mut buf = [0u8; 256];
+    let _ = sock.set_read_timeout(Some(Duration::from_millis(5)));
+    let mut last_sent = Instant::now();
+    let mut seq = 0u64;
+    loop {
+        if seq < PROBE_COUNT && (seq == 0 || last_sent.elapsed() >= PROBE_SPACING) {
+            let mut frame = b"R\n".to_vec();
+            frame.extend_from_slice(
+                serde_json::to_string(&ProbeFrame { seq })
+                    .expect("probe json")
+                    .as_bytes(),
+            );
+            last_sent = Instant::now();
+            let _ = sock.send(&frame);
+            sent_at.push(Some(last_sent));
+            seq += 1;
+        }
+        if rtts.len() as u64 == PROBE_COUNT
+            || (seq == PROBE_COUNT && last_sent.elapsed() >= PROBE_WAIT)
+        {
+            break;
+        }
+        // ECONNREFUSED от прошлой пробы (никто не слушает) — тоже просто ждём дальше
+        let Ok(n) = sock.recv(&mut buf) else {
+            continue;
+        };
+        if n < 2 || &buf[..2] != b"R\n" {
+            continue;
+        }
+        if let Ok(p) = serde_json::from_slice::<ProbeFrame>(&buf[2..n]) {
+            // take(): дубликат эха не засчитываем
+            if let Some(t) = sent_at.get_mut(p.seq as usize).and_then(Option::take) {
+                rtts.push(t.elapsed());
+            }
+        }
+    }
+    let _ = sock.set_read_timeout(None);
+    rtts
+}
+
+fn packet_frame(p: &Packet) -> Vec<u8> {
+    let jp = JsonPkt {
+        ids: &p.ids,
+        body_hex: hex::encode(&p.body),
+    };
+    let mut frame = b"P\n".to_vec();
+    frame.extend_from_slice(serde_json::to_string(&jp).expect("packet json").as_bytes());
+    frame
+}
+
 fn main() {
     let args: Vec<String> = env::args().skip(1).collect();
     if args.is_empty() {
         usage();
     }
-    let dir = std::path::PathBuf::from(&args[0]);
+    let dir = PathBuf::from(&args[0]);
     let to = flag(&args, "to").unwrap_or_else(|| usage());
     let bind = flag(&args, "bind").unwrap_or_else(|| "0.0.0.0:0".to_string());
     let loss: f32 = flag(&args, "loss")
         .unwrap_or_else(|| "0".into())
         .parse()
         .unwrap_or(0.0);
-    let pps: u64 = flag(&args, "pps")
+    let mut pps: u64 = flag(&args, "pps")
         .unwrap_or_else(|| "500".into())
         .parse()
         .unwrap_or(500);
     let do_loop = flag(&args, "loop").is_some();
+    let retries: u32 = flag(&args, "retries")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(3);
+    let generate = flag(&args, "generate").is_some();
 
     let meta_path = dir.join("fountain_meta.json");
     let pkts_path = dir.join("fountain_packets.jsonl");
-    if !meta_path.exists() || !pkts_path.exists() {
+    let ct_path = flag(&args, "ct")
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| dir.join("recovered_ct.bin"));
+    if generate && (!meta_path.exists() || !ct_path.exists()) {
+        eprintln!(
+            "missing files for --generate: need {} + {}",
+            meta_path.display(),
+            ct_path.display()
+        );
+        std::process::exit(2);
+    }
+    if !generate && (!meta_path.exists() || !pkts_path.exists()) {
         eprintln!(
             "missing files in {}: need fountain_meta.json + fountain_packets.jsonl",
             dir.display()
@@ -64,6 +277,20 @@ fn main() {
 
     let sock = UdpSocket::bind(&bind).expect("bind");
     sock.connect(&to).expect("connect");
+    if flag(&args, "probe").is_some() {
+        let rtts = probe_link(&sock);
+        match probe_rate(&rtts, PROBE_COUNT) {
+            Some(rate) => {
+                eprintln!(
+                    "probe: {}/{PROBE_COUNT} echoed, median rtt={:.2} ms → pps={rate}",
+                    rtts.len(),
+                    median_rtt(&rtts).unwrap_or_default().as_secs_f64() * 1000.0
+                );
+                pps = rate;
+            }
+            None => eprintln!("probe: no echo within {PROBE_WAIT:?}, keeping pps={pps}"),
+        }
+    }
     eprintln!(
         "serving to {} (bind={}), pps={}, loss={}",
         to, bind, pps, loss
@@ -78,17 +305,84 @@ fn main() {
     thread::sleep(Duration::from_millis(50));
     let _ = sock.send(&meta_frame); // дубликат на старт
 
-    let sleep_per_pkt = if pps == 0 {
-        None
-    } else {
-        Some(Duration::from_micros(1_000_000 / pps))
-    };
+    // pps=0 — без паузы между пакетами
+    let sleep_per_pkt = 1_000_000u64.checked_div(pps).map(Duration::from_micros);
     let mut rng = StdRng::seed_from_u64(0xF0F0_0041u64); // валидное u64 вместо 0xF0UNT41N
 
+    if generate {
+        let meta: GenMeta = serde_json::from_slice(&meta_bytes).expect("meta parse");
+        let ct = std::fs::read(&ct_path).expect("read ct");
+        if ct.len() != meta.ct_len || meta.k < 2 || meta.k > ct.len() {
+            eprintln!(
+                "{}: {} bytes, meta expects ct_len={} (k={})",
+                ct_path.display(),
+                ct.len(),
+                meta.ct_len,
+                meta.k
+            );
+            std::process::exit(2);
+        }
+        let (blocks, block_len) = partition_into_blocks(&ct, meta.k);
+        if block_len != meta.block_len {
+            eprintln!(
+                "block_len mismatch: ct gives {block_len}, meta has {}",
+                meta.block_len
+            );
+            std::process::exit(2);
+        }
+        let prefix = meta
+            .systematic_count
+            .unwrap_or(if meta.systematic { meta.k } else { 0 })
+            .min(meta.k);
+        let probs: &'static [(usize, f32)] =
+            Box::leak(robust_soliton(meta.k, meta.c, meta.delta).into_boxed_slice());
+        let mut enc = FountainEncoder::new(
+            meta.k,
+            block_len,
+            FountainParams {
+                degree_probs: probs,
+                seed: meta.seed,
+            },
+        );
+
+        let mut produced = 0usize;
+        let mut stats = SendStats::new(retries);
+        let mut since_meta = 0usize;
+        // без --loop — ровно столько пакетов, сколько записал бы pack-fountain
+        while do_loop || produced < meta.packets {
+            let pkt = if produced < prefix {
+                Packet {
+                    ids: vec![produced],
+                    body: blocks[produced].clone(),
+                }
+            } else {
+                enc.next_packet(&blocks)
+            };
+            produced += 1;
+
+            if loss > 0.0 && rng.gen::<f32>() < loss {
+                stats.simulated_drops += 1;
+            } else {
+                stats.send(&sock, &packet_frame(&pkt));
+            }
+            if let Some(d) = sleep_per_pkt {
+                thread::sleep(d);
+            }
+            // в бесконечном режиме периодически повторяем мету для поздно подключившихся
+            since_meta += 1;
+            if do_loop && since_meta == 1000 {
+                let _ = sock.send(&meta_frame);
+                since_meta = 0;
+            }
+        }
+        stats.log(&format!("generated {produced} packets"));
+        return;
+    }
+
     loop {
         let f = File::open(&pkts_path).expect("open packets");
         let reader = BufReader::new(f);
-        let mut sent = 0usize;
+        let mut stats = SendStats::new(retries);
 
         for line in reader.lines() {
             let line = match line {
@@ -101,13 +395,12 @@ fn main() {
 
             // искусственная потеря
             if loss > 0.0 && rng.gen::<f32>() < loss {
-                // drop
+                stats.simulated_drops += 1;
             } else {
                 let mut frame = Vec::with_capacity(2 + line.len());
                 frame.extend_from_slice(b"P\n");
                 frame.extend_from_slice(line.as_bytes());
-                let _ = sock.send(&frame);
-                sent += 1;
+                stats.send(&sock, &frame);
             }
 
             if let Some(d) = sleep_per_pkt {
@@ -115,7 +408,7 @@ fn main() {
             }
         }
 
-        eprintln!("batch finished, sent={} (loop={})", sent, do_loop);
+        stats.log(&format!("batch finished (loop={do_loop})"));
         if !do_loop {
             break;
         }
@@ -0,0 +1,311 @@
+//! Общая fountain-математика для `s3p pack-fountain`/`unpack-fountain`, s3p-fountain-serve,
+//! s3p-fountain-fetch и асинхронного транспорта: таблица robust-soliton, структурная проверка
+//! пакета и метод Гаусса над GF(2). Одна копия на всех — чтобы отправитель и получатели
+//! не разошлись в распределении степеней или в том, какой набор пакетов считается решаемым.
+
+use s3p_core::fountain::{peel_decode, Packet};
+
+/// Robust-soliton: μ = (ρ + τ) / Z, пары (степень, вероятность) с ненулевой вероятностью.
+/// Ту же таблицу строят pack-fountain и `serve --generate` — пакеты совпадают бит в бит
+pub fn robust_soliton(k: usize, c: f64, delta: f64) -> Vec<(usize, f32)> {
+    assert!(k >= 2, "k must be >= 2");
+    let kf = k as f64;
+
+    // ρ(d)
+    let mut rho = vec![0.0f64; k + 1];
+    rho[1] = 1.0 / kf;
+    for (d, r) in rho.iter_mut().enumerate().take(k + 1).skip(2) {
+        *r = 1.0 / ((d as f64) * ((d as f64) - 1.0));
+    }
+
+    // τ(d)
+    let r = c * ((kf / delta).ln()) * kf.sqrt();
+    let s = ((kf / r).floor() as usize).max(1);
+    let mut tau = vec![0.0f64; k + 1];
+    for (d, t) in tau.iter_mut().enumerate().take(k + 1).skip(1) {
+        if d < s {
+            *t = r / ((d as f64) * kf);
+        } else if d == s {
+            *t = (r * (r / delta).ln()) / kf;
+        }
+    }
+
+    // μ(d) и нормировка
+    let mut mu = vec![0.0f64; k + 1];
+    let mut z = 0.0f64;
+    for (m, (&rv, &tv)) in mu
+        .iter_mut()
+        .zip(rho.iter().zip(tau.iter()))
+        .take(k + 1)
+        .skip(1)
+    {
+        *m = rv + tv;
+        z += *m;
+    }
+    for m in mu.iter_mut().take(k + 1).skip(1) {
+        *m /= z;
+    }
+
+    mu.iter()
+        .enumerate()
+        .take(k + 1)
+        .skip(1)
+        .map(|(d, &p)| (d, p as f32))
+        .filter(|(_, p)| *p > 0.0)
+        .collect()
+}
+
+/// Структурная проверка пакета под мету: непустые ids без повторов в 0..k и тело
+/// ровно block_len байт. Испорченный пакет отбрасывают до декодера — иначе он сорвёт
+/// peel/Гаусса для всех остальных
+pub fn packet_fits(ids: &[usize], body_len: usize, k: usize, block_len: usize) -> bool {
+    if ids.is_empty() || body_len != block_len {
+        return false;
+    }
+    let mut seen = vec![false; k];
+    for &id in ids {
+        if id >= k || seen[id] {
+            return false;
+        }
+        seen[id] = true;
+    }
+    true
+}
+
+/// Итог [`Gf2Solver::insert_row`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Row {
+    Independent,  // ранг вырос
+    Redundant,    // зависимый, тело согласовано с принятыми
+    Inconsistent, // зависимый, но тело противоречит принятым
+}
+
+/// Инкрементальный метод Гаусса над GF(2). С телами пакетов — полный декодер
+/// ([`Gf2Solver::solve`]); с пустыми телами — только ранг принятого набора
+/// (вставка — O(rank·k/64), без работы с данными)
+pub struct Gf2Solver {
+    k: usize,
+    pivots: Vec<Option<(Vec<u64>, Vec<u8>)>>, // pivots[c] — строка с младшим единичным битом c
+    rank: usize,
+}
+
+impl Gf2Solver {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            pivots: vec![None; k],
+            rank: 0,
+        }
+    }
+
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    /// Ранг = k: набор решается однозначно
+    pub fn is_full(&self) -> bool {
+        self.rank == self.k
+    }
+
+    /// Добавить пакет (ids должны быть в 0..k; повторные id взаимно уничтожаются,
+    /// как и при XOR тел). true — пакет линейно независим, ранг вырос
+    pub fn insert(&mut self, ids: &[usize], body: &[u8]) -> bool {
+        self.insert_row(ids, body) == Row::Independent
+    }
+
+    /// То же с итогом для зависимого пакета: тело сводится к нулю — [`Row::Redundant`],
+    /// нет — [`Row::Inconsistent`] (этот пакет или уже принятые испорчены). Противоречивый
+    /// пакет в решатель не попадает. С пустыми телами зависимый пакет всегда Redundant
+    pub fn insert_row(&mut self, ids: &[usize], body: &[u8]) -> Row {
+        let mut coeffs = vec![0u64; self.k.div_ceil(64)];
+        for &i in ids {
+            coeffs[i / 64] ^= 1u64 << (i % 64);
+        }
+        let mut body = body.to_vec();
+        loop {
+            let lead = coeffs
+                .iter()
+                .enumerate()
+                .find(|(_, w)| **w != 0)
+                .map(|(wi, w)| wi * 64 + w.trailing_zeros() as usize);
+            let Some(c) = lead else {
+                // линейно зависимый пакет: остаток тела — расхождение с принятыми
+                return if body.iter().all(|&b| b == 0) {
+                    Row::Redundant
+                } else {
+                    Row::Inconsistent
+                };
+            };
+            match &self.pivots[c] {
+                Some((pc, pb)) => {
+                    for (a, b) in coeffs.iter_mut().zip(pc) {
+                        *a ^= b;
+                    }
+                    for (a, b) in body.iter_mut().zip(pb) {
+                        *a ^= b;
+                    }
+                }
+                None => {
+                    self.pivots[c] = Some((coeffs, body));
+                    self.rank += 1;
+                    return Row::Independent;
+                }
+            }
+        }
+    }
+
+    /// Обратная подстановка: k исходных блоков или None, пока ранг < k
+    pub fn solve(mut self) -> Option<Vec<Vec<u8>>> {
+        if !self.is_full() {
+            return None;
+        }
+        let k = self.k;
+        let mut solved: Vec<Vec<u8>> = vec![Vec::new(); k];
+        for c in (0..k).rev() {
+            let (coeffs, mut body) = self.pivots[c].take()?;
+            for j in (c + 1)..k {
+                if coeffs[j / 64] & (1u64 << (j % 64)) != 0 {
+                    for (a, b) in body.iter_mut().zip(&solved[j]) {
+                        *a ^= b;
+                    }
+                }
+            }
+            solved[c] = body;
+        }
+        Some(solved)
+    }
+}
+
+/// Метод Гаусса по всем пакетам (не прошедшие [`packet_fits`] пропускаются).
+/// Сходится, когда peel застрял, а ранг набора уже = k
+pub fn gauss_decode(k: usize, block_len: usize, packets: &[Packet]) -> Option<Vec<Vec<u8>>> {
+    let mut solver = Gf2Solver::new(k);
+    for p in packets {
+        if packet_fits(&p.ids, p.body.len(), k, block_len) {
+            solver.insert(&p.ids, &p.body);
+            if solver.is_full() {
+                break;
+            }
+        }
+    }
+    solver.solve()
+}
+
+/// Декод, который запускают все получатели: peel (быстрый), при застревании — Гаусс
+pub fn decode(k: usize, block_len: usize, packets: &[Packet]) -> Option<Vec<Vec<u8>>> {
+    peel_decode(k, block_len, copy_packets(packets)).or_else(|| gauss_decode(k, block_len, packets))
+}
+
+/// Packet из s3p-core не Clone, а peel_decode забирает Vec целиком
+pub fn copy_packets(packets: &[Packet]) -> Vec<Packet> {
+    packets
+        .iter()
+        .map(|p| Packet {
+            ids: p.ids.clone(),
+            body: p.body.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xor_packet(blocks: &[Vec<u8>], ids: &[usize]) -> Packet {
+        let mut body = vec![0u8; blocks[0].len()];
+        for &i in ids {
+            for (a, b) in body.iter_mut().zip(&blocks[i]) {
+                *a ^= b;
+            }
+        }
+        Packet {
+            ids: ids.to_vec(),
+            body,
+        }
+    }
+
+    fn blocks(k: usize) -> Vec<Vec<u8>> {
+        (0..k).map(|i| vec![i as u8 * 17 + 1; 8]).collect()
+    }
+
+    #[test]
+    fn gauss_solves_a_full_rank_set_peel_cannot_start() {
+        // нет пакетов степени 1 — peel не сдвинется, а ранг 3
+        let b = blocks(3);
+        let pk = vec![
+            xor_packet(&b, &[0, 1]),
+            xor_packet(&b, &[1, 2]),
+            xor_packet(&b, &[0, 1, 2]),
+        ];
+        assert_eq!(gauss_decode(3, 8, &pk), Some(b.clone()));
+        assert_eq!(decode(3, 8, &pk), Some(b));
+    }
+
+    #[test]
+    fn rank_tracking_ignores_dependent_packets() {
+        let mut s = Gf2Solver::new(3);
+        assert!(s.insert(&[0, 1], &[]));
+        assert!(s.insert(&[1, 2], &[]));
+        assert!(!s.insert(&[0, 2], &[])); // сумма первых двух
+        assert_eq!(s.rank(), 2);
+        assert!(!s.is_full());
+        assert!(s.insert(&[2], &[]));
+        assert!(s.is_full());
+    }
+
+    #[test]
+    fn dependent_row_with_a_different_body_is_inconsistent() {
+        let b = blocks(3);
+        let mut s = Gf2Solver::new(3);
+        assert_eq!(
+            s.insert_row(&[0, 1], &xor_packet(&b, &[0, 1]).body),
+            Row::Independent
+        );
+        assert_eq!(
+            s.insert_row(&[1], &xor_packet(&b, &[1]).body),
+            Row::Independent
+        );
+        assert_eq!(
+            s.insert_row(&[0], &xor_packet(&b, &[0]).body),
+            Row::Redundant
+        );
+        assert_eq!(s.insert_row(&[0], &[0xAA; 8]), Row::Inconsistent);
+        assert_eq!(s.rank(), 2);
+    }
+
+    #[test]
+    fn gauss_skips_malformed_packets() {
+        let b = blocks(2);
+        let mut bad = xor_packet(&b, &[0]);
+        bad.body.pop();
+        let pk = vec![
+            bad,
+            Packet {
+                ids: vec![0, 0],
+                body: vec![0; 8],
+            },
+            xor_packet(&b, &[0, 1]),
+        ];
+        assert_eq!(gauss_decode(2, 8, &pk), None);
+        let mut pk = pk;
+        pk.push(xor_packet(&b, &[1]));
+        assert_eq!(gauss_decode(2, 8, &pk), Some(b));
+    }
+
+    #[test]
+    fn packet_fits_rejects_duplicates_and_out_of_range() {
+        assert!(packet_fits(&[0, 2], 4, 3, 4));
+        assert!(!packet_fits(&[], 4, 3, 4));
+        assert!(!packet_fits(&[1, 1], 4, 3, 4));
+        assert!(!packet_fits(&[3], 4, 3, 4));
+        assert!(!packet_fits(&[0], 5, 3, 4));
+    }
+
+    #[test]
+    fn robust_soliton_is_a_distribution() {
+        let t = robust_soliton(100, 0.1, 0.05);
+        let sum: f32 = t.iter().map(|(_, p)| p).sum();
+        assert!((sum - 1.0).abs() < 1e-4, "{sum}");
+        assert!(t.iter().all(|&(d, p)| (1..=100).contains(&d) && p > 0.0));
+    }
+}
@@ -0,0 +1,218 @@
+//! Асинхронный fountain-транспорт поверх tokio: тот же UDP-протокол, что у
+//! s3p-fountain-serve / s3p-fountain-fetch ('M\n' + JSON меты, 'P\n' + JSON пакета),
+//! но без блокировок — в одном рантайме можно держать много сессий сразу.
+//! Кодирование/декодирование остаётся синхронным (s3p-core и [`crate::fountain`]),
+//! асинхронен только I/O.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::{fs, net::UdpSocket, time};
+
+use crate::fountain::{decode, packet_fits, Gf2Solver};
+use s3p_core::fountain::{join_blocks, Packet};
+
+/// Параметры отправки
+#[derive(Debug, Clone)]
+pub struct ServeConfig {
+    /// Локальный адрес сокета (по умолчанию 0.0.0.0:0)
+    pub bind: SocketAddr,
+    /// Пакетов в секунду; 0 — без пауз
+    pub pps: u64,
+    /// Повторять мету каждые N пакетов (0 — только на старте)
+    pub meta_every: usize,
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 0)),
+            pps: 500,
+            meta_every: 1000,
+        }
+    }
+}
+
+/// Параметры приёма
+#[derive(Debug, Clone)]
+pub struct FetchConfig {
+    /// Сколько ждать декода с момента старта
+    pub timeout: Duration,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Итог успешного приёма
+#[derive(Debug, Clone)]
+pub struct FetchOutcome {
+    /// Принятых пакетов до декода
+    pub packets: usize,
+    /// Длина восстановленного шифртекста (recovered_ct.bin)
+    pub ct_len: usize,
+}
+
+#[derive(Serialize)]
+struct JsonPkt<'a> {
+    ids: &'a [usize],
+    body_hex: String,
+}
+
+#[derive(Deserialize)]
+struct WirePkt {
+    ids: Vec<usize>,
+    body_hex: String,
+}
+
+// Поля fountain_meta.json, нужные для декода
+#[derive(Deserialize)]
+struct RecvMeta {
+    k: usize,
+    block_len: usize,
+    ct_len: usize,
+}
+
+fn frame(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut f = Vec::with_capacity(2 + body.len());
+    f.extend_from_slice(&[tag, b'\n']);
+    f.extend_from_slice(body);
+    f
+}
+
+/// Отправить мету и пакеты на `to`. Возвращает число отправленных P-кадров
+pub async fn serve_stream(
+    meta_json: &[u8],
+    packets: &[Packet],
+    to: SocketAddr,
+    config: &ServeConfig,
+) -> io::Result<usize> {
+    let sock = UdpSocket::bind(config.bind).await?;
+    sock.connect(to).await?;
+
+    let meta_frame = frame(b'M', meta_json);
+    sock.send(&meta_frame).await?;
+    time::sleep(Duration::from_millis(50)).await;
+    sock.send(&meta_frame).await?; // дубликат на старт
+
+    let pause = 1_000_000u64
+        .checked_div(config.pps)
+        .map(Duration::from_micros);
+    let mut sent = 0usize;
+    let mut since_meta = 0usize;
+    for p in packets {
+        let jp = JsonPkt {
+            ids: &p.ids,
+            body_hex: hex::encode(&p.body),
+        };
+        let json = serde_json::to_vec(&jp).map_err(io::Error::other)?;
+        sock.send(&frame(b'P', &json)).await?;
+        sent += 1;
+        // периодически повторяем мету для поздно подключившихся
+        since_meta += 1;
+        if since_meta == config.meta_every {
+            sock.send(&meta_frame).await?;
+            since_meta = 0;
+        }
+        if let Some(d) = pause {
+            time::sleep(d).await;
+        }
+    }
+    Ok(sent)
+}
+
+/// Слушать `bind`, пока принятый набор не наберёт ранг k, декодировать (peel, при
+/// застревании — Гаусс) и записать в out_dir fountain_meta.json + recovered_ct.bin
+/// (как s3p-fountain-fetch). Пакеты не под мету (тело ≠ block_len, пустые/повторные ids,
+/// ids вне 0..k) отбрасываются. По таймауту — ошибка TimedOut
+pub async fn fetch_stream(
+    bind: SocketAddr,
+    out_dir: &Path,
+    config: &FetchConfig,
+) -> io::Result<FetchOutcome> {
+    let sock = UdpSocket::bind(bind).await?;
+    fs::create_dir_all(out_dir).await?;
+    let deadline = Instant::now() + config.timeout;
+
+    let mut meta: Option<RecvMeta> = None;
+    // Packet не Clone, а декодер берёт срез — копим (ids, body) и собираем заново
+    let mut received: Vec<(Vec<usize>, Vec<u8>)> = Vec::new();
+    let mut rank = Gf2Solver::new(0);
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let n = match time::timeout(left, sock.recv(&mut buf)).await {
+            Ok(r) => r?,
+            Err(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "fountain fetch: no decode after {} packets (rank {})",
+                        received.len(),
+                        rank.rank()
+                    ),
+                ))
+            }
+        };
+        if n < 2 || buf[1] != b'\n' {
+            continue;
+        }
+        match buf[0] {
+            b'M' if meta.is_none() => {
+                let Ok(m) = serde_json::from_slice::<RecvMeta>(&buf[2..n]) else {
+                    continue;
+                };
+                if m.k == 0 || m.block_len == 0 {
+                    continue;
+                }
+                fs::write(out_dir.join("fountain_meta.json"), &buf[2..n]).await?;
+                rank = Gf2Solver::new(m.k);
+                meta = Some(m);
+            }
+            b'P' => {
+                let Some(m) = meta.as_ref() else {
+                    continue;
+                };
+                let Ok(wp) = serde_json::from_slice::<WirePkt>(&buf[2..n]) else {
+                    continue;
+                };
+                let Ok(body) = hex::decode(&wp.body_hex) else {
+                    continue;
+                };
+                if !packet_fits(&wp.ids, body.len(), m.k, m.block_len) {
+                    continue;
+                }
+                rank.insert(&wp.ids, &[]);
+                received.push((wp.ids, body));
+                // до ранга k декод заведомо не сойдётся, на ранге k — сойдётся наверняка
+                if !rank.is_full() {
+                    continue;
+                }
+                let packets: Vec<Packet> = received
+                    .iter()
+                    .map(|(ids, body)| Packet {
+                        ids: ids.clone(),
+                        body: body.clone(),
+                    })
+                    .collect();
+                if let Some(blocks) = decode(m.k, m.block_len, &packets) {
+                    let ct = join_blocks(&blocks, m.ct_len);
+                    fs::write(out_dir.join("recovered_ct.bin"), &ct).await?;
+                    return Ok(FetchOutcome {
+                        packets: received.len(),
+                        ct_len: ct.len(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+}
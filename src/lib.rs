@@ -0,0 +1,7 @@
+//! s3p-cli как библиотека: общая fountain-математика бинарей и асинхронный fountain-транспорт
+//! (feature `async`). Сами команды живут в бинарях (src/main.rs, src/bin/*).
+
+pub mod fountain;
+
+#[cfg(feature = "async")]
+pub mod fountain_async;
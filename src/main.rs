@@ -1,9 +1,12 @@
 use std::{
+    collections::{BTreeMap, HashSet},
     env, fs,
     fs::OpenOptions,
-    io::{BufRead, BufReader, Read, Write},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
-    time::{SystemTime, UNIX_EPOCH},
+    sync::atomic::{AtomicU8, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use s3p_core::{
@@ -17,11 +20,75 @@ use s3p_core::{
     series::SeriesCommit,
 };
 
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::{engine::general_purpose, Engine as _};
 use ed25519_dalek::SigningKey;
-use rand::{rngs::OsRng, RngCore};
+use rand::{
+    rngs::{OsRng, StdRng},
+    Rng, RngCore, SeedableRng,
+};
+use s3p_cli::fountain::{copy_packets, decode, gauss_decode, packet_fits, robust_soliton};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+//==================== Логирование ====================//
+
+// Диагностика идёт в stderr через уровни; итоговая строка команды — println! в stdout.
+// --quiet: только ошибки; -v: debug; -vv: trace (по чанкам/шардам).
+const LOG_ERROR: u8 = 0;
+const LOG_WARN: u8 = 1;
+const LOG_INFO: u8 = 2;
+const LOG_DEBUG: u8 = 3;
+const LOG_TRACE: u8 = 4;
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LOG_INFO);
+
+fn log_enabled(level: u8) -> bool {
+    LOG_LEVEL.load(Ordering::Relaxed) >= level
+}
+
+macro_rules! log_at {
+    ($level:expr, $prefix:expr, $($arg:tt)*) => {
+        if log_enabled($level) {
+            eprintln!("{}{}", $prefix, format_args!($($arg)*));
+        }
+    };
+}
+macro_rules! log_error { ($($arg:tt)*) => { log_at!(LOG_ERROR, "", $($arg)*) }; }
+macro_rules! log_warn { ($($arg:tt)*) => { log_at!(LOG_WARN, "warning: ", $($arg)*) }; }
+macro_rules! log_info { ($($arg:tt)*) => { log_at!(LOG_INFO, "", $($arg)*) }; }
+macro_rules! log_debug { ($($arg:tt)*) => { log_at!(LOG_DEBUG, "debug: ", $($arg)*) }; }
+macro_rules! log_trace { ($($arg:tt)*) => { log_at!(LOG_TRACE, "trace: ", $($arg)*) }; }
+
+/// Вынимает глобальные -v/-vv/--verbose/-q/--quiet из аргументов (в любой позиции) и ставит уровень
+fn take_verbosity(args: &mut Vec<String>) {
+    let mut level = LOG_INFO;
+    let mut quiet = false;
+    args.retain(|a| match a.as_str() {
+        "--quiet" | "-q" => {
+            quiet = true;
+            false
+        }
+        "--verbose" => {
+            level = level.saturating_add(1);
+            false
+        }
+        s if s.len() > 1 && s.starts_with('-') && s[1..].chars().all(|c| c == 'v') => {
+            level = level.saturating_add((s.len() - 1) as u8);
+            false
+        }
+        _ => true,
+    });
+    LOG_LEVEL.store(
+        if quiet {
+            LOG_ERROR
+        } else {
+            level.min(LOG_TRACE)
+        },
+        Ordering::Relaxed,
+    );
+}
 
 //==================== Общие структуры/утилиты ====================//
 
@@ -36,6 +103,237 @@ struct Manifest {
     data_shards: usize,
     parity_shards: usize,
     file_name: String, // имя исходного файла
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    aad_hex: Option<String>, // бинарный AAD (--aad-hex); если задан — поле aad пустое
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kdf: Option<KdfParams>, // растяжение ikm (--kdf=argon2id); нет — HKDF по ikm напрямую
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shard_digests: Option<Vec<String>>, // --layout=cas: blake3-hex шардов по порядку индексов
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    salt_hex: Option<String>, // --salt-random: соль KDF; unpack берёт её отсюда
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bind_context: Option<String>, // --bind-context: AAD привязан к контексту, file_name и размерам
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    placement: Option<Vec<PlacementRange>>, // --placement: у какого провайдера лежат шарды (справочно)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    shard_trailer: bool, // --shard-trailer: в конце каждого shard-файла blake3(тела), 32 байта
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shard_leaves: Option<Vec<String>>, // leaf_hash каждого шарда (hex) — для scrub; нет в старых манифестах
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    batch: Option<Vec<BatchEntry>>, // pack-batch: файлы внутри plaintext (по порядку смещений)
+}
+
+// Файл внутри склеенного plaintext pack-batch
+#[derive(Serialize, Deserialize, Clone)]
+struct BatchEntry {
+    name: String, // относительный путь с '/', как в <input_dir>
+    offset: usize,
+    len: usize,
+}
+
+/// Сравнение за время, не зависящее от содержимого (длины сравниваются обычно).
+/// Обязательно для тегов/MAC и всего, что может стать секретным; по соглашению —
+/// для всех проверок целостности: blake3-trailer и digest шарда, merkle_root паков,
+/// chunk_root и лист чанка потока, leaf_hash в PoD, pod_root агрегата. Учётные сравнения
+/// (индексы, scid для выбора файла, pubkey подписанта) — обычным ==
+fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+const SHARD_TRAILER_LEN: usize = 32;
+
+fn with_shard_trailer(body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len() + SHARD_TRAILER_LEN);
+    out.extend_from_slice(body);
+    out.extend_from_slice(blake3::hash(body).as_bytes());
+    out
+}
+
+/// Тело шарда без trailer; None — файл короче trailer или blake3 не сходится (порча на диске)
+fn strip_shard_trailer(mut file: Vec<u8>) -> Option<Vec<u8>> {
+    let body_len = file.len().checked_sub(SHARD_TRAILER_LEN)?;
+    let trailer = file.split_off(body_len);
+    ct_eq(blake3::hash(&file).as_bytes(), &trailer).then_some(file)
+}
+
+// Диапазон индексов шардов [from..=to] у одного провайдера хранения
+#[derive(Serialize, Deserialize, Clone)]
+struct PlacementRange {
+    provider: String,
+    from: usize,
+    to: usize,
+}
+
+/// --placement=<file>: JSON-массив PlacementRange; каждый индекс 0..total — ровно в одном диапазоне
+fn load_placement(path: &Path, total: usize) -> Vec<PlacementRange> {
+    let ranges: Vec<PlacementRange> = match serde_json::from_slice(&read_all(path)) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("error: --placement {}: {e}", path.display());
+            std::process::exit(2);
+        }
+    };
+    let mut owner: Vec<Option<&str>> = vec![None; total];
+    for r in &ranges {
+        if r.provider.is_empty() || r.from > r.to || r.to >= total {
+            eprintln!(
+                "error: --placement: bad range '{}' {}..={} ({total} shards)",
+                r.provider, r.from, r.to
+            );
+            std::process::exit(2);
+        }
+        for (i, slot) in owner.iter_mut().enumerate().take(r.to + 1).skip(r.from) {
+            if let Some(prev) = slot.replace(&r.provider) {
+                eprintln!(
+                    "error: --placement: shard {i} assigned to both '{prev}' and '{}'",
+                    r.provider
+                );
+                std::process::exit(2);
+            }
+        }
+    }
+    if let Some(i) = owner.iter().position(|o| o.is_none()) {
+        eprintln!("error: --placement: shard {i} is not assigned to any provider");
+        std::process::exit(2);
+    }
+    ranges
+}
+
+/// "0–3, 7, 9–11" для списка индексов по возрастанию
+fn fmt_index_ranges(idx: &[usize]) -> String {
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < idx.len() {
+        let mut j = i;
+        while j + 1 < idx.len() && idx[j + 1] == idx[j] + 1 {
+            j += 1;
+        }
+        parts.push(if i == j {
+            idx[i].to_string()
+        } else {
+            format!("{}–{}", idx[i], idx[j])
+        });
+        i = j + 1;
+    }
+    parts.join(", ")
+}
+
+fn log_missing_by_provider(by_provider: &BTreeMap<String, Vec<usize>>) {
+    for (provider, idx) in by_provider {
+        log_warn!(
+            "provider '{provider}': {} missing shard(s): {}",
+            idx.len(),
+            fmt_index_ranges(idx)
+        );
+    }
+}
+
+impl Manifest {
+    /// Недостающие шарды, сгруппированные по провайдерам из placement (пусто без placement)
+    fn missing_by_provider(&self, missing: &[usize]) -> BTreeMap<String, Vec<usize>> {
+        let mut out: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for r in self.placement.iter().flatten() {
+            let idx: Vec<usize> = missing
+                .iter()
+                .copied()
+                .filter(|i| (r.from..=r.to).contains(i))
+                .collect();
+            if !idx.is_empty() {
+                out.entry(r.provider.clone()).or_default().extend(idx);
+            }
+        }
+        for v in out.values_mut() {
+            v.sort_unstable();
+        }
+        out
+    }
+
+    /// Шарды пака в каталоге in_dir (раскладка — по shard_digests)
+    fn shard_store(&self, in_dir: &Path) -> FsShardStore {
+        FsShardStore::new(
+            in_dir,
+            self.data_shards + self.parity_shards,
+            self.shard_digests.clone(),
+        )
+    }
+
+    /// Соль для unpack: встроенная в манифест и/или --salt-hex (если заданы обе — должны совпасть)
+    fn resolve_salt(&self, args: &[String]) -> Vec<u8> {
+        let flag = arg_flag(args, "salt-hex").map(|h| hex_decode(&h));
+        match (flag, &self.salt_hex) {
+            (Some(f), Some(m)) if f != hex_decode(m) => {
+                eprintln!("error: --salt-hex does not match salt_hex embedded in manifest.json");
+                std::process::exit(2);
+            }
+            (Some(f), _) => f,
+            (None, Some(m)) => hex_decode(m),
+            (None, None) => hex_decode(&require_flag(args, "salt-hex")),
+        }
+    }
+
+    /// Тело шарда i для RS/Merkle/PoD: trailer снят и проверен, cas-digest сверен.
+    /// None — файла нет или он испорчен (такой шард считается отсутствующим)
+    fn read_shard(&self, store: &dyn ShardStore, i: usize) -> Option<Vec<u8>> {
+        let mut bytes = store.get(i)?;
+        if self.shard_trailer {
+            bytes = match strip_shard_trailer(bytes) {
+                Some(body) => body,
+                None => {
+                    log_warn!("{} fails its trailer check, ignored", store.describe(i));
+                    return None;
+                }
+            };
+        }
+        // в cas-пуле имя файла — его хэш: испорченный шард считаем отсутствующим
+        if let Some(d) = &self.shard_digests {
+            if !ct_eq(blake3::hash(&bytes).to_hex().as_bytes(), d[i].as_bytes()) {
+                log_warn!("{} does not match its digest, ignored", store.describe(i));
+                return None;
+            }
+        }
+        Some(bytes)
+    }
+
+    /// Точные байты AAD, использованные при pack
+    fn aad_bytes(&self) -> Vec<u8> {
+        let base = match &self.aad_hex {
+            Some(h) => hex_decode(h),
+            None => self.aad.as_bytes().to_vec(),
+        };
+        match &self.bind_context {
+            Some(ctx) => bound_aad(
+                &base,
+                ctx,
+                &self.file_name,
+                self.commit.size_bytes,
+                self.data_shards,
+                self.parity_shards,
+            ),
+            None => base,
+        }
+    }
+}
+
+/// AAD с привязкой к контексту (--bind-context): всё, что известно до seal.
+/// scid от AAD не зависит напрямую, но покрывает его через ciphertext → Merkle root.
+/// Кодирование: "s3p-bind/v1" | len‖aad | len‖context | len‖file_name | size | data | parity (u64 LE)
+fn bound_aad(
+    base: &[u8],
+    context: &str,
+    file_name: &str,
+    size_bytes: usize,
+    data_shards: usize,
+    parity_shards: usize,
+) -> Vec<u8> {
+    let mut out = b"s3p-bind/v1".to_vec();
+    for part in [base, context.as_bytes(), file_name.as_bytes()] {
+        out.extend_from_slice(&(part.len() as u64).to_le_bytes());
+        out.extend_from_slice(part);
+    }
+    for n in [size_bytes, data_shards, parity_shards] {
+        out.extend_from_slice(&(n as u64).to_le_bytes());
+    }
+    out
 }
 
 #[derive(Serialize, Deserialize)]
@@ -52,6 +350,31 @@ struct StreamManifest {
     ct_len_per_chunk: usize, // = chunk_size + 16 (AEAD tag)
     chunks: usize,           // количество чанков
     nonce_base_hex: String,  // 24 байта — база для детерминированных nonce
+    #[serde(default)]
+    shard_len: Option<usize>, // фактическая длина шарда в полосе (как отдал rs_encode); нет в старых манифестах
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chunk_root_hex: Option<String>, // корень дерева по шифртекстам чанков (chunk-proof/chunk-verify)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shard_leaves: Option<Vec<String>>, // leaf_hash каждого shard-файла (hex) — для scrub
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kdf: Option<KdfParams>, // растяжение ikm (--kdf=argon2id); нет — HKDF по ikm напрямую
+}
+
+impl StreamManifest {
+    /// Длина шарда одной полосы: записанная при упаковке, либо legacy-расчёт для старых манифестов.
+    fn stripe_shard_len(&self) -> usize {
+        self.shard_len
+            .unwrap_or_else(|| self.ct_len_per_chunk.div_ceil(self.data_shards))
+    }
+
+    /// Листья commit.merkle_root сверх шардов: chunk_root, если он есть (см. chunk_root_commit_leaf).
+    /// Битый hex даёт лист, который с коммитом не сойдётся
+    fn commit_extra_leaves(&self) -> Vec<[u8; 32]> {
+        self.chunk_root_hex
+            .iter()
+            .map(|h| chunk_root_commit_leaf(&hex::decode(h.trim()).unwrap_or_default()))
+            .collect()
+    }
 }
 
 fn hex_decode(s: &str) -> Vec<u8> {
@@ -67,6 +390,39 @@ fn read_all(p: &Path) -> Vec<u8> {
     fs::read(p).expect("read file")
 }
 
+/// Читает plaintext из stdin: ровно `size` байт, если длина известна заранее, иначе до EOF.
+fn read_stdin(size: Option<usize>) -> Vec<u8> {
+    let stdin = std::io::stdin();
+    let mut lock = stdin.lock();
+    let mut buf = Vec::with_capacity(size.unwrap_or(0));
+    match size {
+        Some(n) => {
+            lock.by_ref()
+                .take(n as u64)
+                .read_to_end(&mut buf)
+                .expect("read stdin");
+            if buf.len() != n {
+                eprintln!(
+                    "stdin ended after {} bytes, expected --size={}",
+                    buf.len(),
+                    n
+                );
+                std::process::exit(2);
+            }
+            // лишние байты после --size — почти наверняка ошибка вызывающего
+            let mut probe = [0u8; 1];
+            if lock.read(&mut probe).expect("read stdin") != 0 {
+                eprintln!("stdin has more than --size={} bytes", n);
+                std::process::exit(2);
+            }
+        }
+        None => {
+            lock.read_to_end(&mut buf).expect("read stdin");
+        }
+    }
+    buf
+}
+
 fn write_all(p: &Path, bytes: &[u8]) {
     if let Some(parent) = p.parent() {
         fs::create_dir_all(parent).ok();
@@ -75,35 +431,314 @@ fn write_all(p: &Path, bytes: &[u8]) {
     f.write_all(bytes).expect("write file");
 }
 
+/// ct_len из манифеста против реально восстановленных байт: срез по нему иначе паникует
+fn check_ct_len(manifest: &str, ct_len: usize, available: usize) {
+    if ct_len > available {
+        eprintln!(
+            "error: {manifest} ct_len={ct_len} exceeds reconstructed data ({available} bytes)"
+        );
+        std::process::exit(2);
+    }
+}
+
+/// scid — идентификатор контента: поле манифеста обязано совпадать с пересчётом из commit
+fn check_scid(manifest: &str, commit: &SeriesCommit, scid: &str) {
+    let computed = commit.scid();
+    if computed != scid {
+        eprintln!("error: {manifest} scid={scid} does not match commit (recomputed {computed})");
+        std::process::exit(2);
+    }
+}
+
+/// Каталог вывода pack*/rekey: чужой manifest или shard_*.bin в нём переживут новую запись
+/// (старшие индексы никто не перезапишет) и сломают unpack. Без --force — отказ, с --force —
+/// удаляем артефакты прежнего пака; общий cas-пул shards/ не трогаем
+fn prepare_out_dir(out_dir: &Path, args: &[String]) {
+    let Ok(entries) = fs::read_dir(out_dir) else {
+        return;
+    };
+    let mut stale: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            let name = p.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            matches!(
+                name,
+                "manifest.json" | "manifest_stream.json" | "stream_progress.json"
+            ) || (name.starts_with("shard_") && name.ends_with(".bin"))
+                || (name.starts_with("pod_") && name.ends_with(".json"))
+        })
+        .collect();
+    if stale.is_empty() {
+        return;
+    }
+    stale.sort();
+    if !has_flag(args, "force") {
+        eprintln!(
+            "error: {} already holds a pack ({} file(s), e.g. {}); use --force to replace it",
+            out_dir.display(),
+            stale.len(),
+            stale[0].display()
+        );
+        std::process::exit(2);
+    }
+    for p in &stale {
+        fs::remove_file(p).unwrap_or_else(|e| {
+            eprintln!("error: --force: {}: {e}", p.display());
+            std::process::exit(2);
+        });
+    }
+    log_info!(
+        "--force: removed {} file(s) of the previous pack in {}",
+        stale.len(),
+        out_dir.display()
+    );
+}
+
+/// Запись через <path>.tmp + rename: при сбое посреди записи старый файл остаётся целым.
+/// Для манифестов/meta; шард-файлы пишутся напрямую.
+fn write_atomic(p: &Path, bytes: &[u8]) {
+    if let Some(parent) = p.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    let mut tmp_name = p.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp = PathBuf::from(tmp_name);
+    let mut f = fs::File::create(&tmp).expect("create tmp file");
+    f.write_all(bytes).expect("write tmp file");
+    f.sync_all().expect("sync tmp file");
+    drop(f);
+    fs::rename(&tmp, p).expect("rename tmp file");
+}
+
 fn usage() -> ! {
     eprintln!(
 "Usage:
-  s3p pack   <input_file> <out_dir> --data=<N> --parity=<M> --ikm-hex=<HEX> --salt-hex=<HEX> [--aad=<str>]
-  s3p unpack <in_dir> <output_file> --ikm-hex=<HEX> --salt-hex=<HEX>
-
-  s3p pack-fountain   <input_file> <out_dir> --ikm-hex=<HEX> --salt-hex=<HEX> [--aad=<str>] --k=<K> [--packets=<N> | --overhead=<x.y>] [--seed=<u64>] [--c=0.1] [--delta=0.05]
-  s3p unpack-fountain <in_dir> <output_file> --ikm-hex=<HEX> --salt-hex=<HEX>
-
-  s3p pack-stream      <input_file> <out_dir> --data=<N> --parity=<M> --ikm-hex=<HEX> --salt-hex=<HEX> --chunk=<bytes> [--aad=<str>] [--nonce-base-hex=<48hex>]
+  s3p pack   <input_file> <out_dir> --data=<N> --parity=<M> --ikm-hex=<HEX> (--salt-hex=<HEX> | --salt-random) [--aad=<str> | --aad-hex=<HEX>] [--bind-context=<str>] [--layout=indexed|cas] [--placement=<json>] [--shard-trailer] [--force] [--dry-run]
+  s3p pack   - <out_dir> [--size=<bytes>] [--name=<file_name>] --data=<N> --parity=<M> --ikm-hex=<HEX> --salt-hex=<HEX> [--aad=<str> | --aad-hex=<HEX>]
+  s3p unpack <in_dir> <output_file> (--ikm-hex=<HEX> [--salt-hex=<HEX>] | --key-file=<json>)
+  s3p pack-batch   <input_dir> <out_dir> --data=<N> --parity=<M> --ikm-hex=<HEX> (--salt-hex=<HEX> | --salt-random) [опции pack]
+  s3p unpack-batch <in_dir> <out_dir> --ikm-hex=<HEX> [--salt-hex=<HEX>] [--file=<name>]
+  s3p rekey  <in_dir> <out_dir> (--old-ikm-hex=<HEX> | --old-password=<str>) [--old-salt-hex=<HEX>]
+             (--new-ikm-hex=<HEX> | --new-password=<str>) --new-salt-hex=<HEX> [--kdf=hkdf|argon2id] [--kdf-params=...]
+             [--data=<N> --parity=<M>] [--aad=<str>] [--name=<file_name>] [--placement=<json>] [--force]
+  s3p rebuild-manifest <in_dir> --data=<N> --parity=<M> --size=<bytes> --nonce-hex=<48hex> [--ct-len=<bytes>]
+             [--aad=<str> | --aad-hex=<HEX>] [--file-name=<name>] [--salt-hex=<HEX>] [--bind-context=<str>] [--shard-trailer]
+             [--kdf=hkdf|argon2id] [--kdf-params=m=64MiB,t=3,p=1]
+
+  s3p pack-fountain   <input_file> <out_dir> --ikm-hex=<HEX> --salt-hex=<HEX> [--aad=<str>] --k=<K> [--packets=<N> | --overhead=<x.y>] [--seed=<u64>] [--c=0.1] [--delta=0.05] [--systematic=true|false | --systematic-count=<0..k>] [--max-packet-bytes=1400 [--strict-mtu]] [--stats] [--ct-only] [--self-test | --self-test-loss=<0..1>]
+  s3p unpack-fountain <in_dir> <output_file> --ikm-hex=<HEX> --salt-hex=<HEX> [--max-packets=<N>] [--from-packets]
+  s3p fountain-calibrate --k=<K> [--c=0.1] [--delta=0.05] [--systematic=true|false] [--loss=0.0] [--trials=100] [--seed=<u64>] [--json]
+
+  s3p pack-stream      <input_file> <out_dir> --data=<N> --parity=<M> --ikm-hex=<HEX> --salt-hex=<HEX> --chunk=<bytes>|auto [--aad=<str>] [--nonce-base-hex=<48hex>] [--resume] [--write-rate=<bytes/s>] [--force] [--dry-run]
   s3p unpack-stream    <in_dir> <output_file> --ikm-hex=<HEX> --salt-hex=<HEX>
-  s3p verify-pack      <in_dir>
-  s3p verify-pack-stream <in_dir>
-
-  s3p keygen         --out-dir=<dir>
-  s3p pod-sign       <in_dir> --sk-hex=<64-hex-secret>
-  s3p pod-verify     <in_dir>
-  s3p pod-aggregate  <in_dir> [--out=<file>]
+  s3p extract-stream   <in_dir> <output_file> --offset=<bytes> --len=<bytes> --ikm-hex=<HEX> --salt-hex=<HEX>
+  s3p verify-pack      <in_dir> [--check-recoverable] [--json]
+  s3p verify-pack-stream <in_dir> [--json]
+  s3p scrub            <in_dir> [--json]
+  s3p chunk-proof      <in_dir> --index=<i> [--out=<file>]
+  s3p chunk-verify     <in_dir> --proof=<file>
+  s3p merkle-proof     <in_dir> --index=<i> [--out=<file>]
+  s3p merkle-verify    <in_dir> --proof=<file> --index=<i>
+
+  s3p keygen         --out-dir=<dir> [--seed-hex=<64-hex>] [--format=hex|pem|jwk] [--encoding=hex|base64]
+  s3p pod-sign       <in_dir> --sk-hex=<64-hex-secret> | --sk-dir=<dir> [--append] [--data-only] [--deterministic]
+  s3p pod-verify     <in_dir>... | --dir-list=<file> [--quorum=<N>] [--max-age-ms=<N> [--max-skew-ms=60000]] [--json]
+  s3p pod-aggregate  <in_dir> [--out=<file>] [--quorum=<N>] [--min-coverage=<fraction>] [--deterministic] [--json]
+  s3p pod-aggregate-verify <in_dir> [--aggregate=<file>] [--json]
 
 Notes:
   - RS-профиль: в <out_dir> будут shard_###.bin и manifest.json; после pod-sign — pod_###.json
+    и pod_###.proof.json — включение шарда в commit.merkle_root (все листья коммита: внутренние узлы
+    дерева s3p-core не публичны, корень пересчитывается его merkle_root); pod-verify проверяет и подписи,
+    и что шард на диске стоит под своим индексом в коммите (иначе bad: shard not in commit); без
+    pod_###.proof.json (старые PoD) — по shard_leaves манифеста или листьям шардов с диска
+  - merkle-proof — то же доказательство для шарда i пака (JSON в stdout или --out), merkle-verify
+    сверяет с ним шард i каталога; --index вне 0..data+parity или шард не из коммита — код 2
+  - --data + --parity ≤ 256 (RS над GF(2^8) в s3p-core 0.1); больше — код 2 до любой записи.
+    Бэкенда GF(2^16) для широких раскладок пока нет: он появится вместе с выбором поля в s3p-core
+  - pack --layout=cas: шарды пишутся в <out_dir>/shards/<blake3-hex>.bin (одинаковые — один файл),
+    manifest.json хранит shard_digests по порядку; unpack/verify-pack/pod-* находят шарды по хэшу
+  - pack '-' читает plaintext из stdin: --size — ожидаемая длина (короче/длиннее → ошибка),
+    без --size stdin буферизуется целиком; file_name в манифесте берётся из --name (иначе stdin.bin)
+  - pack --placement=<file> — JSON [{{\"provider\":\"A\",\"from\":0,\"to\":3}}, ...]: каждый индекс шарда ровно
+    у одного провайдера; пишется в manifest.json (справочно), unpack/verify-pack группируют
+    недостающие шарды по провайдерам (в --json — missing_by_provider)
+  - pack --shard-trailer — к каждому shard-файлу дописывается blake3 его тела (32 байта), в манифесте
+    shard_trailer=true; unpack/verify-pack/pod-* проверяют и снимают trailer, испорченный шард — как отсутствующий
+  - pack-batch — все файлы <input_dir> (рекурсивно) склеиваются в один plaintext и пакуются одним pack;
+    manifest.json хранит batch: имя/смещение/длину каждого (пустые файлы тоже). Имена, совпадающие
+    без учёта регистра, — ошибка. unpack-batch извлекает все файлы или один (--file), не перезаписывая
+  - rekey — ротация ключа RS-пака без исходного файла: шарды → open старым ключом → seal новым
+    (свежий nonce, новый scid) в отдельный <out_dir>; data/parity, aad, file_name, раскладка, trailer
+    и bind-context переносятся; --kdf*/--new-password (или --password) относятся к новому ключу, KDF
+    старого берётся из манифеста (--old-password — только для пака с argon2id). Пароль и hex-ikm
+    одного ключа вместе — код 2 (как и --password с --ikm-hex у pack/unpack). Старые шарды удалите сами
   - Stream RS: manifest_stream.json + те же shard_###.bin (заполняются «полосами» по чанкам)
+  - pack-stream пишет stream_progress.json по ходу работы; --resume продолжает прерванную упаковку
+    с последнего зафиксированного чанка (параметры и nonce base берутся/сверяются из прогресса;
+    другие соль, ключ или вход — по соли, ключевому коммитменту и sha256 первого MiB — код 2)
+  - pack-stream пишет chunk_root_hex — корень sha256-дерева по шифртекстам чанков; он же — последний
+    лист commit.merkle_root (после шардов), так что scid связывает и его; chunk-proof выдаёт
+    путь включения чанка i, chunk-verify проверяет его, читая из shard-файлов только полосу i (ключ не нужен)
+    и сверяя chunk_root с коммитом по shard_leaves манифеста
   - Fountain-профиль: fountain_meta.json + fountain_packets.jsonl
+  - --systematic=false — без префикса из k исходных блоков: все пакеты из robust-soliton энкодера;
+    декодер тот же, но нужен больший запас пакетов (берите --overhead от ~1.5 и выше)
+  - --systematic-count=N — в префиксе только первые N исходных блоков (0..=k), дальше энкодер;
+    пишется в fountain_meta.json, serve --generate повторяет ту же последовательность
+  - --max-packet-bytes — предел UDP-кадра для s3p-fountain-serve ('P\\n' + JSON, тело в hex);
+    при превышении — предупреждение с подсказкой по --k, с --strict-mtu — ошибка (код 2)
+  - pack-fountain --ct-only — без fountain_packets.jsonl: meta + recovered_ct.bin, пакеты порождает
+    на лету 's3p-fountain-serve --generate' (unpack-fountain тоже читает recovered_ct.bin напрямую)
+  - pack-fountain печатает в stderr сводку по степеням пакетов; --stats — полная гистограмма JSON в stdout и в fountain_meta.json
+  - pack/pack-stream --dry-run — только длина входа: число и размер шардов, длина шифртекста,
+    оценка места на диске и поля будущего SeriesCommit; ключ не нужен, файлы не создаются
+  - unpack-fountain читает jsonl построчно и останавливается, как только peel-декодер сошёлся;
+    в конце (EOF или --max-packets), если peel застрял, — метод Гаусса, как в --self-test;
+    --max-packets — не читать больше N уникальных пакетов (не сошлось → код 2)
+  - Пустой вход (0 байт) допустим везде: pack/pack-fountain шифруют пустой plaintext (шифртекст —
+    только 16-байтный тег), pack-stream пишет один нулевой чанк; unpack* восстанавливают пустой файл
+  - pack-fountain --self-test — до записи файлов декодирует пакеты в памяти (peel, затем Гаусс) и
+    сверяет с шифртекстом, иначе код 2; --self-test-loss=p — то же и ещё раз по случайному
+    подмножеству без доли p пакетов (seed от --seed)
+  - unpack-fountain пропускает (с подсчётом) строки jsonl с битым JSON/hex/base64 и пакеты с телом
+    не block_len байт, пустыми/повторными ids или id вне 0..k — как и s3p-fountain-fetch;
+    остальные декодируются как обычно
+  - unpack-fountain берёт recovered_ct.bin, только если его длина ровно ct_len из fountain_meta.json
+    (иначе код 2); --from-packets — игнорировать recovered_ct.bin и декодировать fountain_packets.jsonl
+  - fountain-calibrate — без реальных данных: T раз кодирует случайные блоки тем же энкодером и
+    robust_soliton, теряет пакеты с вероятностью --loss и ищет минимум отправленных пакетов для
+    peel-декода; печатает медиану/p95 (пакеты и overhead = пакеты/k) — p95 годится как --overhead
+  - rebuild-manifest — если manifest.json потерян, а shard_###.bin целы (indexed-раскладка): шифртекст
+    восстанавливается RS, перекодируется и сверяется с шардами, merkle_root/scid считаются заново;
+    nonce, aad, размер, имя (и соль для --salt-random) — из ваших записей; --ct-len по умолчанию size+16;
+    пак с --kdf=argon2id — те же --kdf/--kdf-params, что при pack (иначе ключ выйдет другим);
+    cas-пул (--layout=cas) не восстанавливается: порядок шардов был только в манифесте (код 2)
+  - pack/pack-batch/pack-stream/rekey отказываются (код 2) писать в каталог, где уже есть manifest*.json,
+    stream_progress.json, shard_*.bin или pod_*.json; --force — сначала удалить их (cas-пул shards/
+    остаётся); pack-stream --resume продолжает в своём каталоге без проверки
+  - pack-stream --chunk=auto — размер чанка по размеру файла: ~1000 чанков, кратно 4 KiB, в пределах
+    [64 KiB, 8 MiB]; выбранный размер, как обычно, записывается в manifest_stream.json (chunk_size)
+  - pack-stream --write-rate=N — не больше N байт/с суммарно во все shard-файлы (ведро токенов
+    на каждую полосу); без флага запись не ограничена
+  - extract-stream — только байты [offset, offset+len): читает (seek) и расшифровывает лишь покрывающие
+    чанки; диапазон за концом файла обрезается до size_bytes
+  - unpack/unpack-stream/verify-pack/verify-pack-stream: shard_NNN.bin с индексом ≥ data+parity из
+    манифеста — код 2 (каталог занят другим паком); прочие посторонние shard_*.bin — предупреждение
+  - verify-pack-stream сверяет и размер каждого shard-файла с chunks × shard_len (ожидаемый/фактический
+    по индексу, mismatch shard_size)
+  - unpack/unpack-batch/unpack-stream/rekey пересчитывают scid из commit манифеста; расхождение
+    с полем scid — код 2 (раньше это ловил только verify-pack)
+  - pack-fountain --k: от 2 до длины шифртекста (размер входа + 16 байт тега); иначе код 2
   - ikm-hex/salt-hex — ключевой материал в hex (ikm обычно 32 байта = 64 hex-символа)
-  - sk-hex — 32-байтный секретный ключ Ed25519 в hex (ровно 64 hex-символа)"
+  - pack --salt-random — 16 байт соли из OsRng пишутся в manifest.json (salt_hex); unpack тогда
+    не требует --salt-hex, а если он задан — сверяет с манифестом (расхождение → код 2)
+  - pack/pack-stream/pack-fountain: [--kdf=hkdf|argon2id] [--kdf-params=m=64MiB,t=3,p=1] [--password=<str>]
+    argon2id растягивает ikm (или пароль вместо --ikm-hex) перед HKDF; salt ≥ 8 байт; параметры KDF
+    пишутся в манифест, unpack* берёт их оттуда (пароль передаётся тем же --password). По умолчанию — hkdf
+  - --bind-context=<str> — AAD дополняется контекстом, file_name, size_bytes, data и parity
+    (всё известно до seal); unpack собирает тот же AAD из манифеста, поэтому шарды, перенесённые
+    под другой манифест/имя/контекст, не расшифруются. scid покрывает AAD косвенно — через ciphertext
+  - pack/unpack/pack-batch/unpack-batch --key-file=<json> — профиль {{\"ikm_hex\", \"salt_hex\", \"aad\", \"kdf\",
+    \"kdf_params\"}} (все поля необязательны, лишние → код 2; kdf_params — строка как у --kdf-params);
+    явные флаги перекрывают файл (--password — ikm_hex, --salt-random — salt_hex, --aad-hex — aad)
+  - aad-hex — произвольные байты AAD (pack); сохраняются в manifest.json как aad_hex, unpack берёт их оттуда
+  - sk-hex — 32-байтный секретный ключ Ed25519: 64 hex-символа или base64 (определяется автоматически)
+  - sk-dir — каталог с секретами комитета (*.hex, *.b64); pod-sign пишет pod_###_<signer>.json на каждого,
+    pod-verify/pod-aggregate считают различных валидных подписантов шарда против --quorum (по умолчанию 1)
+  - pod-verify с несколькими каталогами или --dir-list=<file> (по строке на каталог) проверяет все,
+    даже если какой-то упал: строка на каталог + общий итог; код 2, если не прошёл хоть один
+  - pod-verify --max-age-ms — PoD старше now−N или из будущего дальше now+skew считаются stale
+    (отдельный счётчик в сводке, код выхода 2); без флага возраст не проверяется
+  - keygen --seed-hex — детерминированный ключ: 32 байта seed используются как секрет Ed25519 напрямую
+  - keygen --format=pem — дополнительно pk.pem (SubjectPublicKeyInfo) и sk.pem (PKCS#8);
+    --format=jwk — pk.jwk/sk.jwk (OKP, crv=Ed25519); sk.hex/pk.hex пишутся при любом формате
+  - keygen --encoding=base64 — вместо sk.hex/pk.hex пишет sk.b64/pk.b64; читатели ключей принимают оба
+  - scrub — проверяет каждый shard-файл отдельно (leaf_hash из shard_leaves манифеста, trailer,
+    cas-digest) и называет все испорченные/пропавшие; RS и stream; 0 — чисто, 2 — есть порча.
+    Списки манифеста должны быть длиной data+parity, а shard_leaves — давать commit.merkle_root
+    (и scid); иначе код 2 без вердикта по шардам
+  - verify-pack --check-recoverable — не требует всех шардов: считает имеющиеся и делает пробный
+    rs_reconstruct (результат перекодируется и сверяется с merkle_root); код 0 — восстановимо, 2 — нет
+  - Глобально: -v (debug), -vv (trace, по чанкам), --quiet/-q — только ошибки; итоговая строка — в stdout
+  - pod-aggregate --min-coverage=0.67 (или 2/3) — доля шардов с PoD по кворуму (ok/total) ниже порога →
+    агрегат не пишется, код 2; coverage и порог сохраняются в агрегате, pod-aggregate-verify сверяет их
+  - pod-sign --append — шарды, у которых уже есть валидный PoD того же подписанта с тем же
+    leaf_hash, пропускаются (файл и ts не меняются); подписываются только новые/изменившиеся
+  - pod-sign --data-only — PoD только для индексов < data (паритет восстановим), область пишется
+    в pod_scope.json; pod-verify/pod-aggregate не считают паритет без PoD missing (unsigned=),
+    coverage агрегата — ok среди индексов < data, делённое на data; data — erasure_data из commit
+    (связан scid), так что pod_scope.json не сужает знаменатель. pod-sign без --data-only его удаляет
+  - pod-sign/pod-aggregate --deterministic — ts_unix_ms=0 (или SOURCE_DATE_EPOCH·1000, если переменная
+    задана — она действует и без флага): одинаковые входы → побайтно одинаковые PoD и агрегат.
+    С такими метками pod-verify --max-age-ms, разумеется, считает PoD устаревшими
+  - pod-aggregate-verify пересчитывает pod_root по PoD шардов из included_indexes (кворум — из агрегата);
+    расхождение корня, scid или непроходящий PoD → код 2
+  - --json — verify-pack*/pod-verify/pod-aggregate* печатают в stdout один JSON-объект (коды выхода те же)"
     );
     std::process::exit(1)
 }
 
+// s3p-core 0.1 кодирует RS над GF(2^8): data + parity не может превышать размер поля.
+// GF(2^16) (до 65536 шардов) — за s3p-core: erasure там без выбора поля, а поле должно попасть
+// в манифест, чтобы unpack выбрал тот же бэкенд. Пока CLI только отказывает заранее
+const MAX_RS_SHARDS: usize = 256;
+
+fn check_rs_shard_count(data_shards: usize, parity_shards: usize) {
+    let total = data_shards + parity_shards;
+    if total > MAX_RS_SHARDS {
+        eprintln!(
+            "error: --data + --parity = {total} exceeds {MAX_RS_SHARDS} shards supported by the GF(2^8) Reed–Solomon backend (GF(2^16) is not available in s3p-core 0.1)"
+        );
+        std::process::exit(2);
+    }
+}
+
+//==================== Dry-run: раскладка без записи ====================//
+
+fn input_len(input: &Path) -> usize {
+    match fs::metadata(input) {
+        Ok(m) => m.len() as usize,
+        Err(e) => {
+            eprintln!("error: {}: {e}", input.display());
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Раскладка pack (один «чанк» на весь файл) или pack-stream — только из длины входа.
+/// commit_chunk — поле chunk_size будущего SeriesCommit (pack: ct_len, pack-stream: --chunk).
+/// Шард полосы = ceil(ct_chunk / data), как у rs_encode; merkle_root/scid без шифрования не получить.
+fn dry_run_report(
+    size: usize,
+    chunks: usize,
+    ct_chunk: usize,
+    commit_chunk: usize,
+    data_shards: usize,
+    parity_shards: usize,
+) {
+    if data_shards == 0 || parity_shards == 0 {
+        eprintln!("error: --data and --parity must be > 0");
+        std::process::exit(2);
+    }
+    let stripe_shard = ct_chunk.div_ceil(data_shards).max(1);
+    let total_shards = data_shards + parity_shards;
+    let shard_file = chunks * stripe_shard;
+    let total = total_shards * shard_file;
+    println!("dry-run (nothing written):");
+    println!("  input:        {size} B");
+    println!("  chunks:       {chunks} x {ct_chunk} B ciphertext");
+    println!("  shards:       {total_shards} ({data_shards} data + {parity_shards} parity)");
+    println!("  shard size:   {shard_file} B ({stripe_shard} B per stripe)");
+    println!(
+        "  on disk:      ~{total} B (x{:.2} of input, manifest not counted)",
+        total as f64 / size.max(1) as f64
+    );
+    println!(
+        "  scid inputs:  size_bytes={size}, chunk_size={commit_chunk}, erasure={data_shards}+{parity_shards}, aead=XChaCha20-Poly1305, merkle_root=<after encryption>"
+    );
+}
+
 fn arg_flag(args: &[String], name: &str) -> Option<String> {
     for a in args {
         if let Some(rest) = a.strip_prefix(&format!("--{}=", name)) {
@@ -113,6 +748,12 @@ fn arg_flag(args: &[String], name: &str) -> Option<String> {
     None
 }
 
+/// Булев флаг без значения: `--name`
+fn has_flag(args: &[String], name: &str) -> bool {
+    let flag = format!("--{name}");
+    args.contains(&flag)
+}
+
 #[inline]
 fn require_flag(args: &[String], name: &str) -> String {
     if let Some(v) = arg_flag(args, name) {
@@ -128,482 +769,2720 @@ fn arg_flag_default<T: std::str::FromStr>(args: &[String], name: &str, default:
         .unwrap_or(default)
 }
 
-//==================== RS-профиль: pack/unpack ====================//
+//==================== KDF (растяжение ikm/пароля) ====================//
 
-fn pack_cmd(args: &[String]) {
-    if args.len() < 3 {
-        usage();
-    }
-    let input = PathBuf::from(&args[0]);
-    let out_dir = PathBuf::from(&args[1]);
+// Параметры KDF, записываемые в манифест; нет поля — ikm идёт в KeySchedule::derive как есть (HKDF)
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct KdfParams {
+    alg: String, // "argon2id"
+    m_kib: u32,  // память, KiB
+    t: u32,      // итерации
+    p: u32,      // параллелизм
+}
 
-    let data_shards: usize = require_flag(args, "data")
-        .parse()
-        .expect("invalid --data (number)");
-    let parity_shards: usize = require_flag(args, "parity")
-        .parse()
-        .expect("invalid --parity (number)");
-    let ikm_hex = require_flag(args, "ikm-hex");
-    let salt_hex = require_flag(args, "salt-hex");
-    let aad = arg_flag(args, "aad").unwrap_or_else(|| "s3p-cli".to_string());
+/// `--kdf=hkdf|argon2id` + `--kdf-params=m=64MiB,t=3,p=1` (для упаковки)
+fn parse_kdf_args(args: &[String]) -> Option<KdfParams> {
+    parse_kdf_args_for(args, "password")
+}
 
-    if data_shards == 0 {
-        eprintln!("error: --data must be > 0\n");
-        usage();
-    }
-    if parity_shards == 0 {
-        eprintln!("error: --parity must be > 0\n");
-        usage();
+/// То же для ключа, пароль которого передан флагом `--<password_flag>` (rekey: --new-password)
+fn parse_kdf_args_for(args: &[String], password_flag: &str) -> Option<KdfParams> {
+    let password = arg_flag(args, password_flag).is_some();
+    let alg = arg_flag(args, "kdf").unwrap_or_else(|| {
+        // пароль без растяжения не принимаем
+        if password {
+            "argon2id".to_string()
+        } else {
+            "hkdf".to_string()
+        }
+    });
+    match alg.as_str() {
+        "hkdf" => {
+            if password {
+                eprintln!("error: --{password_flag} requires --kdf=argon2id");
+                std::process::exit(2);
+            }
+            if arg_flag(args, "kdf-params").is_some() {
+                eprintln!("error: --kdf-params only applies to --kdf=argon2id");
+                std::process::exit(2);
+            }
+            None
+        }
+        "argon2id" => {
+            let mut kp = KdfParams {
+                alg,
+                m_kib: 64 * 1024,
+                t: 3,
+                p: 1,
+            };
+            if let Some(spec) = arg_flag(args, "kdf-params") {
+                for part in spec.split(',').filter(|s| !s.is_empty()) {
+                    let (k, v) = part.split_once('=').unwrap_or((part, ""));
+                    let parsed = match k {
+                        "m" => parse_kib(v).map(|m| kp.m_kib = m),
+                        "t" => v.parse().ok().map(|t| kp.t = t),
+                        "p" => v.parse().ok().map(|p| kp.p = p),
+                        _ => None,
+                    };
+                    if parsed.is_none() {
+                        eprintln!("error: bad --kdf-params entry '{part}' (expected m=<size>,t=<n>,p=<n>)");
+                        std::process::exit(2);
+                    }
+                }
+            }
+            Some(kp)
+        }
+        other => {
+            eprintln!("error: unknown --kdf={other} (expected hkdf|argon2id)");
+            std::process::exit(2);
+        }
     }
+}
 
-    let ikm = hex_decode(&ikm_hex);
-    let salt = hex_decode(&salt_hex);
+/// Размер памяти: `65536` (KiB), `64MiB`, `1GiB`, `512KiB`
+fn parse_kib(v: &str) -> Option<u32> {
+    let (num, mul) = if let Some(n) = v.strip_suffix("GiB") {
+        (n, 1024 * 1024)
+    } else if let Some(n) = v.strip_suffix("MiB") {
+        (n, 1024)
+    } else if let Some(n) = v.strip_suffix("KiB") {
+        (n, 1)
+    } else {
+        (v, 1)
+    };
+    num.parse::<u32>().ok()?.checked_mul(mul)
+}
 
-    // читаем файл
-    let plain = read_all(&input);
-    // derive keys
-    let ks = KeySchedule::derive(&ikm, &salt).expect("ks derive");
-    // шифруем весь файл одним вызовом
-    let (ciphertext, nonce) = ks.seal(aad.as_bytes(), &plain).expect("seal");
-    let ct_len = ciphertext.len();
+/// Ключевой материал: `--password=<str>` (только с argon2id) или `--ikm-hex=<HEX>`
+fn read_ikm(args: &[String]) -> Vec<u8> {
+    read_ikm_from(args, "password", "ikm-hex")
+}
 
-    // Reed–Solomon поверх ciphertext
-    let shards = rs_encode(&ciphertext, data_shards, parity_shards).expect("rs_encode");
+/// Пароль или hex-ikm под заданными именами флагов; оба сразу — неоднозначно, код 2
+fn read_ikm_from(args: &[String], password_flag: &str, ikm_flag: &str) -> Vec<u8> {
+    match (arg_flag(args, password_flag), arg_flag(args, ikm_flag)) {
+        (Some(_), Some(_)) => {
+            eprintln!("error: --{password_flag} and --{ikm_flag} are mutually exclusive");
+            std::process::exit(2);
+        }
+        (Some(pw), None) => pw.into_bytes(),
+        (None, _) => hex_decode(&require_flag(args, ikm_flag)),
+    }
+}
 
-    // меркл-дерево шардов
-    let leaf_hashes: Vec<[u8; 32]> = shards.iter().map(|s| leaf_hash(s)).collect();
-    let root = merkle_root(leaf_hashes.clone()).expect("merkle_root");
+// Ключевой профиль --key-file: те же значения, что и флаги, одним JSON
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyFile {
+    ikm_hex: Option<String>,
+    salt_hex: Option<String>,
+    aad: Option<String>,
+    kdf: Option<String>,
+    kdf_params: Option<String>, // в формате --kdf-params: "m=64MiB,t=3,p=1"
+}
 
-    // commit + scid
-    let commit = SeriesCommit {
-        version: 1,
-        size_bytes: plain.len(),
-        chunk_size: ciphertext.len(), // исторически так (весь файл как один «чанк шифртекста»)
-        erasure_data: data_shards,
-        erasure_parity: parity_shards,
-        aead_alg: "XChaCha20-Poly1305".to_string(),
-        merkle_root: root,
+/// `--key-file=<json>`: поля профиля дописываются в args как флаги, если их (или
+/// взаимоисключающих с ними) нет в командной строке — явный флаг всегда важнее файла
+fn with_key_file(args: &[String]) -> Vec<String> {
+    let mut out = args.to_vec();
+    let Some(path) = arg_flag(args, "key-file") else {
+        return out;
     };
-    let scid = commit.scid();
-
-    // записываем шард-файлы
-    fs::create_dir_all(&out_dir).expect("mkdir out_dir");
-    for (i, s) in shards.iter().enumerate() {
-        let path = out_dir.join(format!("shard_{:03}.bin", i));
-        write_all(&path, s);
+    let kf: KeyFile = match serde_json::from_slice(&read_all(Path::new(&path))) {
+        Ok(kf) => kf,
+        Err(e) => {
+            eprintln!("error: --key-file {path}: {e}");
+            std::process::exit(2);
+        }
+    };
+    for (field, v) in [("ikm_hex", &kf.ikm_hex), ("salt_hex", &kf.salt_hex)] {
+        if v.as_ref()
+            .is_some_and(|h| h.trim().is_empty() || hex::decode(h.trim()).is_err())
+        {
+            eprintln!("error: --key-file {path}: {field} is not valid hex");
+            std::process::exit(2);
+        }
     }
-
-    // манифест
-    let manifest = Manifest {
-        version: 1,
-        scid,
-        commit,
-        aad,
-        nonce_hex: hex_encode(&nonce),
-        ct_len,
-        data_shards,
-        parity_shards,
-        file_name: input
-            .file_name()
-            .and_then(|s| s.to_str())
-            .unwrap_or("input.bin")
-            .to_string(),
+    let given = |names: &[&str]| {
+        names
+            .iter()
+            .any(|n| arg_flag(args, n).is_some() || has_flag(args, n))
     };
-    let mf_json = serde_json::to_vec_pretty(&manifest).expect("manifest json");
-    write_all(&out_dir.join("manifest.json"), &mf_json);
-
-    println!("Packed → {}", out_dir.display());
+    let fields = [
+        (&kf.ikm_hex, "ikm-hex", &["ikm-hex", "password"][..]),
+        (&kf.salt_hex, "salt-hex", &["salt-hex", "salt-random"][..]),
+        (&kf.aad, "aad", &["aad", "aad-hex"][..]),
+        (&kf.kdf, "kdf", &["kdf"][..]),
+        (&kf.kdf_params, "kdf-params", &["kdf-params"][..]),
+    ];
+    for (value, flag, overrides) in fields {
+        if let Some(v) = value {
+            if !given(overrides) {
+                out.push(format!("--{flag}={v}"));
+            }
+        }
+    }
+    out
 }
 
-fn unpack_cmd(args: &[String]) {
-    if args.len() < 3 {
-        usage();
+/// KeySchedule с учётом KDF из манифеста (или из флагов при упаковке)
+fn derive_key_schedule(ikm: &[u8], salt: &[u8], kdf: Option<&KdfParams>) -> KeySchedule {
+    let Some(kp) = kdf else {
+        return KeySchedule::derive(ikm, salt).expect("ks derive");
+    };
+    if kp.alg != "argon2id" {
+        eprintln!("error: unsupported kdf '{}' in manifest", kp.alg);
+        std::process::exit(2);
     }
-    let in_dir = PathBuf::from(&args[0]);
-    let output = PathBuf::from(&args[1]);
+    let params = Params::new(kp.m_kib, kp.t, kp.p, Some(32)).unwrap_or_else(|e| {
+        eprintln!("error: invalid argon2id params: {e}");
+        std::process::exit(2);
+    });
+    let mut stretched = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(ikm, salt, &mut stretched)
+        .unwrap_or_else(|e| {
+            // типичный случай — salt короче 8 байт
+            eprintln!("error: argon2id: {e}");
+            std::process::exit(2);
+        });
+    KeySchedule::derive(&stretched, salt).expect("ks derive")
+}
 
-    let ikm_hex = require_flag(args, "ikm-hex");
-    let salt_hex = require_flag(args, "salt-hex");
+//==================== Хранилище шардов ====================//
+
+/// Откуда берутся и куда кладутся байты шардов (как лежат на носителе, с trailer, если он есть).
+/// Проверки trailer/digest — уровнем выше, в Manifest::read_shard
+trait ShardStore {
+    /// Байты шарда index; None — его нет
+    fn get(&self, index: usize) -> Option<Vec<u8>>;
+    fn put(&mut self, index: usize, bytes: &[u8]);
+    /// Индексы имеющихся шардов, по возрастанию
+    fn list(&self) -> Vec<usize>;
+    /// Имя шарда для логов
+    fn describe(&self, index: usize) -> String;
+}
 
-    let ikm = hex_decode(&ikm_hex);
-    let salt = hex_decode(&salt_hex);
-    let ks = KeySchedule::derive(&ikm, &salt).expect("ks derive");
+/// Каталог пака: shard_###.bin (indexed) или shards/<blake3>.bin (cas)
+struct FsShardStore {
+    dir: PathBuf,
+    total: usize,
+    digests: Option<Vec<String>>,
+}
 
-    // читаем манифест
-    let mf_bytes = read_all(&in_dir.join("manifest.json"));
-    let mf: Manifest = serde_json::from_slice(&mf_bytes).expect("manifest parse");
+impl FsShardStore {
+    fn new(dir: &Path, total: usize, digests: Option<Vec<String>>) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            total,
+            digests,
+        }
+    }
 
-    // собираем список шардов
-    let total = mf.data_shards + mf.parity_shards;
-    let mut shards_opt: Vec<Option<Vec<u8>>> = vec![None; total];
-    for (i, slot) in shards_opt.iter_mut().enumerate().take(total) {
-        let p = in_dir.join(format!("shard_{:03}.bin", i));
-        if p.exists() {
-            *slot = Some(read_all(&p));
+    /// Лишние shard_*.bin в каталоге: индекс ≥ total — каталог делят несовместимые паки (код 2),
+    /// прочие неожиданные имена (и любые shard_*.bin рядом с cas-пулом) — предупреждение
+    fn check_strays(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut out_of_range = Vec::new();
+        for name in entries.filter_map(|e| e.ok()?.file_name().into_string().ok()) {
+            let Some(num) = name
+                .strip_prefix("shard_")
+                .and_then(|r| r.strip_suffix(".bin"))
+            else {
+                continue;
+            };
+            match num.parse::<usize>() {
+                Ok(i) if self.digests.is_none() && i >= self.total => out_of_range.push(name),
+                Ok(i) if self.digests.is_none() && name == format!("shard_{:03}.bin", i) => {}
+                _ => log_warn!("unexpected shard file {name} (not part of this pack)"),
+            }
+        }
+        if !out_of_range.is_empty() {
+            out_of_range.sort();
+            eprintln!(
+                "error: {} has shard files beyond the manifest's {} shards: {} (directory reused by another pack?)",
+                self.dir.display(),
+                self.total,
+                out_of_range.join(", ")
+            );
+            std::process::exit(2);
         }
     }
 
-    // RS восстановление
-    let recovered_joined =
-        rs_reconstruct(shards_opt, mf.data_shards, mf.parity_shards).expect("rs_reconstruct");
-    let ciphertext = &recovered_joined[..mf.ct_len];
+    fn path(&self, index: usize) -> PathBuf {
+        match &self.digests {
+            Some(d) => self.dir.join("shards").join(format!("{}.bin", d[index])),
+            None => self.dir.join(format!("shard_{:03}.bin", index)),
+        }
+    }
+}
 
-    // расшифровка
-    let mut nonce = [0u8; 24];
-    let nonce_bytes = hex_decode(&mf.nonce_hex);
-    assert_eq!(nonce_bytes.len(), 24, "nonce must be 24 bytes");
-    nonce.copy_from_slice(&nonce_bytes);
+impl ShardStore for FsShardStore {
+    fn get(&self, index: usize) -> Option<Vec<u8>> {
+        let p = self.path(index);
+        p.exists().then(|| read_all(&p))
+    }
 
-    let plain = ks
-        .open(mf.aad.as_bytes(), &nonce, ciphertext)
-        .expect("open");
+    fn put(&mut self, index: usize, bytes: &[u8]) {
+        let p = self.path(index);
+        if self.digests.is_some() {
+            // content-addressed: одинаковые шарды (в т.ч. из других pack) хранятся один раз
+            if p.exists() {
+                return;
+            }
+            fs::create_dir_all(self.dir.join("shards")).expect("mkdir shards");
+        }
+        write_all(&p, bytes);
+    }
 
-    // финально — обрезать до заявленного в commit размера
-    let mut out_bytes = plain;
-    out_bytes.truncate(mf.commit.size_bytes);
-    write_all(&output, &out_bytes);
+    fn list(&self) -> Vec<usize> {
+        (0..self.total).filter(|&i| self.path(i).exists()).collect()
+    }
 
-    println!("Unpacked → {}", output.display());
+    fn describe(&self, index: usize) -> String {
+        self.path(index).display().to_string()
+    }
 }
 
-//==================== Stream RS: pack-stream / unpack-stream ====================//
+//==================== RS-профиль: pack/unpack ====================//
 
-fn derive_nonce_from_base(base: &[u8; 24], idx: u64) -> [u8; 24] {
-    // Простая детерминизация: XOR последних 8 байт с LE-счётчиком
-    // Условие безопасности: уникальность nonce на ключ гарантирована при idx<2^64
-    let mut n = *base;
-    let ctr = idx.to_le_bytes();
-    for j in 0..8 {
-        n[16 + j] ^= ctr[j];
+fn put_shard(store: &mut dyn ShardStore, index: usize, body: &[u8], trailer: bool) {
+    if trailer {
+        store.put(index, &with_shard_trailer(body));
+    } else {
+        store.put(index, body);
     }
-    n
 }
 
-fn pack_stream_cmd(args: &[String]) {
+fn pack_cmd(args: &[String]) {
     if args.len() < 3 {
         usage();
     }
+    let args = &with_key_file(args);
     let input = PathBuf::from(&args[0]);
     let out_dir = PathBuf::from(&args[1]);
 
-    let data_shards: usize = require_flag(args, "data").parse().expect("invalid --data");
-    let parity_shards: usize = require_flag(args, "parity")
+    let data_shards: usize = require_flag(args, "data")
         .parse()
-        .expect("invalid --parity");
-    let ikm_hex = require_flag(args, "ikm-hex");
-    let salt_hex = require_flag(args, "salt-hex");
-    let chunk_size: usize = require_flag(args, "chunk")
+        .expect("invalid --data (number)");
+    let parity_shards: usize = require_flag(args, "parity")
         .parse()
-        .expect("invalid --chunk");
-    let aad = arg_flag(args, "aad").unwrap_or_else(|| "s3p-stream".to_string());
-
-    if data_shards == 0 || parity_shards == 0 {
-        eprintln!("--data and --parity must be > 0");
-        std::process::exit(2);
-    }
-    if chunk_size == 0 {
-        eprintln!("--chunk must be > 0");
-        std::process::exit(2);
+        .expect("invalid --parity (number)");
+    if has_flag(args, "dry-run") {
+        check_rs_shard_count(data_shards, parity_shards);
+        let size = if args[0] == "-" {
+            arg_flag(args, "size")
+                .map(|s| s.parse::<usize>().expect("invalid --size"))
+                .unwrap_or_else(|| {
+                    eprintln!("error: --dry-run with stdin input needs --size=<bytes>");
+                    std::process::exit(2);
+                })
+        } else {
+            input_len(&input)
+        };
+        let ct_len = size + 16; // XChaCha20-Poly1305 тег
+        dry_run_report(size, 1, ct_len, ct_len, data_shards, parity_shards);
+        return;
     }
-
-    let ikm = hex_decode(&ikm_hex);
-    let salt = hex_decode(&salt_hex);
-    let ks = KeySchedule::derive(&ikm, &salt).expect("ks derive");
-
-    // nonce base
-    let mut nonce_base = [0u8; 24];
-    if let Some(nb_hex) = arg_flag(args, "nonce-base-hex") {
-        let nb = hex_decode(&nb_hex);
-        assert_eq!(nb.len(), 24, "nonce-base-hex must be 24 bytes (48 hex)");
-        nonce_base.copy_from_slice(&nb);
+    let opts = PackOptions::from_args(args, data_shards, parity_shards);
+    prepare_out_dir(&out_dir, args);
+
+    // читаем файл (или stdin при input = "-")
+    let from_stdin = args[0] == "-";
+    let plain = if from_stdin {
+        let size = arg_flag(args, "size").map(|s| s.parse::<usize>().expect("invalid --size"));
+        read_stdin(size)
     } else {
-        OsRng.fill_bytes(&mut nonce_base);
-    }
-
-    // входной файл (стримом)
-    let mut f_in = fs::File::open(&input).expect("open input");
-    let file_size = f_in.metadata().expect("meta").len() as usize;
-    let chunks = file_size.div_ceil(chunk_size);
-    let ct_len_per_chunk = chunk_size + 16; // XChaCha20-Poly1305 тег
-
-    let total_shards = data_shards + parity_shards;
-    fs::create_dir_all(&out_dir).expect("mkdir out_dir");
+        read_all(&input)
+    };
+    let file_name = arg_flag(args, "name").unwrap_or_else(|| {
+        if from_stdin {
+            "stdin.bin".to_string()
+        } else {
+            input
+                .file_name()
+                .and_then(|s| s.to_str())
+                .unwrap_or("input.bin")
+                .to_string()
+        }
+    });
+    write_rs_pack(&out_dir, opts, &plain, file_name, None);
 
-    // Подымаем писатели шард-файлов (append, truncate)
-    let mut shard_files = Vec::with_capacity(total_shards);
-    for i in 0..total_shards {
-        let path = out_dir.join(format!("shard_{:03}.bin", i));
-        let file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path)
-            .expect("open shard file");
-        shard_files.push(file);
-    }
+    println!("Packed → {}", out_dir.display());
+}
 
-    // Буферы
-    let mut plain_chunk = vec![0u8; chunk_size];
+// Флаги pack (общие с pack-batch), проверенные до чтения входа
+struct PackOptions {
+    kdf: Option<KdfParams>,
+    ikm: Vec<u8>,
+    salt_hex: String,
+    salt_random: bool,
+    aad: String,
+    aad_hex: Option<String>,
+    bind_context: Option<String>,
+    placement: Option<Vec<PlacementRange>>,
+    layout: ShardLayout,
+}
 
-    for idx in 0..chunks {
-        // читаем максимум chunk_size
-        let mut read_total = 0usize;
-        while read_total < chunk_size {
-            let n = f_in.read(&mut plain_chunk[read_total..]).expect("read");
-            if n == 0 {
-                break;
-            }
-            read_total += n;
+impl PackOptions {
+    fn from_args(args: &[String], data_shards: usize, parity_shards: usize) -> Self {
+        let kdf = parse_kdf_args(args);
+        let ikm = read_ikm(args);
+        // --salt-random: свежая соль, хранится в манифесте (соль не секрет)
+        let salt_random = has_flag(args, "salt-random");
+        if salt_random && arg_flag(args, "salt-hex").is_some() {
+            eprintln!("error: --salt-hex and --salt-random are mutually exclusive\n");
+            usage();
         }
-        if read_total < chunk_size {
-            // добиваем нулями (padding)
-            for b in &mut plain_chunk[read_total..] {
-                *b = 0;
-            }
+        let salt_hex = if salt_random {
+            let mut salt = [0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            hex_encode(&salt)
+        } else {
+            require_flag(args, "salt-hex")
+        };
+        let aad_str = arg_flag(args, "aad");
+        let aad_hex = arg_flag(args, "aad-hex");
+        if aad_str.is_some() && aad_hex.is_some() {
+            eprintln!("error: --aad and --aad-hex are mutually exclusive\n");
+            usage();
         }
+        // нормализуем hex сразу, чтобы в манифест попало ровно то, что пошло в AEAD
+        let aad_hex = aad_hex.map(|h| hex_encode(&hex_decode(&h)));
+        let aad = if aad_hex.is_some() {
+            String::new()
+        } else {
+            aad_str.unwrap_or_else(|| "s3p-cli".to_string())
+        };
 
-        // AEAD с детерминированным nonce
-        let nonce = derive_nonce_from_base(&nonce_base, idx as u64);
-        let ciphertext = ks
-            .seal_with_nonce(aad.as_bytes(), &nonce, &plain_chunk)
-            .expect("seal");
+        if data_shards == 0 {
+            eprintln!("error: --data must be > 0\n");
+            usage();
+        }
+        if parity_shards == 0 {
+            eprintln!("error: --parity must be > 0\n");
+            usage();
+        }
+        check_rs_shard_count(data_shards, parity_shards);
+        let placement = arg_flag(args, "placement")
+            .map(|p| load_placement(Path::new(&p), data_shards + parity_shards));
+        let cas = match arg_flag(args, "layout").as_deref() {
+            None | Some("indexed") => false,
+            Some("cas") => true,
+            Some(other) => {
+                eprintln!("error: unknown --layout={other} (expected indexed|cas)");
+                std::process::exit(2);
+            }
+        };
 
-        // RS кодирование для этого чанка
-        let shards = rs_encode(&ciphertext, data_shards, parity_shards).expect("rs");
-        // аппенд в shard_###.bin
-        for (i, s) in shards.iter().enumerate() {
-            shard_files[i].write_all(s).expect("write shard");
+        PackOptions {
+            kdf,
+            ikm,
+            salt_hex,
+            salt_random,
+            aad,
+            aad_hex,
+            bind_context: arg_flag(args, "bind-context"),
+            placement,
+            layout: ShardLayout {
+                data_shards,
+                parity_shards,
+                cas,
+                shard_trailer: has_flag(args, "shard-trailer"),
+            },
         }
     }
+}
 
-    // сброс на диск
-    for f in shard_files.iter_mut() {
-        f.flush().ok();
-    }
+/// plaintext → шарды + manifest.json (pack, pack-batch)
+fn write_rs_pack(
+    out_dir: &Path,
+    opts: PackOptions,
+    plain: &[u8],
+    file_name: String,
+    batch: Option<Vec<BatchEntry>>,
+) {
+    let data_shards = opts.layout.data_shards;
+    let parity_shards = opts.layout.parity_shards;
+    let aad_bytes = match &opts.aad_hex {
+        Some(h) => hex_decode(h),
+        None => opts.aad.as_bytes().to_vec(),
+    };
+    let aad_bytes = match &opts.bind_context {
+        Some(ctx) => bound_aad(
+            &aad_bytes,
+            ctx,
+            &file_name,
+            plain.len(),
+            data_shards,
+            parity_shards,
+        ),
+        None => aad_bytes,
+    };
+    // derive keys
+    let salt = hex_decode(&opts.salt_hex);
+    let ks = derive_key_schedule(&opts.ikm, &salt, opts.kdf.as_ref());
+    let sealed = seal_and_write_shards(out_dir, &ks, &aad_bytes, plain, &opts.layout);
 
-    // посчитаем Merkle по итоговым файлам
-    let mut leaves = Vec::<[u8; 32]>::with_capacity(total_shards);
-    for i in 0..total_shards {
-        let p = out_dir.join(format!("shard_{:03}.bin", i));
-        let bytes = read_all(&p);
-        leaves.push(leaf_hash(&bytes));
+    // манифест
+    let manifest = Manifest {
+        version: 1,
+        scid: sealed.scid,
+        commit: sealed.commit,
+        aad: opts.aad,
+        nonce_hex: hex_encode(&sealed.nonce),
+        ct_len: sealed.ct_len,
+        data_shards,
+        parity_shards,
+        file_name,
+        aad_hex: opts.aad_hex,
+        kdf: opts.kdf,
+        shard_digests: sealed.shard_digests,
+        salt_hex: opts.salt_random.then_some(opts.salt_hex),
+        bind_context: opts.bind_context,
+        placement: opts.placement,
+        shard_trailer: opts.layout.shard_trailer,
+        shard_leaves: Some(sealed.shard_leaves),
+        batch,
+    };
+    let mf_json = serde_json::to_vec_pretty(&manifest).expect("manifest json");
+    write_atomic(&out_dir.join("manifest.json"), &mf_json);
+}
+
+// Раскладка шардов RS-пака (pack, rekey)
+struct ShardLayout {
+    data_shards: usize,
+    parity_shards: usize,
+    cas: bool,
+    shard_trailer: bool,
+}
+
+// Всё, что pack вычисляет при шифровании, — для полей манифеста
+struct SealedPack {
+    commit: SeriesCommit,
+    scid: String,
+    nonce: [u8; 24],
+    ct_len: usize,
+    shard_digests: Option<Vec<String>>,
+    shard_leaves: Vec<String>,
+}
+
+/// seal → RS → Merkle → shard-файлы в out_dir (manifest.json пишет вызывающий)
+fn seal_and_write_shards(
+    out_dir: &Path,
+    ks: &KeySchedule,
+    aad_bytes: &[u8],
+    plain: &[u8],
+    layout: &ShardLayout,
+) -> SealedPack {
+    let (sealed, shards) = seal_shards(ks, aad_bytes, plain, layout);
+    fs::create_dir_all(out_dir).expect("mkdir out_dir");
+    let mut store = FsShardStore::new(out_dir, shards.len(), sealed.shard_digests.clone());
+    put_shards(&mut store, &shards, layout.shard_trailer);
+    sealed
+}
+
+/// Шарды пака в хранилище по порядку индексов
+fn put_shards(store: &mut dyn ShardStore, shards: &[Vec<u8>], trailer: bool) {
+    for (i, s) in shards.iter().enumerate() {
+        put_shard(store, i, s, trailer);
     }
-    let root = merkle_root(leaves).expect("merkle_root");
+}
 
-    // commit + scid (chunk_size = размер plaintext-чанка)
+/// seal → RS → Merkle: тела шардов и поля манифеста, без записи
+fn seal_shards(
+    ks: &KeySchedule,
+    aad_bytes: &[u8],
+    plain: &[u8],
+    layout: &ShardLayout,
+) -> (SealedPack, Vec<Vec<u8>>) {
+    // шифруем весь файл одним вызовом
+    let (ciphertext, nonce) = ks.seal(aad_bytes, plain).expect("seal");
+    let ct_len = ciphertext.len();
+
+    // Reed–Solomon поверх ciphertext
+    let shards =
+        rs_encode(&ciphertext, layout.data_shards, layout.parity_shards).expect("rs_encode");
+    log_debug!(
+        "ct_len={ct_len}, {} shards x {}B",
+        shards.len(),
+        shards.first().map_or(0, |s| s.len())
+    );
+
+    // меркл-дерево шардов
+    let leaf_hashes: Vec<[u8; 32]> = shards.iter().map(|s| leaf_hash(s)).collect();
+    let root = merkle_root(leaf_hashes.clone()).expect("merkle_root");
+
+    // commit + scid
     let commit = SeriesCommit {
         version: 1,
-        size_bytes: file_size,
-        chunk_size,
-        erasure_data: data_shards,
-        erasure_parity: parity_shards,
+        size_bytes: plain.len(),
+        chunk_size: ciphertext.len(), // исторически так (весь файл как один «чанк шифртекста»)
+        erasure_data: layout.data_shards,
+        erasure_parity: layout.parity_shards,
         aead_alg: "XChaCha20-Poly1305".to_string(),
         merkle_root: root,
     };
     let scid = commit.scid();
 
-    // манифест стрима
-    let sm = StreamManifest {
-        version: 1,
-        scid,
+    // cas: имя шард-файла — blake3 тела
+    let shard_digests = layout.cas.then(|| {
+        shards
+            .iter()
+            .map(|s| blake3::hash(s).to_hex().to_string())
+            .collect::<Vec<_>>()
+    });
+
+    let sealed = SealedPack {
         commit,
-        aad,
-        file_name: input
-            .file_name()
-            .and_then(|s| s.to_str())
-            .unwrap_or("input.bin")
-            .to_string(),
-        size_bytes: file_size,
-        data_shards,
-        parity_shards,
-        chunk_size,
-        ct_len_per_chunk,
-        chunks,
-        nonce_base_hex: hex_encode(&nonce_base),
+        scid,
+        nonce,
+        ct_len,
+        shard_digests,
+        shard_leaves: leaf_hashes.iter().map(|h| hex_encode(h)).collect(),
     };
-    let sm_json = serde_json::to_vec_pretty(&sm).unwrap();
-    write_all(&out_dir.join("manifest_stream.json"), &sm_json);
-
-    println!(
-        "Stream packed → {} ({} chunks, chunk={}B)",
-        out_dir.display(),
-        chunks,
-        chunk_size
-    );
+    (sealed, shards)
 }
 
-fn unpack_stream_cmd(args: &[String]) {
-    if args.len() < 3 {
+/// Ротация ключа RS-пака: шифртекст из шардов → open старым ключом → seal новым (свежий nonce)
+/// → новые шарды и манифест в out_dir. Раскладка, file_name и aad переносятся, если не заданы заново
+fn rekey_cmd(args: &[String]) {
+    if args.len() < 2 {
         usage();
     }
     let in_dir = PathBuf::from(&args[0]);
-    let output = PathBuf::from(&args[1]);
-
-    let ikm_hex = require_flag(args, "ikm-hex");
-    let salt_hex = require_flag(args, "salt-hex");
-
-    let ikm = hex_decode(&ikm_hex);
-    let salt = hex_decode(&salt_hex);
-    let ks = KeySchedule::derive(&ikm, &salt).expect("ks derive");
-
-    // читаем stream-манифест
-    let sm_bytes = read_all(&in_dir.join("manifest_stream.json"));
-    let sm: StreamManifest = serde_json::from_slice(&sm_bytes).expect("manifest_stream parse");
-
-    let total_shards = sm.data_shards + sm.parity_shards;
-    let shard_size = sm.ct_len_per_chunk.div_ceil(sm.data_shards);
+    let out_dir = PathBuf::from(&args[1]);
+    if fs::canonicalize(&in_dir).ok() == fs::canonicalize(&out_dir).ok() {
+        eprintln!(
+            "error: rekey needs a separate <out_dir> (old shards are kept until you remove them)"
+        );
+        std::process::exit(2);
+    }
+    prepare_out_dir(&out_dir, args);
+
+    let mf: Manifest =
+        serde_json::from_slice(&read_all(&in_dir.join("manifest.json"))).expect("manifest parse");
+    check_scid("manifest", &mf.commit, &mf.scid);
+    // ключи — как у pack/unpack: пароль (argon2id) или hex-ikm; --password — синоним --new-password
+    if arg_flag(args, "password").is_some() && arg_flag(args, "new-password").is_some() {
+        eprintln!("error: --password and --new-password are mutually exclusive");
+        std::process::exit(2);
+    }
+    let new_password_flag = if arg_flag(args, "password").is_some() {
+        "password"
+    } else {
+        "new-password"
+    };
+    let old_ikm = read_ikm_from(args, "old-password", "old-ikm-hex");
+    if arg_flag(args, "old-password").is_some() && mf.kdf.is_none() {
+        eprintln!(
+            "error: {} was packed without a password (kdf hkdf); use --old-ikm-hex",
+            in_dir.display()
+        );
+        std::process::exit(2);
+    }
+    let old_salt = match (arg_flag(args, "old-salt-hex"), &mf.salt_hex) {
+        (Some(h), _) => hex_decode(&h),
+        (None, Some(m)) => hex_decode(m),
+        (None, None) => hex_decode(&require_flag(args, "old-salt-hex")),
+    };
+    let new_ikm = read_ikm_from(args, new_password_flag, "new-ikm-hex");
+    let new_salt_hex = require_flag(args, "new-salt-hex");
+    let new_kdf = parse_kdf_args_for(args, new_password_flag);
 
-    // Открываем доступные шард-файлы как читатели
-    let mut shard_readers: Vec<Option<BufReader<fs::File>>> = Vec::with_capacity(total_shards);
-    for i in 0..total_shards {
-        let p = in_dir.join(format!("shard_{:03}.bin", i));
-        if let Ok(f) = fs::File::open(&p) {
-            shard_readers.push(Some(BufReader::new(f)));
-        } else {
-            shard_readers.push(None);
+    // ---- старый ключ: восстановить plaintext
+    let total = mf.data_shards + mf.parity_shards;
+    let store = mf.shard_store(&in_dir);
+    let shards_opt: Vec<Option<Vec<u8>>> = (0..total).map(|i| mf.read_shard(&store, i)).collect();
+    let (expected_len, bad_lens) = present_shard_len_mismatches(&shards_opt);
+    if !bad_lens.is_empty() {
+        for (i, len) in &bad_lens {
+            eprintln!("error: shard_{i:03} is {len} bytes, expected {expected_len}");
         }
+        std::process::exit(2);
     }
+    let mut ciphertext = match rs_reconstruct(shards_opt, mf.data_shards, mf.parity_shards) {
+        Ok(ct) => ct,
+        Err(e) => {
+            eprintln!("error: cannot reconstruct {}: {e}", in_dir.display());
+            std::process::exit(2);
+        }
+    };
+    check_ct_len("manifest", mf.ct_len, ciphertext.len());
+    ciphertext.truncate(mf.ct_len);
+    let old_ks = derive_key_schedule(&old_ikm, &old_salt, mf.kdf.as_ref());
+    let mut nonce = [0u8; 24];
+    nonce.copy_from_slice(&hex_decode(&mf.nonce_hex));
+    let Ok(mut plain) = old_ks.open(&mf.aad_bytes(), &nonce, &ciphertext) else {
+        eprintln!("error: old key does not open {}", in_dir.display());
+        std::process::exit(2);
+    };
+    plain.truncate(mf.commit.size_bytes);
 
-    let mut out = fs::File::create(&output).expect("create output");
+    // ---- новые параметры: по умолчанию как у исходного пака
+    // явно заданный флаг разбираем строго: опечатка не должна молча оставить старую раскладку
+    let shard_flag = |name: &str, default: usize| match arg_flag(args, name) {
+        Some(s) => s.parse::<usize>().unwrap_or_else(|_| {
+            eprintln!("error: invalid --{name}={s} (number)");
+            std::process::exit(2);
+        }),
+        None => default,
+    };
+    let data_shards = shard_flag("data", mf.data_shards);
+    let parity_shards = shard_flag("parity", mf.parity_shards);
+    if data_shards == 0 || parity_shards == 0 {
+        eprintln!("error: --data and --parity must be > 0");
+        std::process::exit(2);
+    }
+    check_rs_shard_count(data_shards, parity_shards);
+    let same_shape = data_shards == mf.data_shards && parity_shards == mf.parity_shards;
+    let placement = match arg_flag(args, "placement") {
+        Some(p) => Some(load_placement(Path::new(&p), data_shards + parity_shards)),
+        None if same_shape => mf.placement.clone(),
+        None => {
+            if mf.placement.is_some() {
+                log_warn!(
+                    "shard count changed: placement dropped (pass --placement to set a new one)"
+                );
+            }
+            None
+        }
+    };
+    let file_name = arg_flag(args, "name").unwrap_or_else(|| mf.file_name.clone());
+    let (aad, aad_hex) = match arg_flag(args, "aad") {
+        Some(a) => (a, None),
+        None => (mf.aad.clone(), mf.aad_hex.clone()),
+    };
+    let base_aad = match &aad_hex {
+        Some(h) => hex_decode(h),
+        None => aad.as_bytes().to_vec(),
+    };
+    let aad_bytes = match &mf.bind_context {
+        Some(ctx) => bound_aad(
+            &base_aad,
+            ctx,
+            &file_name,
+            plain.len(),
+            data_shards,
+            parity_shards,
+        ),
+        None => base_aad,
+    };
+
+    let new_salt = hex_decode(&new_salt_hex);
+    let ks = derive_key_schedule(&new_ikm, &new_salt, new_kdf.as_ref());
+    let layout = ShardLayout {
+        data_shards,
+        parity_shards,
+        cas: mf.shard_digests.is_some(),
+        shard_trailer: mf.shard_trailer,
+    };
+    let sealed = seal_and_write_shards(&out_dir, &ks, &aad_bytes, &plain, &layout);
+
+    let manifest = Manifest {
+        version: 1,
+        scid: sealed.scid,
+        commit: sealed.commit,
+        aad,
+        nonce_hex: hex_encode(&sealed.nonce),
+        ct_len: sealed.ct_len,
+        data_shards,
+        parity_shards,
+        file_name,
+        aad_hex,
+        kdf: new_kdf,
+        shard_digests: sealed.shard_digests,
+        // соль была встроена в манифест (--salt-random) — встраиваем и новую
+        salt_hex: mf.salt_hex.is_some().then_some(new_salt_hex),
+        bind_context: mf.bind_context.clone(),
+        placement,
+        shard_trailer: mf.shard_trailer,
+        shard_leaves: Some(sealed.shard_leaves),
+        batch: mf.batch.clone(), // plaintext тот же — смещения не меняются
+    };
+    let mf_json = serde_json::to_vec_pretty(&manifest).expect("manifest json");
+    write_atomic(&out_dir.join("manifest.json"), &mf_json);
+
+    println!(
+        "Rekeyed → {} (scid {} → {})",
+        out_dir.display(),
+        mf.scid,
+        manifest.scid
+    );
+}
+
+fn unpack_cmd(args: &[String]) {
+    if args.len() < 3 {
+        usage();
+    }
+    let args = &with_key_file(args);
+    let in_dir = PathBuf::from(&args[0]);
+    let output = PathBuf::from(&args[1]);
+
+    let (_, out_bytes) = open_rs_pack(&in_dir, args);
+    write_all(&output, &out_bytes);
+
+    println!("Unpacked → {}", output.display());
+}
+
+/// Манифест + расшифрованный plaintext RS-пака (unpack, unpack-batch)
+fn open_rs_pack(in_dir: &Path, args: &[String]) -> (Manifest, Vec<u8>) {
+    let ikm = read_ikm(args);
+
+    // читаем манифест
+    let mf_bytes = read_all(&in_dir.join("manifest.json"));
+    let mf: Manifest = serde_json::from_slice(&mf_bytes).expect("manifest parse");
+    check_scid("manifest", &mf.commit, &mf.scid);
+    let salt = mf.resolve_salt(args);
+    // KDF — как при упаковке (из манифеста)
+    let ks = derive_key_schedule(&ikm, &salt, mf.kdf.as_ref());
+
+    let store = mf.shard_store(in_dir);
+    store.check_strays();
+    let ciphertext = recover_ciphertext(&mf, &store);
+
+    // расшифровка
+    let mut nonce = [0u8; 24];
+    let nonce_bytes = hex_decode(&mf.nonce_hex);
+    assert_eq!(nonce_bytes.len(), 24, "nonce must be 24 bytes");
+    nonce.copy_from_slice(&nonce_bytes);
+
+    // чужие шарды/nonce под этим манифестом (или подменённые file_name/контекст при --bind-context)
+    let Ok(plain) = ks.open(&mf.aad_bytes(), &nonce, &ciphertext) else {
+        eprintln!(
+            "error: {}: AEAD open failed (wrong key/salt, or shards do not belong to this manifest)",
+            in_dir.display()
+        );
+        std::process::exit(2);
+    };
+
+    // финально — обрезать до заявленного в commit размера
+    let mut out_bytes = plain;
+    out_bytes.truncate(mf.commit.size_bytes);
+    (mf, out_bytes)
+}
+
+/// Шарды из хранилища → RS-восстановление → шифртекст ровно ct_len байт
+fn recover_ciphertext(mf: &Manifest, store: &dyn ShardStore) -> Vec<u8> {
+    // собираем список шардов
+    let total = mf.data_shards + mf.parity_shards;
+    let mut shards_opt: Vec<Option<Vec<u8>>> = vec![None; total];
+    for (i, slot) in shards_opt.iter_mut().enumerate() {
+        *slot = mf.read_shard(store, i);
+    }
+
+    log_debug!(
+        "{}/{} shards present (need {})",
+        shards_opt.iter().filter(|s| s.is_some()).count(),
+        total,
+        mf.data_shards
+    );
+    let missing: Vec<usize> = (0..total).filter(|&i| shards_opt[i].is_none()).collect();
+    log_missing_by_provider(&mf.missing_by_provider(&missing));
+    // шард не той длины иначе роняет rs_reconstruct глубоко в библиотеке
+    let (expected_len, bad_lens) = present_shard_len_mismatches(&shards_opt);
+    if !bad_lens.is_empty() {
+        for (i, len) in &bad_lens {
+            eprintln!("error: shard_{i:03} is {len} bytes, expected {expected_len}");
+        }
+        std::process::exit(2);
+    }
+    // RS восстановление
+    let recovered_joined =
+        rs_reconstruct(shards_opt, mf.data_shards, mf.parity_shards).expect("rs_reconstruct");
+    check_ct_len("manifest", mf.ct_len, recovered_joined.len());
+    let mut ciphertext = recovered_joined;
+    ciphertext.truncate(mf.ct_len);
+    ciphertext
+}
+
+/// Аварийное восстановление manifest.json по уцелевшим shard_###.bin: RS-сторона (шифртекст,
+/// merkle_root, scid) пересчитывается из шардов, AEAD-сторону (nonce, aad, размер) даёт пользователь
+fn rebuild_manifest_cmd(args: &[String]) {
+    if args.is_empty() {
+        usage();
+    }
+    let in_dir = PathBuf::from(&args[0]);
+    let data_shards: usize = require_flag(args, "data").parse().expect("invalid --data");
+    let parity_shards: usize = require_flag(args, "parity")
+        .parse()
+        .expect("invalid --parity");
+    check_rs_shard_count(data_shards, parity_shards);
+    let size: usize = require_flag(args, "size").parse().expect("invalid --size");
+    let ct_len: usize = arg_flag_default(args, "ct-len", size + 16); // XChaCha20-Poly1305 тег
+    let nonce_hex = require_flag(args, "nonce-hex");
+    if hex_decode(&nonce_hex).len() != 24 {
+        eprintln!("error: --nonce-hex must be 24 bytes (48 hex chars)");
+        std::process::exit(2);
+    }
+    let aad_hex = arg_flag(args, "aad-hex").map(|h| hex_encode(&hex_decode(&h)));
+    let aad = match (&aad_hex, arg_flag(args, "aad")) {
+        (Some(_), Some(_)) => {
+            eprintln!("error: --aad and --aad-hex are mutually exclusive");
+            std::process::exit(2);
+        }
+        (Some(_), None) => String::new(),
+        (None, a) => a.unwrap_or_else(|| "s3p-cli".to_string()),
+    };
+    let shard_trailer = has_flag(args, "shard-trailer");
+    let kdf = parse_kdf_args(args);
+    // cas-пул: порядок шардов (какой хэш под каким индексом) был только в манифесте
+    let cas_refused =
+        "error: --layout=cas packs cannot be rebuilt: shard order is recorded only in manifest.json";
+    match arg_flag(args, "layout").as_deref() {
+        None | Some("indexed") => {}
+        Some("cas") => {
+            eprintln!("{cas_refused}");
+            std::process::exit(2);
+        }
+        Some(other) => {
+            eprintln!("error: unknown --layout={other} (expected indexed or cas)");
+            std::process::exit(2);
+        }
+    }
+    let mf_path = in_dir.join("manifest.json");
+    if mf_path.exists() {
+        eprintln!(
+            "error: {} exists; move it away to rebuild",
+            mf_path.display()
+        );
+        std::process::exit(2);
+    }
+
+    // уцелевшие шарды (с trailer — только прошедшие проверку)
+    let total = data_shards + parity_shards;
+    let store = FsShardStore::new(&in_dir, total, None);
+    store.check_strays();
+    let shards_opt: Vec<Option<Vec<u8>>> = (0..total)
+        .map(|i| {
+            let bytes = store.get(i)?;
+            if shard_trailer {
+                strip_shard_trailer(bytes)
+            } else {
+                Some(bytes)
+            }
+        })
+        .collect();
+    let present = shards_opt.iter().filter(|s| s.is_some()).count();
+    let (expected_len, bad_lens) = present_shard_len_mismatches(&shards_opt);
+    if !bad_lens.is_empty() {
+        for (i, len) in &bad_lens {
+            eprintln!("error: shard_{i:03} is {len} bytes, expected {expected_len}");
+        }
+        std::process::exit(2);
+    }
+    if present < data_shards {
+        if in_dir.join("shards").is_dir() {
+            eprintln!("{cas_refused}");
+        } else {
+            eprintln!("error: {present}/{total} shards present, need {data_shards}");
+        }
+        std::process::exit(2);
+    }
+
+    // шифртекст → заново RS: так получаем и недостающие листья, и проверку --ct-len/--data/--parity
+    let joined =
+        rs_reconstruct(shards_opt.clone(), data_shards, parity_shards).expect("rs_reconstruct");
+    check_ct_len("--ct-len", ct_len, joined.len());
+    let shards = rs_encode(&joined[..ct_len], data_shards, parity_shards).expect("rs_encode");
+    let mismatched: Vec<usize> = (0..total)
+        .filter(|&i| shards_opt[i].as_ref().is_some_and(|s| *s != shards[i]))
+        .collect();
+    if !mismatched.is_empty() {
+        eprintln!(
+            "error: re-encoded shards differ from disk at {} (wrong --ct-len/--data/--parity, or corrupt shards)",
+            fmt_index_ranges(&mismatched)
+        );
+        std::process::exit(2);
+    }
+
+    let leaf_hashes: Vec<[u8; 32]> = shards.iter().map(|s| leaf_hash(s)).collect();
+    let commit = SeriesCommit {
+        version: 1,
+        size_bytes: size,
+        chunk_size: ct_len,
+        erasure_data: data_shards,
+        erasure_parity: parity_shards,
+        aead_alg: "XChaCha20-Poly1305".to_string(),
+        merkle_root: merkle_root(leaf_hashes.clone()).expect("merkle_root"),
+    };
+    let manifest = Manifest {
+        version: 1,
+        scid: commit.scid(),
+        commit,
+        aad,
+        nonce_hex: nonce_hex.trim().to_lowercase(),
+        ct_len,
+        data_shards,
+        parity_shards,
+        file_name: arg_flag(args, "file-name").unwrap_or_else(|| "input.bin".to_string()),
+        aad_hex,
+        kdf,
+        shard_digests: None,
+        salt_hex: arg_flag(args, "salt-hex").map(|h| hex_encode(&hex_decode(&h))),
+        bind_context: arg_flag(args, "bind-context"),
+        placement: None,
+        shard_trailer,
+        shard_leaves: Some(leaf_hashes.iter().map(|h| hex_encode(h)).collect()),
+        batch: None,
+    };
+    write_atomic(
+        &mf_path,
+        &serde_json::to_vec_pretty(&manifest).expect("manifest json"),
+    );
+    println!(
+        "manifest rebuilt → {} (scid={}, {present}/{total} shards present)",
+        mf_path.display(),
+        manifest.scid
+    );
+}
+
+//==================== Batch: много мелких файлов в одном паке ====================//
+
+/// Все файлы под dir (рекурсивно), относительные пути с '/', по возрастанию
+fn collect_batch_files(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut out = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(d) = stack.pop() {
+        for e in fs::read_dir(&d).expect("read_dir") {
+            let p = e.expect("dir entry").path();
+            if p.is_dir() {
+                stack.push(p);
+            } else if p.is_file() {
+                let rel = p.strip_prefix(dir).expect("strip_prefix");
+                let name: Vec<&str> = rel
+                    .components()
+                    .map(|c| c.as_os_str().to_str().expect("non-UTF-8 file name"))
+                    .collect();
+                out.push((name.join("/"), p));
+            }
+        }
+    }
+    out.sort();
+    out
+}
+
+/// Имя из манифеста → путь под out_dir; абсолютные пути и '..' (подделанный манифест) отвергаются
+fn batch_output_path(out_dir: &Path, name: &str) -> Option<PathBuf> {
+    let parts: Vec<&str> = name.split('/').collect();
+    if name.is_empty()
+        || parts
+            .iter()
+            .any(|p| p.is_empty() || *p == "." || *p == "..")
+    {
+        return None;
+    }
+    Some(
+        parts
+            .iter()
+            .fold(out_dir.to_path_buf(), |acc, p| acc.join(p)),
+    )
+}
+
+fn pack_batch_cmd(args: &[String]) {
+    if args.len() < 3 {
+        usage();
+    }
+    let args = &with_key_file(args);
+    let input_dir = PathBuf::from(&args[0]);
+    let out_dir = PathBuf::from(&args[1]);
+    let data_shards: usize = require_flag(args, "data").parse().expect("invalid --data");
+    let parity_shards: usize = require_flag(args, "parity")
+        .parse()
+        .expect("invalid --parity");
+    let opts = PackOptions::from_args(args, data_shards, parity_shards);
+    if !input_dir.is_dir() {
+        eprintln!("error: {} is not a directory", input_dir.display());
+        std::process::exit(2);
+    }
+    prepare_out_dir(&out_dir, args);
+
+    // склеиваем файлы в один plaintext; индекс (имя, смещение, длина) — в манифест
+    let files = collect_batch_files(&input_dir);
+    // на регистронезависимой ФС такие имена при извлечении перезапишут друг друга
+    let mut lower = HashSet::new();
+    for (name, _) in &files {
+        if !lower.insert(name.to_lowercase()) {
+            eprintln!("error: '{name}' collides with another file name ignoring case");
+            std::process::exit(2);
+        }
+    }
+    let mut plain = Vec::new();
+    let mut entries = Vec::with_capacity(files.len());
+    for (name, p) in files {
+        let bytes = read_all(&p);
+        entries.push(BatchEntry {
+            name,
+            offset: plain.len(),
+            len: bytes.len(),
+        });
+        plain.extend_from_slice(&bytes);
+    }
+    let file_name = input_dir
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("batch")
+        .to_string();
+    let count = entries.len();
+    write_rs_pack(&out_dir, opts, &plain, file_name, Some(entries));
+
+    println!(
+        "Batch packed → {} ({count} files, {} bytes)",
+        out_dir.display(),
+        plain.len()
+    );
+}
+
+fn unpack_batch_cmd(args: &[String]) {
+    if args.len() < 3 {
+        usage();
+    }
+    let args = &with_key_file(args);
+    let in_dir = PathBuf::from(&args[0]);
+    let out_dir = PathBuf::from(&args[1]);
+    let only = arg_flag(args, "file");
+
+    let (mf, plain) = open_rs_pack(&in_dir, args);
+    let Some(entries) = mf.batch else {
+        eprintln!(
+            "error: {} is not a pack-batch pack (no batch index)",
+            in_dir.display()
+        );
+        std::process::exit(2);
+    };
+    if let Some(name) = &only {
+        if !entries.iter().any(|e| &e.name == name) {
+            eprintln!("error: '{name}' is not in the batch");
+            std::process::exit(2);
+        }
+    }
+
+    let mut written = 0usize;
+    for e in entries
+        .iter()
+        .filter(|e| only.is_none() || only.as_ref() == Some(&e.name))
+    {
+        let (Some(path), Some(bytes)) = (
+            batch_output_path(&out_dir, &e.name),
+            plain.get(e.offset..e.offset.saturating_add(e.len)),
+        ) else {
+            eprintln!("error: bad batch entry '{}' in manifest.json", e.name);
+            std::process::exit(2);
+        };
+        // существующие файлы не перезаписываем
+        if path.exists() {
+            eprintln!("error: {} already exists", path.display());
+            std::process::exit(2);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("mkdir");
+        }
+        write_all(&path, bytes);
+        written += 1;
+    }
+    println!("Batch unpacked → {} ({written} files)", out_dir.display());
+}
+
+//==================== Stream RS: pack-stream / unpack-stream ====================//
+
+fn derive_nonce_from_base(base: &[u8; 24], idx: u64) -> [u8; 24] {
+    // Простая детерминизация: XOR последних 8 байт с LE-счётчиком.
+    // При фиксированной базе x ↦ base ^ x — биекция, поэтому разные idx дают разные nonce
+    // при любых младших байтах базы (в т.ч. заданной через --nonce-base-hex); idx < 2^64 всегда.
+    // Сложение с переносом не надёжнее и сломало бы расшифровку уже упакованных потоков.
+    // Реальный риск — повтор самой базы под тем же ключом (две упаковки с одним --nonce-base-hex).
+    let mut n = *base;
+    let ctr = idx.to_le_bytes();
+    for j in 0..8 {
+        n[16 + j] ^= ctr[j];
+    }
+    n
+}
+
+// Прогресс pack-stream (stream_progress.json) для --resume; удаляется по завершении
+#[derive(Serialize, Deserialize)]
+struct StreamProgress {
+    version: u8,
+    size_bytes: usize,
+    data_shards: usize,
+    parity_shards: usize,
+    chunk_size: usize,
+    aad: String,
+    nonce_base_hex: String,
+    shard_len: Option<usize>,
+    chunks_done: usize, // сколько чанков полностью записано во все shard-файлы
+    #[serde(default)]
+    kdf: Option<KdfParams>,
+    // отпечатки для --resume: тот же ключ (соль + ключевой коммитмент) и тот же вход;
+    // в старых файлах их нет — такой прогресс не продолжаем
+    #[serde(default)]
+    salt_hex: Option<String>,
+    #[serde(default)]
+    key_commit_hex: Option<String>,
+    #[serde(default)]
+    input_prefix_sha256: Option<String>,
+}
+
+// сколько первых байт входа хэшируется в input_prefix_sha256
+const RESUME_INPUT_PREFIX: u64 = 1 << 20;
+
+/// Коммитмент ключа для --resume: sha256 от AEAD-тега пустого сообщения под фиксированным
+/// nonce и отдельным AAD. Сам ikm/пароль не хэшируем — иначе пароль, растянутый argon2id,
+/// можно было бы перебирать по быстрому sha256 из прогресс-файла
+fn stream_key_commit(ks: &KeySchedule) -> String {
+    let tag = ks
+        .seal_with_nonce(b"s3p-stream-key-commit-v1", &[0xFF; 24], &[])
+        .expect("seal key commitment");
+    hex_encode(&Sha256::digest(tag))
+}
+
+/// sha256 первых RESUME_INPUT_PREFIX байт входа (другой файл той же длины → другой отпечаток)
+fn input_prefix_sha256(path: &Path) -> String {
+    let f = fs::File::open(path).expect("open input");
+    let mut h = Sha256::new();
+    std::io::copy(&mut f.take(RESUME_INPUT_PREFIX), &mut h).expect("read input");
+    hex_encode(&h.finalize())
+}
+
+// как часто (в чанках) обновлять stream_progress.json
+const PROGRESS_EVERY: usize = 64;
+
+// --chunk=auto: ~AUTO_CHUNKS чанков, кратно 4 KiB, в пределах [64 KiB, 8 MiB]
+const AUTO_CHUNKS: usize = 1000;
+const AUTO_CHUNK_MIN: usize = 64 << 10;
+const AUTO_CHUNK_MAX: usize = 8 << 20;
+
+fn auto_chunk_size(file_size: usize) -> usize {
+    (file_size.div_ceil(AUTO_CHUNKS).div_ceil(4096) * 4096).clamp(AUTO_CHUNK_MIN, AUTO_CHUNK_MAX)
+}
+
+/// --write-rate: ведро токенов на суммарную запись во все shard-файлы.
+/// Старт с пустым ведром, ёмкость — секунда трафика (не копим простой дольше)
+struct WriteThrottle {
+    rate: f64, // байт/с
+    tokens: f64,
+    last: Instant,
+}
+
+impl WriteThrottle {
+    fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            rate: bytes_per_sec as f64,
+            tokens: 0.0,
+            last: Instant::now(),
+        }
+    }
+
+    /// Списать bytes; при долге — поспать, пока ведро не восполнит его
+    fn consume(&mut self, bytes: usize) {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.rate) - bytes as f64;
+        self.last = now;
+        if self.tokens < 0.0 {
+            thread::sleep(Duration::from_secs_f64(-self.tokens / self.rate));
+        }
+    }
+}
+
+fn pack_stream_cmd(args: &[String]) {
+    if args.len() < 3 {
+        usage();
+    }
+    let input = PathBuf::from(&args[0]);
+    let out_dir = PathBuf::from(&args[1]);
+
+    let data_shards: usize = require_flag(args, "data").parse().expect("invalid --data");
+    let parity_shards: usize = require_flag(args, "parity")
+        .parse()
+        .expect("invalid --parity");
+    let chunk_size: usize = match require_flag(args, "chunk").as_str() {
+        "auto" => {
+            let c = auto_chunk_size(input_len(&input));
+            log_info!("--chunk=auto: {c} bytes");
+            c
+        }
+        s => s.parse().expect("invalid --chunk"),
+    };
+    // параметры раскладки проверяются до --dry-run: пробный прогон с неверными не должен
+    // ни падать на делении, ни проваливаться в настоящую упаковку
+    if data_shards == 0 || parity_shards == 0 {
+        eprintln!("--data and --parity must be > 0");
+        std::process::exit(2);
+    }
+    check_rs_shard_count(data_shards, parity_shards);
+    if chunk_size == 0 {
+        eprintln!("--chunk must be > 0");
+        std::process::exit(2);
+    }
+    if has_flag(args, "dry-run") {
+        let size = input_len(&input);
+        dry_run_report(
+            size,
+            size.div_ceil(chunk_size).max(1),
+            chunk_size + 16,
+            chunk_size,
+            data_shards,
+            parity_shards,
+        );
+        return;
+    }
+    let kdf = parse_kdf_args(args);
+    let ikm = read_ikm(args);
+    let salt_hex = require_flag(args, "salt-hex");
+    let aad = arg_flag(args, "aad").unwrap_or_else(|| "s3p-stream".to_string());
+
+    let mut throttle = arg_flag(args, "write-rate").map(|s| match s.parse::<u64>() {
+        Ok(r) if r > 0 => WriteThrottle::new(r),
+        _ => {
+            eprintln!("error: --write-rate expects bytes/s > 0, got '{s}'");
+            std::process::exit(2);
+        }
+    });
+
+    let salt = hex_decode(&salt_hex);
+    let ks = derive_key_schedule(&ikm, &salt, kdf.as_ref());
+
+    // входной файл (стримом)
+    let mut f_in = fs::File::open(&input).expect("open input");
+    let file_size = f_in.metadata().expect("meta").len() as usize;
+    let key_commit_hex = stream_key_commit(&ks);
+    let input_prefix_hex = input_prefix_sha256(&input);
+    // пустой вход — один нулевой чанк: в паке остаётся AEAD-тег, и unpack-stream его проверяет
+    // (без чанков подмена пустого пака ничем бы не обнаруживалась); size_bytes=0 обрежет вывод
+    let chunks = file_size.div_ceil(chunk_size).max(1);
+    let ct_len_per_chunk = chunk_size + 16; // XChaCha20-Poly1305 тег
+
+    let total_shards = data_shards + parity_shards;
+    // --resume дописывает в свой же каталог
+    if !has_flag(args, "resume") {
+        prepare_out_dir(&out_dir, args);
+    }
+    fs::create_dir_all(&out_dir).expect("mkdir out_dir");
+    let progress_path = out_dir.join("stream_progress.json");
+
+    // --resume: продолжаем с места, записанного в stream_progress.json
+    let resume = if has_flag(args, "resume") {
+        if !progress_path.exists() {
+            eprintln!("--resume: no {} to resume from", progress_path.display());
+            std::process::exit(2);
+        }
+        let pr: StreamProgress =
+            serde_json::from_slice(&read_all(&progress_path)).expect("stream_progress parse");
+        if pr.data_shards != data_shards
+            || pr.parity_shards != parity_shards
+            || pr.chunk_size != chunk_size
+            || pr.size_bytes != file_size
+            || pr.aad != aad
+            || pr.kdf != kdf
+        {
+            eprintln!(
+                "--resume: parameters differ from progress file (data={}, parity={}, chunk={}, size={})",
+                pr.data_shards, pr.parity_shards, pr.chunk_size, pr.size_bytes
+            );
+            std::process::exit(2);
+        }
+        // иначе серия молча окажется запечатана разными ключами / склеена из разных входов
+        let (Some(pr_salt), Some(pr_key), Some(pr_input)) =
+            (&pr.salt_hex, &pr.key_commit_hex, &pr.input_prefix_sha256)
+        else {
+            eprintln!(
+                "--resume: progress file has no key/input fingerprints; restart without --resume"
+            );
+            std::process::exit(2);
+        };
+        if hex_decode(pr_salt) != salt {
+            eprintln!("--resume: --salt-hex differs from progress file");
+            std::process::exit(2);
+        }
+        if !ct_eq(pr_key.as_bytes(), key_commit_hex.as_bytes()) {
+            eprintln!("--resume: key differs from the one the progress file was written with");
+            std::process::exit(2);
+        }
+        if !ct_eq(pr_input.as_bytes(), input_prefix_hex.as_bytes()) {
+            eprintln!("--resume: input differs from the one the progress file was written for");
+            std::process::exit(2);
+        }
+        Some(pr)
+    } else {
+        None
+    };
+
+    // nonce base (при --resume — строго из прогресса)
+    let mut nonce_base = [0u8; 24];
+    if let Some(pr) = &resume {
+        let nb = hex_decode(&pr.nonce_base_hex);
+        if let Some(nb_hex) = arg_flag(args, "nonce-base-hex") {
+            if hex_decode(&nb_hex) != nb {
+                eprintln!("--resume: --nonce-base-hex differs from progress file");
+                std::process::exit(2);
+            }
+        }
+        nonce_base.copy_from_slice(&nb);
+    } else if let Some(nb_hex) = arg_flag(args, "nonce-base-hex") {
+        let nb = hex_decode(&nb_hex);
+        if nb.len() != 24 {
+            eprintln!(
+                "error: --nonce-base-hex must be 24 bytes (48 hex), got {}",
+                nb.len()
+            );
+            std::process::exit(2);
+        }
+        log_warn!("--nonce-base-hex: never reuse the same base with the same key and salt");
+        nonce_base.copy_from_slice(&nb);
+    } else {
+        OsRng.fill_bytes(&mut nonce_base);
+    }
+
+    // длину шарда берём у rs_encode, а не вычисляем сами — unpack читает её из манифеста
+    let mut shard_len: Option<usize> = resume.as_ref().and_then(|pr| pr.shard_len);
+    let start_chunk = resume.as_ref().map(|pr| pr.chunks_done).unwrap_or(0);
+
+    // Подымаем писатели шард-файлов: с нуля (truncate) или с обрезкой до записанного прогресса
+    let mut shard_files = Vec::with_capacity(total_shards);
+    for i in 0..total_shards {
+        let path = out_dir.join(format!("shard_{:03}.bin", i));
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(resume.is_none())
+            .open(path)
+            .expect("open shard file");
+        if resume.is_some() {
+            // хвост после последнего зафиксированного чанка мог быть дописан частично
+            let keep = (start_chunk * shard_len.unwrap_or(0)) as u64;
+            file.set_len(keep).expect("truncate shard file");
+            file.seek(SeekFrom::End(0)).expect("seek shard file");
+        }
+        shard_files.push(file);
+    }
+    if start_chunk > 0 {
+        log_info!("resuming at chunk {start_chunk}/{chunks}");
+    }
+
+    let write_progress = |chunks_done: usize, shard_len: Option<usize>| {
+        let pr = StreamProgress {
+            version: 1,
+            size_bytes: file_size,
+            data_shards,
+            parity_shards,
+            chunk_size,
+            aad: aad.clone(),
+            nonce_base_hex: hex_encode(&nonce_base),
+            shard_len,
+            chunks_done,
+            kdf: kdf.clone(),
+            salt_hex: Some(hex_encode(&salt)),
+            key_commit_hex: Some(key_commit_hex.clone()),
+            input_prefix_sha256: Some(input_prefix_hex.clone()),
+        };
+        write_atomic(&progress_path, &serde_json::to_vec_pretty(&pr).unwrap());
+    };
+
+    // Буферы
+    let mut plain_chunk = vec![0u8; chunk_size];
+    // листья дерева по чанкам (chunk_root); при --resume уже записанные чанки
+    // перешифровываются (nonce детерминирован) только ради листьев — без RS и записи
+    let mut chunk_leaves: Vec<[u8; 32]> = Vec::with_capacity(chunks);
+
+    for idx in 0..chunks {
+        // читаем максимум chunk_size
+        let mut read_total = 0usize;
+        while read_total < chunk_size {
+            let n = f_in.read(&mut plain_chunk[read_total..]).expect("read");
+            if n == 0 {
+                break;
+            }
+            read_total += n;
+        }
+        if read_total < chunk_size {
+            // добиваем нулями (padding)
+            for b in &mut plain_chunk[read_total..] {
+                *b = 0;
+            }
+        }
+
+        // AEAD с детерминированным nonce
+        let nonce = derive_nonce_from_base(&nonce_base, idx as u64);
+        let ciphertext = ks
+            .seal_with_nonce(aad.as_bytes(), &nonce, &plain_chunk)
+            .expect("seal");
+        chunk_leaves.push(chunk_leaf(idx, &ciphertext));
+        if idx < start_chunk {
+            continue;
+        }
+
+        // RS кодирование для этого чанка
+        let shards = rs_encode(&ciphertext, data_shards, parity_shards).expect("rs");
+        let this_len = shards[0].len();
+        if shards.iter().any(|s| s.len() != this_len)
+            || *shard_len.get_or_insert(this_len) != this_len
+        {
+            eprintln!("rs_encode produced uneven shard lengths at chunk {idx}");
+            std::process::exit(2);
+        }
+        log_trace!(
+            "chunk {idx}/{chunks}: plain={read_total}B nonce_idx={idx} shards={}x{this_len}B",
+            shards.len()
+        );
+        // аппенд в shard_###.bin (троттлинг — по всей полосе сразу)
+        if let Some(t) = throttle.as_mut() {
+            t.consume(this_len * shards.len());
+        }
+        for (i, s) in shards.iter().enumerate() {
+            shard_files[i].write_all(s).expect("write shard");
+        }
+
+        if (idx + 1) % PROGRESS_EVERY == 0 {
+            // прогресс не должен опережать шарды: сначала fsync, потом запись прогресса
+            for f in shard_files.iter_mut() {
+                f.sync_all().expect("sync shard file");
+            }
+            write_progress(idx + 1, shard_len);
+        }
+    }
+
+    // сброс на диск (до манифеста — по той же причине)
+    for f in shard_files.iter_mut() {
+        f.sync_all().expect("sync shard file");
+    }
+
+    // посчитаем Merkle по итоговым файлам
+    let mut leaves = Vec::<[u8; 32]>::with_capacity(total_shards);
+    for i in 0..total_shards {
+        let p = out_dir.join(format!("shard_{:03}.bin", i));
+        let bytes = read_all(&p);
+        leaves.push(leaf_hash(&bytes));
+    }
+    let shard_leaves: Vec<String> = leaves.iter().map(|h| hex_encode(h)).collect();
+    // корень по чанкам — последним листом коммита, чтобы scid связывал и его
+    let chunk_root = chunk_tree_levels(chunk_leaves)
+        .last()
+        .map(|top| top[0])
+        .expect("at least one chunk");
+    leaves.push(chunk_root_commit_leaf(&chunk_root));
+    let root = merkle_root(leaves).expect("merkle_root");
+
+    // commit + scid (chunk_size = размер plaintext-чанка)
+    let commit = SeriesCommit {
+        version: 1,
+        size_bytes: file_size,
+        chunk_size,
+        erasure_data: data_shards,
+        erasure_parity: parity_shards,
+        aead_alg: "XChaCha20-Poly1305".to_string(),
+        merkle_root: root,
+    };
+    let scid = commit.scid();
+
+    // манифест стрима
+    let sm = StreamManifest {
+        version: 1,
+        scid,
+        commit,
+        aad,
+        file_name: input
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("input.bin")
+            .to_string(),
+        size_bytes: file_size,
+        data_shards,
+        parity_shards,
+        chunk_size,
+        ct_len_per_chunk,
+        chunks,
+        nonce_base_hex: hex_encode(&nonce_base),
+        shard_len,
+        kdf,
+        chunk_root_hex: Some(hex_encode(&chunk_root)),
+        shard_leaves: Some(shard_leaves),
+    };
+    let sm_json = serde_json::to_vec_pretty(&sm).unwrap();
+    write_atomic(&out_dir.join("manifest_stream.json"), &sm_json);
+    // манифест записан — прогресс больше не нужен
+    fs::remove_file(&progress_path).ok();
+
+    println!(
+        "Stream packed → {} ({} chunks, chunk={}B)",
+        out_dir.display(),
+        chunks,
+        chunk_size
+    );
+}
+
+fn unpack_stream_cmd(args: &[String]) {
+    if args.len() < 3 {
+        usage();
+    }
+    let in_dir = PathBuf::from(&args[0]);
+    let output = PathBuf::from(&args[1]);
+
+    let ikm = read_ikm(args);
+    let salt = hex_decode(&require_flag(args, "salt-hex"));
+
+    // читаем stream-манифест
+    let sm_bytes = read_all(&in_dir.join("manifest_stream.json"));
+    let sm: StreamManifest = serde_json::from_slice(&sm_bytes).expect("manifest_stream parse");
+    check_scid("manifest_stream", &sm.commit, &sm.scid);
+    // KDF — как при упаковке (из манифеста)
+    let ks = derive_key_schedule(&ikm, &salt, sm.kdf.as_ref());
+
+    let total_shards = sm.data_shards + sm.parity_shards;
+    let shard_size = sm.stripe_shard_len();
+    FsShardStore::new(&in_dir, total_shards, None).check_strays();
+
+    // Открываем доступные шард-файлы как читатели
+    let mut shard_readers: Vec<Option<BufReader<fs::File>>> = Vec::with_capacity(total_shards);
+    for i in 0..total_shards {
+        let p = in_dir.join(format!("shard_{:03}.bin", i));
+        if let Ok(f) = fs::File::open(&p) {
+            shard_readers.push(Some(BufReader::new(f)));
+        } else {
+            shard_readers.push(None);
+        }
+    }
+
+    let mut out = fs::File::create(&output).expect("create output");
+
+    for idx in 0..sm.chunks {
+        // читаем очередную «полосу» по shard_size из каждого шард-файла
+        let mut stripe: Vec<Option<Vec<u8>>> = Vec::with_capacity(total_shards);
+        for r_opt in shard_readers.iter_mut() {
+            if let Some(r) = r_opt {
+                let mut buf = vec![0u8; shard_size];
+                let mut read_total = 0usize;
+                while read_total < shard_size {
+                    let n = r.read(&mut buf[read_total..]).unwrap_or(0);
+                    if n == 0 {
+                        break;
+                    }
+                    read_total += n;
+                }
+                if read_total == shard_size {
+                    stripe.push(Some(buf));
+                } else if read_total == 0 {
+                    // файл закончился — считаем шард потерян
+                    stripe.push(None);
+                } else {
+                    // укороченный фрагмент — тоже считаем потерянным
+                    stripe.push(None);
+                }
+            } else {
+                stripe.push(None);
+            }
+        }
+
+        // RS реконструкция полосы
+        let joined =
+            rs_reconstruct(stripe, sm.data_shards, sm.parity_shards).expect("rs_reconstruct");
+        check_ct_len("manifest_stream", sm.ct_len_per_chunk, joined.len());
+        let ct_chunk = &joined[..sm.ct_len_per_chunk];
+
+        // AEAD open с детерминированным nonce для idx
+        let mut nonce_base = [0u8; 24];
+        nonce_base.copy_from_slice(&hex_decode(&sm.nonce_base_hex));
+        let nonce = derive_nonce_from_base(&nonce_base, idx as u64);
+
+        let pt = ks.open(sm.aad.as_bytes(), &nonce, ct_chunk).expect("open");
+        out.write_all(&pt).expect("write pt");
+    }
+
+    // обрезаем до исходного размера
+    out.flush().ok();
+    out.set_len(sm.size_bytes as u64).ok();
+
+    println!("Stream unpacked → {}", output.display());
+}
+
+/// Диапазон [offset, offset+len) открытого текста: расшифровываются только покрывающие его чанки.
+/// Чанки фиксированного размера chunk_size, так что индекс — offset / chunk_size
+fn extract_stream_cmd(args: &[String]) {
+    if args.len() < 2 {
+        usage();
+    }
+    let in_dir = PathBuf::from(&args[0]);
+    let output = PathBuf::from(&args[1]);
+    let offset: usize = require_flag(args, "offset")
+        .parse()
+        .expect("invalid --offset (bytes)");
+    let len: usize = require_flag(args, "len")
+        .parse()
+        .expect("invalid --len (bytes)");
+
+    let ikm = read_ikm(args);
+    let salt = hex_decode(&require_flag(args, "salt-hex"));
+
+    let sm: StreamManifest =
+        serde_json::from_slice(&read_all(&in_dir.join("manifest_stream.json")))
+            .expect("manifest_stream parse");
+    check_scid("manifest_stream", &sm.commit, &sm.scid);
+    let ks = derive_key_schedule(&ikm, &salt, sm.kdf.as_ref());
+
+    // за концом файла — обрезаем до size_bytes
+    let start = offset.min(sm.size_bytes);
+    let end = offset.saturating_add(len).min(sm.size_bytes);
+    if end - start < len {
+        log_warn!(
+            "range clamped to [{start}, {end}) (size_bytes={})",
+            sm.size_bytes
+        );
+    }
+
+    let mut nonce_base = [0u8; 24];
+    nonce_base.copy_from_slice(&hex_decode(&sm.nonce_base_hex));
+    let mut files = open_stream_shards(&in_dir, &sm);
+    let mut out = fs::File::create(&output).expect("create output");
+    let cs = sm.chunk_size;
+    let chunk_range = if start < end {
+        start / cs..(end - 1) / cs + 1
+    } else {
+        0..0
+    };
+    for idx in chunk_range.clone() {
+        let Some(ct) = read_stream_chunk_ct(&mut files, &sm, idx) else {
+            eprintln!("error: chunk {idx}: not enough readable shards to reconstruct");
+            std::process::exit(2);
+        };
+        let nonce = derive_nonce_from_base(&nonce_base, idx as u64);
+        let Ok(pt) = ks.open(sm.aad.as_bytes(), &nonce, &ct) else {
+            eprintln!("error: chunk {idx}: AEAD open failed (wrong key/salt?)");
+            std::process::exit(2);
+        };
+        let lo = start.max(idx * cs) - idx * cs;
+        let hi = end.min((idx + 1) * cs) - idx * cs;
+        out.write_all(&pt[lo..hi]).expect("write pt");
+        log_trace!("chunk {idx}: bytes {lo}..{hi}");
+    }
+
+    println!(
+        "Extracted [{start}, {end}) from {} chunk(s) → {}",
+        chunk_range.len(),
+        output.display()
+    );
+}
+
+//==================== Stream: коммитмент по чанкам ====================//
+// Отдельное sha256-дерево над шифртекстами чанков — не merkle_root из s3p-core: формат задаёт CLI,
+// поэтому и доказательства включения строятся здесь. Нечётный последний узел поднимается без хэширования.
+
+fn chunk_leaf(idx: usize, ct_chunk: &[u8]) -> [u8; 32] {
+    let mut h = Sha256::new();
+    h.update(b"s3p-chunk-leaf-v1");
+    h.update((idx as u64).to_le_bytes());
+    h.update(ct_chunk);
+    h.finalize().into()
+}
+
+fn chunk_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut h = Sha256::new();
+    h.update(b"s3p-chunk-node-v1");
+    h.update(left);
+    h.update(right);
+    h.finalize().into()
+}
+
+/// Лист, которым chunk_root входит в commit.merkle_root потока (после листьев шардов):
+/// так корень по чанкам связан scid наравне с шардами, а не лежит в манифесте сам по себе
+fn chunk_root_commit_leaf(chunk_root: &[u8]) -> [u8; 32] {
+    let mut h = Sha256::new();
+    h.update(b"s3p-chunk-root-v1");
+    h.update(chunk_root);
+    h.finalize().into()
+}
+
+/// Все уровни дерева: [0] — листья, последний — [root]. Пусто, если листьев нет.
+fn chunk_tree_levels(leaves: Vec<[u8; 32]>) -> Vec<Vec<[u8; 32]>> {
+    if leaves.is_empty() {
+        return Vec::new();
+    }
+    let mut levels = vec![leaves];
+    while levels.last().map_or(0, |l| l.len()) > 1 {
+        let next = levels
+            .last()
+            .unwrap()
+            .chunks(2)
+            .map(|pair| match pair {
+                [l, r] => chunk_node(l, r),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+        levels.push(next);
+    }
+    levels
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChunkProofStep {
+    side: String, // "L" | "R" — с какой стороны сосед
+    hash_hex: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChunkProof {
+    version: u8,
+    scid: String,
+    index: usize,
+    chunks: usize,
+    leaf_hex: String,
+    path: Vec<ChunkProofStep>,
+}
+
+fn chunk_proof_path(levels: &[Vec<[u8; 32]>], mut index: usize) -> Vec<ChunkProofStep> {
+    let mut path = Vec::new();
+    for level in &levels[..levels.len().saturating_sub(1)] {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            path.push(ChunkProofStep {
+                side: if sibling < index { "L" } else { "R" }.to_string(),
+                hash_hex: hex_encode(&level[sibling]),
+            });
+        }
+        index /= 2;
+    }
+    path
+}
+
+fn chunk_proof_root(leaf: [u8; 32], path: &[ChunkProofStep]) -> Option<[u8; 32]> {
+    let mut acc = leaf;
+    for step in path {
+        let sib: [u8; 32] = hex::decode(&step.hash_hex).ok()?.try_into().ok()?;
+        acc = match step.side.as_str() {
+            "L" => chunk_node(&sib, &acc),
+            "R" => chunk_node(&acc, &sib),
+            _ => return None,
+        };
+    }
+    Some(acc)
+}
+
+/// Шифртекст чанка idx: полоса idx из shard-файлов (seek) → rs_reconstruct. Ключ не нужен.
+fn read_stream_chunk_ct(
+    files: &mut [Option<fs::File>],
+    sm: &StreamManifest,
+    idx: usize,
+) -> Option<Vec<u8>> {
+    let shard_size = sm.stripe_shard_len();
+    let stripe: Vec<Option<Vec<u8>>> = files
+        .iter_mut()
+        .map(|f| {
+            let f = f.as_mut()?;
+            f.seek(SeekFrom::Start((idx * shard_size) as u64)).ok()?;
+            let mut buf = vec![0u8; shard_size];
+            f.read_exact(&mut buf).ok()?;
+            Some(buf)
+        })
+        .collect();
+    let mut joined = rs_reconstruct(stripe, sm.data_shards, sm.parity_shards).ok()?;
+    joined.truncate(sm.ct_len_per_chunk);
+    Some(joined)
+}
+
+fn open_stream_shards(in_dir: &Path, sm: &StreamManifest) -> Vec<Option<fs::File>> {
+    (0..sm.data_shards + sm.parity_shards)
+        .map(|i| fs::File::open(in_dir.join(format!("shard_{:03}.bin", i))).ok())
+        .collect()
+}
 
+/// Записанные в манифесте shard_leaves (ровно `total`, hex) вместе с `extra` дают
+/// commit.merkle_root — только тогда им можно верить без чтения шардов
+fn shard_leaves_bind_commit(
+    leaves: &[String],
+    total: usize,
+    extra: &[[u8; 32]],
+    commit: &SeriesCommit,
+) -> bool {
+    if leaves.len() != total {
+        return false;
+    }
+    let decoded: Option<Vec<[u8; 32]>> = leaves
+        .iter()
+        .map(|h| hex::decode(h).ok()?.try_into().ok())
+        .collect();
+    decoded.is_some_and(|mut leaves| {
+        leaves.extend_from_slice(extra);
+        merkle_root(leaves).is_ok_and(|r| ct_eq(&r, &commit.merkle_root))
+    })
+}
+
+/// chunk_root_hex, но только связанный scid: листья шардов из манифеста + лист chunk_root
+/// дают commit.merkle_root, а commit — scid. Шарды целиком не читаются
+fn load_chunk_root(sm: &StreamManifest) -> [u8; 32] {
+    let root: [u8; 32] = match sm.chunk_root_hex.as_deref().map(hex_decode) {
+        Some(b) if b.len() == 32 => b.try_into().unwrap(),
+        _ => {
+            eprintln!("error: manifest_stream.json has no chunk_root_hex (repack with a newer pack-stream)");
+            std::process::exit(2);
+        }
+    };
+    check_scid("manifest_stream", &sm.commit, &sm.scid);
+    let bound = sm.shard_leaves.as_ref().is_some_and(|l| {
+        shard_leaves_bind_commit(
+            l,
+            sm.data_shards + sm.parity_shards,
+            &[chunk_root_commit_leaf(&root)],
+            &sm.commit,
+        )
+    });
+    if !bound {
+        eprintln!("error: chunk_root_hex is not bound by scid (shard_leaves + chunk_root do not give commit.merkle_root)");
+        std::process::exit(2);
+    }
+    root
+}
+
+fn chunk_proof_cmd(args: &[String]) {
+    if args.is_empty() {
+        usage();
+    }
+    let in_dir = PathBuf::from(&args[0]);
+    let index: usize = require_flag(args, "index")
+        .parse()
+        .expect("invalid --index");
+
+    let sm_bytes = read_all(&in_dir.join("manifest_stream.json"));
+    let sm: StreamManifest = serde_json::from_slice(&sm_bytes).expect("manifest_stream parse");
+    let root = load_chunk_root(&sm);
+    if index >= sm.chunks {
+        eprintln!("error: --index={index} out of range ({} chunks)", sm.chunks);
+        std::process::exit(2);
+    }
+
+    // листья по всем чанкам — заодно сверяем, что шарды на диске соответствуют манифесту
+    let mut files = open_stream_shards(&in_dir, &sm);
+    let mut leaves = Vec::with_capacity(sm.chunks);
     for idx in 0..sm.chunks {
-        // читаем очередную «полосу» по shard_size из каждого шард-файла
-        let mut stripe: Vec<Option<Vec<u8>>> = Vec::with_capacity(total_shards);
-        for r_opt in shard_readers.iter_mut() {
-            if let Some(r) = r_opt {
-                let mut buf = vec![0u8; shard_size];
-                let mut read_total = 0usize;
-                while read_total < shard_size {
-                    let n = r.read(&mut buf[read_total..]).unwrap_or(0);
-                    if n == 0 {
-                        break;
-                    }
-                    read_total += n;
-                }
-                if read_total == shard_size {
-                    stripe.push(Some(buf));
-                } else if read_total == 0 {
-                    // файл закончился — считаем шард потерян
-                    stripe.push(None);
+        let Some(ct) = read_stream_chunk_ct(&mut files, &sm, idx) else {
+            eprintln!("error: chunk {idx} is not recoverable from the shards on disk");
+            std::process::exit(2);
+        };
+        leaves.push(chunk_leaf(idx, &ct));
+    }
+    let levels = chunk_tree_levels(leaves);
+    if !levels.last().is_some_and(|top| ct_eq(&top[0], &root)) {
+        eprintln!("error: shards on disk do not match chunk_root_hex");
+        std::process::exit(2);
+    }
+
+    let proof = ChunkProof {
+        version: 1,
+        scid: sm.scid.clone(),
+        index,
+        chunks: sm.chunks,
+        leaf_hex: hex_encode(&levels[0][index]),
+        path: chunk_proof_path(&levels, index),
+    };
+    let proof_json = serde_json::to_vec_pretty(&proof).unwrap();
+    match arg_flag(args, "out") {
+        Some(out) => {
+            write_atomic(Path::new(&out), &proof_json);
+            println!("chunk proof {index} → {out}");
+        }
+        None => println!("{}", String::from_utf8(proof_json).unwrap()),
+    }
+}
+
+/// Проверка одного чанка: читается только его полоса + доказательство
+fn chunk_verify_cmd(args: &[String]) {
+    if args.is_empty() {
+        usage();
+    }
+    let in_dir = PathBuf::from(&args[0]);
+    let proof: ChunkProof =
+        serde_json::from_slice(&read_all(Path::new(&require_flag(args, "proof"))))
+            .expect("proof parse");
+
+    let sm_bytes = read_all(&in_dir.join("manifest_stream.json"));
+    let sm: StreamManifest = serde_json::from_slice(&sm_bytes).expect("manifest_stream parse");
+    let root = load_chunk_root(&sm);
+    if proof.scid != sm.scid || proof.chunks != sm.chunks || proof.index >= sm.chunks {
+        eprintln!("error: proof does not belong to this stream (scid/chunks/index)");
+        std::process::exit(2);
+    }
+
+    let mut files = open_stream_shards(&in_dir, &sm);
+    let Some(ct) = read_stream_chunk_ct(&mut files, &sm, proof.index) else {
+        eprintln!(
+            "error: chunk {} is not recoverable from the shards on disk",
+            proof.index
+        );
+        std::process::exit(2);
+    };
+    let leaf = chunk_leaf(proof.index, &ct);
+    if !ct_eq(hex_encode(&leaf).as_bytes(), proof.leaf_hex.as_bytes()) {
+        eprintln!(
+            "chunk {}: on-disk chunk does not match proof leaf",
+            proof.index
+        );
+        std::process::exit(2);
+    }
+    if !chunk_proof_root(leaf, &proof.path).is_some_and(|r| ct_eq(&r, &root)) {
+        eprintln!(
+            "chunk {}: proof does not lead to chunk_root_hex",
+            proof.index
+        );
+        std::process::exit(2);
+    }
+    println!("chunk-verify: OK (chunk {} of {})", proof.index, sm.chunks);
+}
+
+//==================== Проверки паков ====================//
+
+// Итог verify-pack / verify-pack-stream (для --json)
+#[derive(Serialize)]
+struct VerifyReport {
+    command: &'static str,
+    status: &'static str, // "ok" | "fail"
+    scid: String,
+    missing: Vec<usize>,
+    mismatches: Vec<&'static str>, // "merkle_root" и/или "scid"
+    merkle_root_hex: String,       // из манифеста
+    computed_root_hex: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    missing_by_provider: BTreeMap<String, Vec<usize>>, // только RS-манифест с placement
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bad_sizes: Vec<ShardSizeMismatch>, // только stream: файл шарда ≠ chunks × shard_len
+}
+
+#[derive(Serialize)]
+struct ShardSizeMismatch {
+    index: usize,
+    expected: u64,
+    actual: u64,
+}
+
+/// `read_shard(i)` — тело шарда i (None — нет на диске); шарды читаются по одному
+fn verify_commit(
+    command: &'static str,
+    total: usize,
+    read_shard: impl Fn(usize) -> Option<Vec<u8>>,
+    extra_leaves: &[[u8; 32]],
+    commit: &SeriesCommit,
+    scid: &str,
+) -> VerifyReport {
+    // требуем наличие всех шардов
+    let mut missing = Vec::new();
+    let mut leaves = Vec::<[u8; 32]>::with_capacity(total);
+    for i in 0..total {
+        match read_shard(i) {
+            Some(bytes) => leaves.push(leaf_hash(&bytes)),
+            None => missing.push(i),
+        }
+    }
+
+    let mut mismatches = Vec::new();
+    let mut computed_root_hex = None;
+    if missing.is_empty() {
+        // сверяем Merkle root (у потока — ещё и лист chunk_root)
+        leaves.extend_from_slice(extra_leaves);
+        let root = merkle_root(leaves).expect("merkle_root");
+        if !ct_eq(&root, &commit.merkle_root) {
+            mismatches.push("merkle_root");
+        }
+        computed_root_hex = Some(hex_encode(&root));
+    }
+    // сверяем SCID
+    if commit.scid() != scid {
+        mismatches.push("scid");
+    }
+
+    VerifyReport {
+        command,
+        status: if missing.is_empty() && mismatches.is_empty() {
+            "ok"
+        } else {
+            "fail"
+        },
+        scid: scid.to_string(),
+        missing,
+        mismatches,
+        merkle_root_hex: hex_encode(&commit.merkle_root),
+        computed_root_hex,
+        missing_by_provider: BTreeMap::new(),
+        bad_sizes: Vec::new(),
+    }
+}
+
+fn emit_verify_report(report: &VerifyReport, manifest_name: &str, json: bool) {
+    if json {
+        println!("{}", serde_json::to_string(report).expect("report json"));
+    } else {
+        for i in &report.missing {
+            log_error!("missing shard_{:03}.bin", i);
+        }
+        log_missing_by_provider(&report.missing_by_provider);
+        for b in &report.bad_sizes {
+            log_error!(
+                "shard_{:03}.bin is {} bytes, expected {}",
+                b.index,
+                b.actual,
+                b.expected
+            );
+        }
+        for m in &report.mismatches {
+            log_error!("{manifest_name} {m} mismatch");
+        }
+        if report.status == "ok" {
+            println!("{}: OK (merkle_root & scid match)", report.command);
+        }
+    }
+    if report.status != "ok" {
+        std::process::exit(2);
+    }
+}
+
+/// Длина шарда по большинству присутствующих и список (индекс, длина) отклонившихся от неё.
+/// В RS-манифесте длина шарда не хранится, поэтому эталон — самая частая длина.
+fn present_shard_len_mismatches(shards: &[Option<Vec<u8>>]) -> (usize, Vec<(usize, usize)>) {
+    let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+    for s in shards.iter().flatten() {
+        *counts.entry(s.len()).or_default() += 1;
+    }
+    let expected = counts
+        .iter()
+        .max_by_key(|(_, &n)| n)
+        .map_or(0, |(&len, _)| len);
+    let bad = shards
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| {
+            s.as_ref()
+                .filter(|s| s.len() != expected)
+                .map(|s| (i, s.len()))
+        })
+        .collect();
+    (expected, bad)
+}
+
+// Отчёт verify-pack --check-recoverable
+#[derive(Serialize)]
+struct RecoverabilityReport {
+    command: &'static str,
+    status: &'static str, // "recoverable" | "unrecoverable"
+    scid: String,
+    data_shards: usize,
+    parity_shards: usize,
+    present: Vec<usize>,
+    missing: Vec<usize>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    missing_by_provider: BTreeMap<String, Vec<usize>>,
+    trial: &'static str, // "ok" | "merkle_root_mismatch" | "shard_len_mismatch" | "rs_failed" | "skipped"
+}
+
+/// Хватит ли имеющихся шардов на восстановление: счёт + пробный rs_reconstruct без записи.
+/// Восстановленный шифртекст заново кодируется в RS, и его Merkle root сверяется с манифестом.
+fn check_recoverable(in_dir: &Path, mf: &Manifest) -> RecoverabilityReport {
+    let mut present = Vec::new();
+    let mut missing = Vec::new();
+    let mut shards_opt: Vec<Option<Vec<u8>>> = Vec::new();
+    let store = mf.shard_store(in_dir);
+    for i in 0..mf.data_shards + mf.parity_shards {
+        let shard = mf.read_shard(&store, i);
+        if shard.is_some() {
+            present.push(i);
+        } else {
+            missing.push(i);
+        }
+        shards_opt.push(shard);
+    }
+
+    let trial = if present.len() < mf.data_shards {
+        "skipped"
+    } else if !present_shard_len_mismatches(&shards_opt).1.is_empty() {
+        "shard_len_mismatch"
+    } else {
+        match rs_reconstruct(shards_opt, mf.data_shards, mf.parity_shards) {
+            Ok(mut ct) => {
+                ct.truncate(mf.ct_len);
+                let root = rs_encode(&ct, mf.data_shards, mf.parity_shards)
+                    .ok()
+                    .and_then(|shards| {
+                        merkle_root(shards.iter().map(|s| leaf_hash(s)).collect()).ok()
+                    });
+                if root.is_some_and(|r| ct_eq(&r, &mf.commit.merkle_root)) {
+                    "ok"
                 } else {
-                    // укороченный фрагмент — тоже считаем потерянным
-                    stripe.push(None);
+                    "merkle_root_mismatch"
                 }
-            } else {
-                stripe.push(None);
             }
+            Err(_) => "rs_failed",
         }
+    };
 
-        // RS реконструкция полосы
-        let joined =
-            rs_reconstruct(stripe, sm.data_shards, sm.parity_shards).expect("rs_reconstruct");
-        let ct_chunk = &joined[..sm.ct_len_per_chunk];
+    RecoverabilityReport {
+        command: "verify-pack",
+        status: if trial == "ok" {
+            "recoverable"
+        } else {
+            "unrecoverable"
+        },
+        scid: mf.scid.clone(),
+        data_shards: mf.data_shards,
+        parity_shards: mf.parity_shards,
+        present,
+        missing_by_provider: mf.missing_by_provider(&missing),
+        missing,
+        trial,
+    }
+}
 
-        // AEAD open с детерминированным nonce для idx
-        let mut nonce_base = [0u8; 24];
-        nonce_base.copy_from_slice(&hex_decode(&sm.nonce_base_hex));
-        let nonce = derive_nonce_from_base(&nonce_base, idx as u64);
+fn verify_pack_cmd(args: &[String]) {
+    if args.is_empty() {
+        usage();
+    }
+    let in_dir = PathBuf::from(&args[0]);
 
-        let pt = ks.open(sm.aad.as_bytes(), &nonce, ct_chunk).expect("open");
-        out.write_all(&pt).expect("write pt");
+    // манифест
+    let mf_bytes = read_all(&in_dir.join("manifest.json"));
+    let mf: Manifest = serde_json::from_slice(&mf_bytes).expect("manifest parse");
+    mf.shard_store(&in_dir).check_strays();
+
+    if has_flag(args, "check-recoverable") {
+        let report = check_recoverable(&in_dir, &mf);
+        if has_flag(args, "json") {
+            println!("{}", serde_json::to_string(&report).expect("report json"));
+        } else {
+            for i in &report.missing {
+                log_warn!("missing shard_{:03}.bin", i);
+            }
+            log_missing_by_provider(&report.missing_by_provider);
+            println!(
+                "verify-pack: {} ({}/{} shards present, need {}; trial reconstruct: {})",
+                report.status.to_uppercase(),
+                report.present.len(),
+                mf.data_shards + mf.parity_shards,
+                mf.data_shards,
+                report.trial
+            );
+        }
+        if report.status != "recoverable" {
+            std::process::exit(2);
+        }
+        return;
+    }
+
+    let store = mf.shard_store(&in_dir);
+    let mut report = verify_commit(
+        "verify-pack",
+        mf.data_shards + mf.parity_shards,
+        |i| mf.read_shard(&store, i),
+        &[],
+        &mf.commit,
+        &mf.scid,
+    );
+    report.missing_by_provider = mf.missing_by_provider(&report.missing);
+    emit_verify_report(&report, "manifest", has_flag(args, "json"));
+}
+
+fn verify_pack_stream_cmd(args: &[String]) {
+    if args.is_empty() {
+        usage();
+    }
+    let in_dir = PathBuf::from(&args[0]);
+
+    // читаем stream-манифест
+    let sm_bytes = read_all(&in_dir.join("manifest_stream.json"));
+    let sm: StreamManifest = serde_json::from_slice(&sm_bytes).expect("manifest_stream parse");
+
+    let store = FsShardStore::new(&in_dir, sm.data_shards + sm.parity_shards, None);
+    store.check_strays();
+    let mut report = verify_commit(
+        "verify-pack-stream",
+        sm.data_shards + sm.parity_shards,
+        |i| store.get(i),
+        &sm.commit_extra_leaves(),
+        &sm.commit,
+        &sm.scid,
+    );
+    // каждая полоса дополнена до shard_len: обрезанный хвост файла иначе всплывает только на unpack
+    let expected = (sm.chunks * sm.stripe_shard_len()) as u64;
+    report.bad_sizes = store
+        .list()
+        .into_iter()
+        .filter_map(|i| {
+            let actual = fs::metadata(in_dir.join(format!("shard_{:03}.bin", i)))
+                .ok()?
+                .len();
+            (actual != expected).then_some(ShardSizeMismatch {
+                index: i,
+                expected,
+                actual,
+            })
+        })
+        .collect();
+    if !report.bad_sizes.is_empty() {
+        report.mismatches.push("shard_size");
+        report.status = "fail";
+    }
+    emit_verify_report(&report, "manifest_stream", has_flag(args, "json"));
+}
+
+//==================== Scrub: порча отдельных шардов ====================//
+
+// Отчёт scrub (для --json): в отличие от verify-pack — поимённо все плохие шарды
+#[derive(Serialize)]
+struct ScrubReport {
+    command: &'static str,
+    manifest: &'static str,
+    status: &'static str, // "clean" | "damaged"
+    scid: String,
+    checked: usize,
+    corrupt: Vec<ScrubBad>,
+    missing: Vec<usize>,
+    unchecked: Vec<usize>, // нечем проверить по отдельности (старый манифест без shard_leaves)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root_ok: Option<bool>, // сверка общего корня — только если есть unchecked и нет пропусков
+}
+
+#[derive(Serialize)]
+struct ScrubBad {
+    index: usize,
+    reason: &'static str, // "trailer" | "digest" | "leaf_hash"
+}
+
+/// Проверка одного shard-файла по всему, что есть в манифесте: Ok((тело, было ли с чем сверить)),
+/// Err — что именно не сошлось
+fn scrub_shard(
+    bytes: Vec<u8>,
+    trailer: bool,
+    digest: Option<&str>,
+    leaf: Option<&str>,
+) -> Result<(Vec<u8>, bool), &'static str> {
+    let body = if trailer {
+        strip_shard_trailer(bytes).ok_or("trailer")?
+    } else {
+        bytes
+    };
+    if digest.is_some_and(|d| !ct_eq(blake3::hash(&body).to_hex().as_bytes(), d.as_bytes())) {
+        return Err("digest");
+    }
+    if leaf.is_some_and(|l| !ct_eq(hex_encode(&leaf_hash(&body)).as_bytes(), l.as_bytes())) {
+        return Err("leaf_hash");
+    }
+    let checked = trailer || digest.is_some() || leaf.is_some();
+    Ok((body, checked))
+}
+
+fn scrub_cmd(args: &[String]) {
+    if args.is_empty() {
+        usage();
+    }
+    let in_dir = PathBuf::from(&args[0]);
+    let json = has_flag(args, "json");
+
+    // RS-манифест или stream — что есть в каталоге
+    struct Target {
+        manifest: &'static str,
+        scid: String,
+        commit: SeriesCommit,
+        store: FsShardStore,
+        trailer: bool,
+        digests: Option<Vec<String>>,
+        leaves: Option<Vec<String>>,
+        extra_leaves: Vec<[u8; 32]>, // сверх шардов в commit.merkle_root (chunk_root потока)
+    }
+    let t = if in_dir.join("manifest.json").exists() {
+        let mf: Manifest = serde_json::from_slice(&read_all(&in_dir.join("manifest.json")))
+            .expect("manifest parse");
+        Target {
+            manifest: "manifest.json",
+            store: mf.shard_store(&in_dir),
+            scid: mf.scid,
+            commit: mf.commit,
+            trailer: mf.shard_trailer,
+            digests: mf.shard_digests,
+            leaves: mf.shard_leaves,
+            extra_leaves: Vec::new(),
+        }
+    } else {
+        let sm: StreamManifest =
+            serde_json::from_slice(&read_all(&in_dir.join("manifest_stream.json")))
+                .expect("manifest_stream parse");
+        Target {
+            manifest: "manifest_stream.json",
+            store: FsShardStore::new(&in_dir, sm.data_shards + sm.parity_shards, None),
+            extra_leaves: sm.commit_extra_leaves(),
+            scid: sm.scid,
+            commit: sm.commit,
+            trailer: false,
+            digests: None,
+            leaves: sm.shard_leaves,
+        }
+    };
+
+    // списки из манифеста индексируются номером шарда: длина — ровно data+parity, а листья
+    // должны давать commit.merkle_root (и scid), иначе «битый шард» назначался бы по подделке
+    check_scid(t.manifest, &t.commit, &t.scid);
+    let total = t.store.total;
+    if total != t.commit.erasure_data + t.commit.erasure_parity {
+        eprintln!(
+            "error: {}: data+parity={total} does not match commit ({}+{})",
+            t.manifest, t.commit.erasure_data, t.commit.erasure_parity
+        );
+        std::process::exit(2);
+    }
+    for (name, list) in [("shard_digests", &t.digests), ("shard_leaves", &t.leaves)] {
+        if let Some(l) = list.as_ref().filter(|l| l.len() != total) {
+            eprintln!(
+                "error: {}: {name} has {} entries, expected {total} (data+parity)",
+                t.manifest,
+                l.len()
+            );
+            std::process::exit(2);
+        }
+    }
+    if t.leaves
+        .as_ref()
+        .is_some_and(|l| !shard_leaves_bind_commit(l, total, &t.extra_leaves, &t.commit))
+    {
+        eprintln!(
+            "error: {}: shard_leaves do not give commit.merkle_root; refusing to judge shards by them",
+            t.manifest
+        );
+        std::process::exit(2);
+    }
+
+    let mut checked = 0usize;
+    let mut corrupt = Vec::new();
+    let mut unchecked = Vec::new();
+    let present = t.store.list();
+    let missing: Vec<usize> = (0..total)
+        .filter(|i| present.binary_search(i).is_err())
+        .collect();
+    let mut leaves = Vec::with_capacity(present.len());
+    for i in present {
+        let Some(bytes) = t.store.get(i) else {
+            continue;
+        };
+        let result = scrub_shard(
+            bytes,
+            t.trailer,
+            t.digests.as_ref().map(|d| d[i].as_str()),
+            t.leaves.as_ref().map(|l| l[i].as_str()),
+        );
+        match result {
+            Ok((body, true)) => {
+                checked += 1;
+                leaves.push(leaf_hash(&body));
+            }
+            Ok((body, false)) => {
+                unchecked.push(i);
+                leaves.push(leaf_hash(&body));
+            }
+            Err(reason) => corrupt.push(ScrubBad { index: i, reason }),
+        }
+    }
+    // по отдельности проверить нечем — хотя бы общий корень (порча есть, но где — неизвестно)
+    leaves.extend_from_slice(&t.extra_leaves);
+    let root_ok = (!unchecked.is_empty() && missing.is_empty() && corrupt.is_empty())
+        .then(|| merkle_root(leaves).is_ok_and(|r| ct_eq(&r, &t.commit.merkle_root)));
+
+    let report = ScrubReport {
+        command: "scrub",
+        manifest: t.manifest,
+        status: if corrupt.is_empty() && missing.is_empty() && root_ok != Some(false) {
+            "clean"
+        } else {
+            "damaged"
+        },
+        scid: t.scid,
+        checked,
+        corrupt,
+        missing,
+        unchecked,
+        root_ok,
+    };
+    if json {
+        println!("{}", serde_json::to_string(&report).expect("report json"));
+    } else {
+        for b in &report.corrupt {
+            log_error!("shard_{:03}: corrupt ({} mismatch)", b.index, b.reason);
+        }
+        for i in &report.missing {
+            log_error!("shard_{:03}: missing", i);
+        }
+        if !report.unchecked.is_empty() {
+            log_warn!(
+                "{} shard(s) have nothing to check against in {} (no shard_leaves)",
+                report.unchecked.len(),
+                report.manifest
+            );
+        }
+        if report.root_ok == Some(false) {
+            log_error!("merkle_root mismatch: some shard is corrupt, but it cannot be located");
+        }
+        println!(
+            "scrub: {} ({} checked, {} corrupt, {} missing)",
+            report.status.to_uppercase(),
+            report.checked,
+            report.corrupt.len(),
+            report.missing.len()
+        );
+    }
+    if report.status != "clean" {
+        std::process::exit(2);
+    }
+}
+
+//==================== PoD: подписать/проверить/агрегировать ====================//
+
+/// Текст ключа из файла/флага: hex или base64 (как пишет keygen --encoding) — определяем сами
+fn decode_key_text(s: &str) -> Vec<u8> {
+    let s = s.trim();
+    if let Ok(bytes) = hex::decode(s) {
+        return bytes;
+    }
+    general_purpose::STANDARD
+        .decode(s)
+        .or_else(|_| general_purpose::URL_SAFE_NO_PAD.decode(s))
+        .unwrap_or_default()
+}
+
+/// 32-байтный секрет Ed25519 из hex или base64; неверная длина/кодировка → exit 2
+fn parse_secret32_hex(hex_s: &str, flag: &str) -> [u8; 32] {
+    let bytes = decode_key_text(hex_s);
+    if bytes.len() != 32 {
+        eprintln!("--{flag} must be 32 bytes (64 hex chars or base64)");
+        std::process::exit(2);
+    }
+    let mut arr = [0u8; 32];
+    arr.copy_from_slice(&bytes);
+    arr
+}
+
+fn parse_sk_hex(sk_hex: &str) -> SigningKey {
+    SigningKey::from_bytes(&parse_secret32_hex(sk_hex, "sk-hex"))
+}
+
+/// Подписанты для pod-sign: один --sk-hex либо все *.hex/*.b64 в --sk-dir (по имени файла)
+fn load_signers(args: &[String]) -> Vec<SigningKey> {
+    if let Some(dir) = arg_flag(args, "sk-dir") {
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+            .expect("read --sk-dir")
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| matches!(p.extension().and_then(|x| x.to_str()), Some("hex" | "b64")))
+            .collect();
+        paths.sort();
+        if paths.is_empty() {
+            eprintln!("no *.hex/*.b64 secrets in --sk-dir={dir}");
+            std::process::exit(2);
+        }
+        return paths
+            .iter()
+            .map(|p| parse_sk_hex(&String::from_utf8_lossy(&read_all(p))))
+            .collect();
+    }
+    vec![parse_sk_hex(&require_flag(args, "sk-hex"))]
+}
+
+/// Короткая метка подписанта для имени файла: первые 8 байт pubkey в hex
+fn signer_tag(pubkey: &[u8; 32]) -> String {
+    hex_encode(&pubkey[..8])
+}
+
+// pod_scope.json — какие шарды подписывались последним pod-sign (нет файла — все)
+const POD_SCOPE_FILE: &str = "pod_scope.json";
+
+#[derive(Serialize, Deserialize)]
+struct PodScope {
+    data_only: bool,
+}
+
+/// Сколько первых индексов обязаны иметь PoD: erasure_data после `pod-sign --data-only`
+/// (паритет восстановим и не подтверждается), иначе все. Числа — из commit (связан scid);
+/// неподписанный pod_scope.json выбирает лишь режим, и меньше erasure_data знаменатель не станет
+fn pod_expected(in_dir: &Path, mf: &Manifest) -> usize {
+    let data_only = fs::read(in_dir.join(POD_SCOPE_FILE))
+        .ok()
+        .and_then(|b| serde_json::from_slice::<PodScope>(&b).ok())
+        .is_some_and(|s| s.data_only);
+    if data_only {
+        mf.commit.erasure_data
+    } else {
+        mf.commit.erasure_data + mf.commit.erasure_parity
     }
+}
 
-    // обрезаем до исходного размера
-    out.flush().ok();
-    out.set_len(sm.size_bytes as u64).ok();
+//— включение шарда в commit.merkle_root —//
+// s3p-core 0.1 отдаёт наружу только leaf_hash/merkle_root: внутренние узлы его дерева (хэш узла,
+// правило нечётного узла) не публичны. Поэтому доказательство — не путь, а все листья коммита
+// (data+parity ≤ 256 хэшей): проверка пересчитывает корень тем же merkle_root из s3p-core,
+// раскладка дерева в CLI не дублируется. Лист — leaf_hash тела шарда (без trailer), как в pack.
 
-    println!("Stream unpacked → {}", output.display());
+fn shard_proof_path(in_dir: &Path, index: usize) -> PathBuf {
+    in_dir.join(format!("pod_{index:03}.proof.json"))
 }
 
-//==================== Проверки паков ====================//
+#[derive(Serialize, Deserialize)]
+struct ShardProof {
+    version: u8,
+    scid: String,
+    index: usize,
+    leaves: Vec<String>, // leaf_hash всех шардов коммита по порядку (hex)
+}
 
-fn verify_pack_cmd(args: &[String]) {
-    if args.is_empty() {
-        usage();
+impl ShardProof {
+    /// Шард с листом `leaf` стоит под `index` в дереве, которое даёт commit.merkle_root
+    fn verify(
+        &self,
+        commit: &SeriesCommit,
+        scid: &str,
+        index: usize,
+        leaf: &[u8; 32],
+    ) -> Result<(), &'static str> {
+        if self.scid != scid || commit.scid() != scid {
+            return Err("proof scid mismatch");
+        }
+        if self.index != index {
+            return Err("proof index mismatch");
+        }
+        let total = commit.erasure_data + commit.erasure_parity;
+        if !shard_leaves_bind_commit(&self.leaves, total, &[], commit) {
+            return Err("proof root mismatch");
+        }
+        if !ct_eq(hex_encode(leaf).as_bytes(), self.leaves[index].as_bytes()) {
+            return Err("shard not in commit");
+        }
+        Ok(())
     }
-    let in_dir = PathBuf::from(&args[0]);
-
-    // манифест
-    let mf_bytes = read_all(&in_dir.join("manifest.json"));
-    let mf: Manifest = serde_json::from_slice(&mf_bytes).expect("manifest parse");
+}
 
-    // требуем наличие всех шардов
-    let total = mf.data_shards + mf.parity_shards;
-    let mut leaves = Vec::<[u8; 32]>::with_capacity(total);
+/// Листья commit.merkle_root пака: shard_leaves манифеста, если они дают корень, иначе — leaf_hash
+/// шардов с диска (нужны все и неиспорченные). Err — восстановить листья коммита не из чего
+fn commit_leaves(mf: &Manifest, store: &dyn ShardStore) -> Result<Vec<String>, String> {
+    let total = mf.commit.erasure_data + mf.commit.erasure_parity;
+    if let Some(l) = mf
+        .shard_leaves
+        .as_ref()
+        .filter(|l| shard_leaves_bind_commit(l, total, &[], &mf.commit))
+    {
+        return Ok(l.clone());
+    }
+    let mut leaves = Vec::with_capacity(total);
     for i in 0..total {
-        let p = in_dir.join(format!("shard_{:03}.bin", i));
-        if !p.exists() {
-            eprintln!("missing shard_{:03}.bin", i);
-            std::process::exit(2);
-        }
-        let bytes = read_all(&p);
-        leaves.push(leaf_hash(&bytes));
+        let Some(body) = mf.read_shard(store, i) else {
+            return Err(format!(
+                "shard_{i:03} is missing and the manifest has no usable shard_leaves"
+            ));
+        };
+        leaves.push(hex_encode(&leaf_hash(&body)));
     }
-
-    // сверяем Merkle root
-    let root = merkle_root(leaves).expect("merkle_root");
-    if root != mf.commit.merkle_root {
-        eprintln!("manifest merkle_root mismatch");
-        std::process::exit(2);
+    if !shard_leaves_bind_commit(&leaves, total, &[], &mf.commit) {
+        return Err("shards on disk do not give commit.merkle_root".to_string());
     }
+    Ok(leaves)
+}
 
-    // сверяем SCID
-    let scid2 = mf.commit.scid();
-    if scid2 != mf.scid {
-        eprintln!("manifest scid mismatch");
+/// pod-verify: шард на диске — тот, что под index в коммите. По pod_###.proof.json, а без него
+/// (PoD до доказательств включения) — по листьям коммита `leaves`; нет и их — не проверяется
+fn check_shard_inclusion(
+    in_dir: &Path,
+    mf: &Manifest,
+    index: usize,
+    body: &[u8],
+    leaves: Option<&[String]>,
+) -> Result<(), &'static str> {
+    let Ok(bytes) = fs::read(shard_proof_path(in_dir, index)) else {
+        return match leaves {
+            Some(l) if !ct_eq(hex_encode(&leaf_hash(body)).as_bytes(), l[index].as_bytes()) => {
+                Err("shard not in commit")
+            }
+            _ => Ok(()),
+        };
+    };
+    let proof: ShardProof =
+        serde_json::from_slice(&bytes).map_err(|_| "malformed inclusion proof")?;
+    proof.verify(&mf.commit, &mf.scid, index, &leaf_hash(body))
+}
+
+fn shard_index_flag(args: &[String], mf: &Manifest) -> usize {
+    let total = mf.commit.erasure_data + mf.commit.erasure_parity;
+    let index: usize = require_flag(args, "index").parse().unwrap_or_else(|_| {
+        eprintln!("error: invalid --index (number)");
+        std::process::exit(2);
+    });
+    if index >= total {
+        eprintln!("error: --index={index} out of range ({total} shards)");
         std::process::exit(2);
     }
-
-    println!("verify-pack: OK (merkle_root & scid match)");
+    index
 }
 
-fn verify_pack_stream_cmd(args: &[String]) {
+/// Доказательство включения шарда i в commit.merkle_root (тот же формат, что pod_###.proof.json)
+fn merkle_proof_cmd(args: &[String]) {
     if args.is_empty() {
         usage();
     }
     let in_dir = PathBuf::from(&args[0]);
-
-    // читаем stream-манифест
-    let sm_bytes = read_all(&in_dir.join("manifest_stream.json"));
-    let sm: StreamManifest = serde_json::from_slice(&sm_bytes).expect("manifest_stream parse");
-
-    let total = sm.data_shards + sm.parity_shards;
-    let mut leaves = Vec::<[u8; 32]>::with_capacity(total);
-    for i in 0..total {
-        let p = in_dir.join(format!("shard_{:03}.bin", i));
-        if !p.exists() {
-            eprintln!("missing shard_{:03}.bin", i);
+    let mf: Manifest =
+        serde_json::from_slice(&read_all(&in_dir.join("manifest.json"))).expect("manifest parse");
+    check_scid("manifest", &mf.commit, &mf.scid);
+    let index = shard_index_flag(args, &mf);
+
+    let store = mf.shard_store(&in_dir);
+    let leaves = commit_leaves(&mf, &store).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        std::process::exit(2);
+    });
+    // шард на диске, если есть, обязан быть тем, что в коммите
+    if let Some(body) = mf.read_shard(&store, index) {
+        if !ct_eq(
+            hex_encode(&leaf_hash(&body)).as_bytes(),
+            leaves[index].as_bytes(),
+        ) {
+            eprintln!("error: shard_{index:03} on disk is not the committed shard");
             std::process::exit(2);
         }
-        let bytes = read_all(&p);
-        leaves.push(leaf_hash(&bytes));
     }
 
-    // сверяем Merkle root
-    let root = merkle_root(leaves).expect("merkle_root");
-    if root != sm.commit.merkle_root {
-        eprintln!("manifest_stream merkle_root mismatch");
-        std::process::exit(2);
+    let proof = ShardProof {
+        version: 1,
+        scid: mf.scid.clone(),
+        index,
+        leaves,
+    };
+    let proof_json = serde_json::to_vec_pretty(&proof).unwrap();
+    match arg_flag(args, "out") {
+        Some(out) => {
+            write_atomic(Path::new(&out), &proof_json);
+            println!("merkle proof {index} → {out}");
+        }
+        None => println!("{}", String::from_utf8(proof_json).unwrap()),
     }
+}
 
-    // сверяем SCID
-    let scid2 = sm.commit.scid();
-    if scid2 != sm.scid {
-        eprintln!("manifest_stream scid mismatch");
+/// Шард i каталога против доказательства: его leaf_hash стоит под i в дереве с commit.merkle_root
+fn merkle_verify_cmd(args: &[String]) {
+    if args.is_empty() {
+        usage();
+    }
+    let in_dir = PathBuf::from(&args[0]);
+    let proof: ShardProof =
+        match serde_json::from_slice(&read_all(Path::new(&require_flag(args, "proof")))) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("error: proof parse: {e}");
+                std::process::exit(2);
+            }
+        };
+    let mf: Manifest =
+        serde_json::from_slice(&read_all(&in_dir.join("manifest.json"))).expect("manifest parse");
+    check_scid("manifest", &mf.commit, &mf.scid);
+    let index = shard_index_flag(args, &mf);
+
+    let store = mf.shard_store(&in_dir);
+    let Some(body) = mf.read_shard(&store, index) else {
+        eprintln!("error: shard_{index:03} is missing");
+        std::process::exit(2);
+    };
+    if let Err(reason) = proof.verify(&mf.commit, &mf.scid, index, &leaf_hash(&body)) {
+        eprintln!("shard {index}: {reason}");
         std::process::exit(2);
     }
-
-    println!("verify-pack-stream: OK (merkle_root & scid match)");
-}
-
-//==================== PoD: подписать/проверить/агрегировать ====================//
-
-fn parse_sk_hex(sk_hex: &str) -> SigningKey {
-    let sk_bytes = hex_decode(sk_hex);
-    assert_eq!(sk_bytes.len(), 32, "sk-hex must be 32 bytes (64 hex chars)");
-    let mut arr = [0u8; 32];
-    arr.copy_from_slice(&sk_bytes);
-    SigningKey::from_bytes(&arr)
+    println!("merkle-verify: OK (shard {index} is in commit {})", mf.scid);
 }
 
 fn pod_sign_cmd(args: &[String]) {
@@ -611,111 +3490,588 @@ fn pod_sign_cmd(args: &[String]) {
         usage();
     }
     let in_dir = PathBuf::from(&args[0]);
-    let sk_hex = require_flag(args, "sk-hex");
-    let sk = parse_sk_hex(&sk_hex);
+    let committee = arg_flag(args, "sk-dir").is_some();
+    let signers = load_signers(args);
+    let ts_ms = artifact_ts_ms(args);
 
     // манифест
     let mf_bytes = read_all(&in_dir.join("manifest.json"));
     let mf: Manifest = serde_json::from_slice(&mf_bytes).expect("manifest parse");
 
     let total = mf.data_shards + mf.parity_shards;
+    let store = mf.shard_store(&in_dir);
+    let append = has_flag(args, "append");
+    let data_only = has_flag(args, "data-only");
     let mut signed = 0usize;
-
-    for i in 0..total {
-        let shard_path = in_dir.join(format!("shard_{:03}.bin", i));
-        if !shard_path.exists() {
+    let mut written = 0usize;
+    let mut kept = 0usize;
+
+    // область подписи запоминаем для pod-verify/pod-aggregate
+    let scope_path = in_dir.join(POD_SCOPE_FILE);
+    if data_only {
+        let scope = serde_json::to_vec_pretty(&PodScope { data_only }).expect("scope json");
+        write_atomic(&scope_path, &scope);
+    } else if scope_path.exists() {
+        fs::remove_file(&scope_path).expect("remove pod_scope.json");
+    }
+    let sign_total = if data_only {
+        mf.commit.erasure_data.min(total)
+    } else {
+        total
+    };
+    check_scid("manifest", &mf.commit, &mf.scid);
+    // рядом с PoD — доказательство включения шарда в коммит (pod_###.proof.json)
+    let leaves = commit_leaves(&mf, &store)
+        .map_err(|e| log_warn!("no inclusion proofs written: {e}"))
+        .ok();
+
+    for i in 0..sign_total {
+        let Some(shard_bytes) = mf.read_shard(&store, i) else {
             continue;
+        };
+
+        if let Some(leaves) = &leaves {
+            if !ct_eq(
+                hex_encode(&leaf_hash(&shard_bytes)).as_bytes(),
+                leaves[i].as_bytes(),
+            ) {
+                log_warn!(
+                    "shard_{i:03} is not the committed shard (leaf_hash differs from commit)"
+                );
+            }
+            let proof = ShardProof {
+                version: 1,
+                scid: mf.scid.clone(),
+                index: i,
+                leaves: leaves.clone(),
+            };
+            write_all(
+                &shard_proof_path(&in_dir, i),
+                &serde_json::to_vec_pretty(&proof).expect("proof json"),
+            );
         }
-        let shard_bytes = read_all(&shard_path);
 
-        // leaf hash = sha256(shard)
-        let mut h = Sha256::new();
-        h.update(&shard_bytes);
-        let leaf_hash: [u8; 32] = h.finalize().into();
+        // leaf hash = sha256(shard) — по телу, без trailer
+        let leaf_hash = shard_sha256(&shard_bytes);
 
-        let pod = ProofOfDelivery::sign(&sk, &mf.scid, i as u32, leaf_hash, None);
-        let pod_json = serde_json::to_vec_pretty(&pod).expect("pod json");
-        write_all(&in_dir.join(format!("pod_{:03}.json", i)), &pod_json);
+        for sk in &signers {
+            let pubkey = sk.verifying_key().to_bytes();
+            // комитет: по файлу на подписанта, иначе — прежнее имя pod_###.json
+            let path = if committee {
+                in_dir.join(format!("pod_{:03}_{}.json", i, signer_tag(&pubkey)))
+            } else {
+                in_dir.join(format!("pod_{:03}.json", i))
+            };
+            // --append: действующий PoD этого же подписанта по тому же шарду не трогаем
+            if append && pod_is_current(&path, &mf.scid, i, &pubkey, &leaf_hash) {
+                kept += 1;
+                continue;
+            }
+            let pod = ProofOfDelivery::sign(sk, &mf.scid, i as u32, leaf_hash, Some(ts_ms));
+            let pod_json = serde_json::to_vec_pretty(&pod).expect("pod json");
+            write_all(&path, &pod_json);
+            written += 1;
+        }
         signed += 1;
     }
 
-    println!(
-        "PoD signed: {}/{} present shards → {}",
-        signed,
-        total,
-        in_dir.display()
-    );
+    if append {
+        println!(
+            "PoD append: {written} written, {kept} kept ({signed}/{sign_total} present shards × {} signer(s)) → {}",
+            signers.len(),
+            in_dir.display()
+        );
+    } else {
+        println!(
+            "PoD signed: {}/{} present shards × {} signer(s) → {}",
+            signed,
+            sign_total,
+            signers.len(),
+            in_dir.display()
+        );
+    }
 }
 
-fn pod_verify_cmd(args: &[String]) {
-    if args.is_empty() {
-        usage();
+/// Есть ли в path валидный PoD этого подписанта для шарда index с текущим leaf_hash
+fn pod_is_current(
+    path: &Path,
+    scid: &str,
+    index: usize,
+    pubkey: &[u8; 32],
+    leaf_hash: &[u8; 32],
+) -> bool {
+    let Ok(bytes) = fs::read(path) else {
+        return false;
+    };
+    let Ok(pod) = serde_json::from_slice::<ProofOfDelivery>(&bytes) else {
+        return false;
+    };
+    pod.verify()
+        && pod.scid == scid
+        && pod.shard_index as usize == index
+        && &pod.signer_pubkey == pubkey
+        && ct_eq(&pod.leaf_hash, leaf_hash)
+}
+
+/// PoD-файлы по индексам шардов: pod_###.json и pod_###_<signer>.json
+fn list_pod_files(in_dir: &Path, total: usize) -> Vec<Vec<PathBuf>> {
+    let mut by_index: Vec<Vec<PathBuf>> = vec![Vec::new(); total];
+    let Ok(rd) = fs::read_dir(in_dir) else {
+        return by_index;
+    };
+    for entry in rd.flatten() {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        let Some(stem) = name
+            .strip_prefix("pod_")
+            .and_then(|r| r.strip_suffix(".json"))
+        else {
+            continue;
+        };
+        // pod_aggregate.json и прочее с нечисловым индексом пропускаем
+        let idx_part = stem.split('_').next().unwrap_or("");
+        if let Ok(i) = idx_part.parse::<usize>() {
+            if i < total {
+                by_index[i].push(entry.path());
+            }
+        }
     }
-    let in_dir = PathBuf::from(&args[0]);
+    for files in by_index.iter_mut() {
+        files.sort();
+    }
+    by_index
+}
 
-    // манифест
-    let mf_bytes = read_all(&in_dir.join("manifest.json"));
-    let mf: Manifest = serde_json::from_slice(&mf_bytes).expect("manifest parse");
+fn unix_now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
 
-    let total = mf.data_shards + mf.parity_shards;
-    let mut ok = 0usize;
-    let mut bad = 0usize;
-    let mut missing = 0usize;
+/// Метка времени для записываемых артефактов (PoD, агрегат): SOURCE_DATE_EPOCH (секунды),
+/// иначе 0 при --deterministic, иначе текущее время. Ed25519 детерминирован, так что при
+/// фиксированной метке повторный запуск даёт побайтно те же файлы
+fn artifact_ts_ms(args: &[String]) -> u64 {
+    if let Ok(epoch) = env::var("SOURCE_DATE_EPOCH") {
+        match epoch.trim().parse::<u64>() {
+            Ok(secs) => return secs.saturating_mul(1000),
+            Err(_) => {
+                eprintln!("error: SOURCE_DATE_EPOCH must be integer seconds, got '{epoch}'");
+                std::process::exit(2);
+            }
+        }
+    }
+    if has_flag(args, "deterministic") {
+        0
+    } else {
+        unix_now_ms()
+    }
+}
 
-    for i in 0..total {
-        let pod_path = in_dir.join(format!("pod_{:03}.json", i));
-        if !pod_path.exists() {
-            missing += 1;
+// Окно свежести PoD (--max-age-ms / --max-skew-ms) относительно текущего времени
+struct FreshnessWindow {
+    now_ms: u64,
+    max_age_ms: u64,
+    max_skew_ms: u64,
+}
+
+impl FreshnessWindow {
+    fn from_args(args: &[String]) -> Option<Self> {
+        let max_age_ms = arg_flag(args, "max-age-ms")?.parse().unwrap_or_else(|_| {
+            eprintln!("error: invalid --max-age-ms (number)");
+            std::process::exit(2);
+        });
+        Some(Self {
+            now_ms: unix_now_ms(),
+            max_age_ms,
+            max_skew_ms: arg_flag_default(args, "max-skew-ms", 60_000u64),
+        })
+    }
+
+    /// Слишком старый или датирован будущим сверх допустимого сдвига часов
+    fn is_stale(&self, ts_unix_ms: u64) -> bool {
+        ts_unix_ms < self.now_ms.saturating_sub(self.max_age_ms)
+            || ts_unix_ms > self.now_ms.saturating_add(self.max_skew_ms)
+    }
+}
+
+// PoD одного шарда: валидные (по одному на подписанта) и отклонённые файлы
+struct ShardPods {
+    valid: Vec<ProofOfDelivery>,
+    rejected: Vec<(String, &'static str)>,
+}
+
+/// Проверяет все PoD одного шарда. `shard_leaf`: None — не сверять с диском,
+/// Some(None) — шард отсутствует, Some(Some(h)) — sha256 шарда для сверки leaf_hash.
+fn collect_shard_pods(
+    files: &[PathBuf],
+    index: usize,
+    scid: &str,
+    shard_leaf: Option<Option<[u8; 32]>>,
+) -> ShardPods {
+    let mut valid: Vec<ProofOfDelivery> = Vec::new();
+    let mut rejected = Vec::new();
+
+    for path in files {
+        let file_name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("pod")
+            .to_string();
+        // нечитаемый/битый файл — отказ по этому PoD, а не падение всей проверки
+        let Ok(pod_bytes) = fs::read(path) else {
+            rejected.push((file_name, "unreadable"));
             continue;
-        }
-        let pod_bytes = read_all(&pod_path);
-        let pod: ProofOfDelivery = serde_json::from_slice(&pod_bytes).expect("pod parse");
+        };
+        let Ok(pod) = serde_json::from_slice::<ProofOfDelivery>(&pod_bytes) else {
+            rejected.push((file_name, "malformed PoD json"));
+            continue;
+        };
 
         // проверим scid
-        if pod.scid != mf.scid {
-            eprintln!("pod_{:03}.json: scid mismatch", i);
-            bad += 1;
+        if pod.scid != scid {
+            rejected.push((file_name, "scid mismatch"));
             continue;
         }
-        // возьмём соответствующий шард и пересчитаем хэш
-        let shard_path = in_dir.join(format!("shard_{:03}.bin", i));
-        if !shard_path.exists() {
-            eprintln!("pod_{:03}.json: shard file missing", i);
-            bad += 1;
+        // PoD, подложенный под чужой индекс (файл переименован/скопирован)
+        if pod.shard_index as usize != index {
+            rejected.push((file_name, "shard index mismatch"));
             continue;
         }
-        let shard_bytes = read_all(&shard_path);
-        let mut h = Sha256::new();
-        h.update(&shard_bytes);
-        let leaf_hash: [u8; 32] = h.finalize().into();
-        if leaf_hash != pod.leaf_hash {
-            eprintln!("pod_{:03}.json: leaf hash mismatch", i);
-            bad += 1;
-            continue;
+        // сверим с шардом на диске
+        match shard_leaf {
+            Some(None) => {
+                rejected.push((file_name, "shard file missing"));
+                continue;
+            }
+            Some(Some(leaf)) if !ct_eq(&leaf, &pod.leaf_hash) => {
+                rejected.push((file_name, "leaf hash mismatch"));
+                continue;
+            }
+            _ => {}
         }
         // криптографическая проверка
-        if pod.verify() {
-            ok += 1;
+        if !pod.verify() {
+            rejected.push((file_name, "signature invalid"));
+            continue;
+        }
+        // один подписант — один голос
+        if !valid.iter().any(|p| p.signer_pubkey == pod.signer_pubkey) {
+            valid.push(pod);
+        }
+    }
+    ShardPods { valid, rejected }
+}
+
+fn shard_sha256(shard_bytes: &[u8]) -> [u8; 32] {
+    let mut h = Sha256::new();
+    h.update(shard_bytes);
+    h.finalize().into()
+}
+
+// Результат проверки одного PoD (для --json)
+#[derive(Serialize)]
+struct PodShardResult {
+    index: usize,
+    status: &'static str, // "ok" | "bad" | "stale" | "missing" | "unsigned"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+    signers: usize, // различных валидных (и свежих, если задан --max-age-ms) подписантов
+    #[serde(skip_serializing_if = "is_zero")]
+    stale_signers: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+#[derive(Serialize)]
+struct PodVerifyReport {
+    status: &'static str, // "ok" | "fail"
+    scid: String,
+    quorum: usize,
+    ok: usize,
+    bad: usize,
+    stale: usize,
+    missing: usize,
+    #[serde(skip_serializing_if = "is_zero")]
+    unsigned: usize, // паритет без PoD после pod-sign --data-only — не missing
+    results: Vec<PodShardResult>,
+}
+
+#[derive(Serialize)]
+struct PodVerifyDir {
+    dir: String,
+    #[serde(flatten)]
+    report: Option<PodVerifyReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// Несколько каталогов (--dir-list / позиционные): сводка по каждому и общий итог
+#[derive(Serialize)]
+struct PodVerifyBatchReport {
+    status: &'static str, // "ok" | "fail"
+    dirs: usize,
+    failed_dirs: usize,
+    ok: usize,
+    bad: usize,
+    stale: usize,
+    missing: usize,
+    #[serde(skip_serializing_if = "is_zero")]
+    unsigned: usize,
+    results: Vec<PodVerifyDir>,
+}
+
+/// PoD одного пака; Err — каталог не читается как пак (нет/битый manifest.json)
+fn pod_verify_dir(
+    in_dir: &Path,
+    quorum: usize,
+    window: Option<&FreshnessWindow>,
+) -> Result<PodVerifyReport, String> {
+    // манифест
+    let mf_bytes =
+        fs::read(in_dir.join("manifest.json")).map_err(|e| format!("manifest.json: {e}"))?;
+    let mf: Manifest =
+        serde_json::from_slice(&mf_bytes).map_err(|e| format!("manifest.json: {e}"))?;
+    if mf.commit.scid() != mf.scid {
+        return Err("manifest.json: scid does not match commit".to_string());
+    }
+
+    let total = mf.data_shards + mf.parity_shards;
+    let store = mf.shard_store(in_dir);
+    let pod_files = list_pod_files(in_dir, total);
+    let expected = pod_expected(in_dir, &mf);
+    // листья коммита нужны, только если какого-то pod_###.proof.json нет
+    let leaves = if (0..total).all(|i| shard_proof_path(in_dir, i).exists()) {
+        None
+    } else {
+        commit_leaves(&mf, &store)
+            .map_err(|e| log_warn!("inclusion proofs missing, membership not checked: {e}"))
+            .ok()
+    };
+    let mut ok = 0usize;
+    let mut bad = 0usize;
+    let mut stale = 0usize;
+    let mut missing = 0usize;
+    let mut unsigned = 0usize;
+    let mut results = Vec::with_capacity(total);
+
+    for (i, files) in pod_files.iter().enumerate() {
+        if files.is_empty() {
+            let status = if i < expected {
+                missing += 1;
+                "missing"
+            } else {
+                unsigned += 1;
+                "unsigned"
+            };
+            results.push(PodShardResult {
+                index: i,
+                status,
+                reason: None,
+                signers: 0,
+                stale_signers: 0,
+            });
+            continue;
+        }
+        let shard = mf.read_shard(&store, i);
+        let pods = collect_shard_pods(files, i, &mf.scid, Some(shard.as_deref().map(shard_sha256)));
+        for (file_name, reason) in &pods.rejected {
+            log_warn!("{file_name}: {reason}");
+        }
+        // подписи подтверждают доставку того, что лежит на диске; принадлежность коммиту — proof
+        let inclusion = match &shard {
+            Some(body) => check_shard_inclusion(in_dir, &mf, i, body, leaves.as_deref()),
+            None => Ok(()), // отсутствие шарда уже отклонило его PoD
+        };
+        if let Err(r) = inclusion {
+            log_warn!("shard {i}: {r}");
+        }
+        // подписи валидны, но вне окна свежести — отдельная категория stale
+        let stale_signers = match window {
+            Some(w) => pods
+                .valid
+                .iter()
+                .filter(|p| w.is_stale(p.ts_unix_ms))
+                .count(),
+            None => 0,
+        };
+        let signers = pods.valid.len() - stale_signers;
+        let (status, reason) = if let Some((_, r)) = pods.rejected.first() {
+            ("bad", Some(*r))
+        } else if let Err(r) = inclusion {
+            ("bad", Some(r))
+        } else if signers < quorum && stale_signers > 0 {
+            log_warn!("shard {i}: {stale_signers} PoD(s) outside the freshness window");
+            ("stale", Some("stale timestamp"))
+        } else if signers < quorum {
+            log_warn!("shard {i}: {signers} valid signer(s), quorum {quorum}");
+            ("bad", Some("below quorum"))
         } else {
-            eprintln!("pod_{:03}.json: signature invalid", i);
-            bad += 1;
+            ("ok", None)
+        };
+        match status {
+            "ok" => ok += 1,
+            "stale" => stale += 1,
+            _ => bad += 1,
         }
+        results.push(PodShardResult {
+            index: i,
+            status,
+            reason,
+            signers,
+            stale_signers,
+        });
     }
 
-    println!(
-        "PoD verify summary: ok={}, bad={}, missing={}",
-        ok, bad, missing
-    );
-    if bad == 0 {
-        // ok
+    Ok(PodVerifyReport {
+        status: if bad == 0 && stale == 0 { "ok" } else { "fail" },
+        scid: mf.scid,
+        quorum,
+        ok,
+        bad,
+        stale,
+        missing,
+        unsigned,
+        results,
+    })
+}
+
+fn pod_verify_summary(r: &PodVerifyReport, with_stale: bool) -> String {
+    let mut s = if with_stale {
+        format!(
+            "ok={}, bad={}, stale={}, missing={}",
+            r.ok, r.bad, r.stale, r.missing
+        )
+    } else {
+        format!("ok={}, bad={}, missing={}", r.ok, r.bad, r.missing)
+    };
+    if r.unsigned > 0 {
+        s.push_str(&format!(", unsigned={}", r.unsigned));
+    }
+    s
+}
+
+fn pod_verify_cmd(args: &[String]) {
+    let json = has_flag(args, "json");
+    let quorum: usize = arg_flag_default(args, "quorum", 1usize).max(1);
+    let window = FreshnessWindow::from_args(args);
+
+    // каталоги: позиционные аргументы и/или --dir-list (по одному на строку, # — комментарий)
+    let mut dirs: Vec<PathBuf> = args
+        .iter()
+        .filter(|a| !a.starts_with("--"))
+        .map(PathBuf::from)
+        .collect();
+    let batch = arg_flag(args, "dir-list").is_some() || dirs.len() > 1;
+    if let Some(list) = arg_flag(args, "dir-list") {
+        let text = String::from_utf8_lossy(&read_all(Path::new(&list))).into_owned();
+        dirs.extend(
+            text.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(PathBuf::from),
+        );
+    }
+    if dirs.is_empty() {
+        usage();
+    }
+
+    if !batch {
+        let report = match pod_verify_dir(&dirs[0], quorum, window.as_ref()) {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("error: {}: {e}", dirs[0].display());
+                std::process::exit(2);
+            }
+        };
+        if json {
+            println!("{}", serde_json::to_string(&report).expect("report json"));
+        } else {
+            println!(
+                "PoD verify summary: {}",
+                pod_verify_summary(&report, window.is_some())
+            );
+        }
+        if report.status != "ok" {
+            std::process::exit(2);
+        }
+        return;
+    }
+
+    // пакетный режим: ошибка одного каталога не останавливает остальные
+    let mut total = PodVerifyBatchReport {
+        status: "ok",
+        dirs: dirs.len(),
+        failed_dirs: 0,
+        ok: 0,
+        bad: 0,
+        stale: 0,
+        missing: 0,
+        unsigned: 0,
+        results: Vec::with_capacity(dirs.len()),
+    };
+    for dir in &dirs {
+        let name = dir.display().to_string();
+        match pod_verify_dir(dir, quorum, window.as_ref()) {
+            Ok(r) => {
+                total.ok += r.ok;
+                total.bad += r.bad;
+                total.stale += r.stale;
+                total.missing += r.missing;
+                total.unsigned += r.unsigned;
+                if r.status != "ok" {
+                    total.failed_dirs += 1;
+                }
+                if !json {
+                    println!(
+                        "{name}: {} ({})",
+                        r.status,
+                        pod_verify_summary(&r, window.is_some())
+                    );
+                }
+                total.results.push(PodVerifyDir {
+                    dir: name,
+                    report: Some(r),
+                    error: None,
+                });
+            }
+            Err(e) => {
+                total.failed_dirs += 1;
+                if !json {
+                    println!("{name}: error ({e})");
+                }
+                total.results.push(PodVerifyDir {
+                    dir: name,
+                    report: None,
+                    error: Some(e),
+                });
+            }
+        }
+    }
+    if total.failed_dirs > 0 {
+        total.status = "fail";
+    }
+    if json {
+        println!("{}", serde_json::to_string(&total).expect("report json"));
     } else {
+        println!(
+            "PoD verify total: dirs={}, failed={}, ok={}, bad={}, stale={}, missing={}",
+            total.dirs, total.failed_dirs, total.ok, total.bad, total.stale, total.missing
+        );
+    }
+    if total.failed_dirs > 0 {
         std::process::exit(2);
     }
 }
 
 //— агрегатор PoD —//
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct PodAggregate {
     version: u8,
     scid: String,
@@ -724,9 +4080,40 @@ struct PodAggregate {
     ok: usize,
     bad: usize,
     missing: usize,
+    quorum: usize,
     pod_root_hex: String,
     included_indexes: Vec<usize>,
     ts_unix_ms: u64,
+    #[serde(default)]
+    coverage: f64, // ok / total_shards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_coverage: Option<f64>, // порог --min-coverage, с которым агрегат был выпущен
+    #[serde(default, skip_serializing_if = "is_false")]
+    data_only: bool, // PoD только по data-шардам: coverage = ok / data_shards
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// --min-coverage: доля 0..=1 как "0.67" или "2/3"
+fn parse_fraction(s: &str) -> Option<f64> {
+    let v = match s.split_once('/') {
+        Some((n, d)) => {
+            let d: f64 = d.trim().parse().ok()?;
+            (d != 0.0).then_some(n.trim().parse::<f64>().ok()? / d)?
+        }
+        None => s.trim().parse().ok()?,
+    };
+    (0.0..=1.0).contains(&v).then_some(v)
+}
+
+#[derive(Serialize)]
+struct PodAggregateReport<'a> {
+    status: &'static str,
+    out: String,
+    #[serde(flatten)]
+    aggregate: &'a PodAggregate,
 }
 
 fn pod_leaf_hash(pod: &ProofOfDelivery) -> [u8; 32] {
@@ -741,6 +4128,123 @@ fn pod_leaf_hash(pod: &ProofOfDelivery) -> [u8; 32] {
     h.finalize().into()
 }
 
+// Отчёт pod-aggregate-verify (для --json)
+#[derive(Serialize)]
+struct PodAggregateVerifyReport {
+    status: &'static str, // "ok" | "fail"
+    scid: String,
+    pod_root_hex: String,
+    computed_root_hex: String,
+    bad_indexes: Vec<usize>, // включённые шарды, PoD которых больше не проходят проверку/кворум
+    coverage: f64,           // по шардам, которые проходят сейчас
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min_coverage: Option<f64>,
+}
+
+/// Пересчитывает корень агрегата по текущим PoD на диске для included_indexes
+fn pod_aggregate_verify_cmd(args: &[String]) {
+    if args.is_empty() {
+        usage();
+    }
+    let in_dir = PathBuf::from(&args[0]);
+    let agg_path = arg_flag(args, "aggregate")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| in_dir.join("pod_aggregate.json"));
+    let json = has_flag(args, "json");
+
+    let mf_bytes = read_all(&in_dir.join("manifest.json"));
+    let mf: Manifest = serde_json::from_slice(&mf_bytes).expect("manifest parse");
+    check_scid("manifest", &mf.commit, &mf.scid);
+    let agg: PodAggregate =
+        serde_json::from_slice(&read_all(&agg_path)).expect("pod_aggregate parse");
+    let total = mf.data_shards + mf.parity_shards;
+
+    let pod_files = list_pod_files(&in_dir, total);
+    let mut leaves = Vec::<[u8; 32]>::new();
+    let mut bad_indexes = Vec::new();
+    for &i in &agg.included_indexes {
+        let Some(files) = pod_files.get(i) else {
+            log_error!("included index {i} is out of range ({total} shards)");
+            bad_indexes.push(i);
+            continue;
+        };
+        // тот же порядок листьев, что и в pod-aggregate: по индексу, подписанты по имени файла
+        let pods = collect_shard_pods(files, i, &agg.scid, None);
+        for (file_name, reason) in &pods.rejected {
+            log_warn!("{file_name}: {reason}");
+        }
+        if !pods.rejected.is_empty() || pods.valid.len() < agg.quorum {
+            bad_indexes.push(i);
+        }
+        leaves.extend(pods.valid.iter().map(pod_leaf_hash));
+    }
+
+    let computed_root_hex = if leaves.is_empty() {
+        String::new()
+    } else {
+        hex_encode(&merkle_root(leaves).expect("pod merkle root"))
+    };
+    let scid_ok = agg.scid == mf.scid;
+    let root_ok = ct_eq(computed_root_hex.as_bytes(), agg.pod_root_hex.as_bytes());
+    // знаменатель — из commit (связан scid), в числителе только индексы из этой области
+    let covered = if agg.data_only {
+        mf.commit.erasure_data
+    } else {
+        mf.commit.erasure_data + mf.commit.erasure_parity
+    };
+    let still_ok = agg
+        .included_indexes
+        .iter()
+        .filter(|&&i| i < covered && !bad_indexes.contains(&i))
+        .count();
+    let coverage = still_ok as f64 / covered as f64;
+    let coverage_ok = !agg.min_coverage.is_some_and(|m| coverage < m);
+    let status = if scid_ok && root_ok && coverage_ok && bad_indexes.is_empty() {
+        "ok"
+    } else {
+        "fail"
+    };
+
+    if json {
+        let report = PodAggregateVerifyReport {
+            status,
+            scid: agg.scid,
+            pod_root_hex: agg.pod_root_hex,
+            computed_root_hex,
+            bad_indexes,
+            coverage,
+            min_coverage: agg.min_coverage,
+        };
+        println!("{}", serde_json::to_string(&report).expect("report json"));
+    } else {
+        if !scid_ok {
+            log_error!("aggregate scid does not match manifest.json");
+        }
+        for i in &bad_indexes {
+            log_error!("shard {i}: included PoD no longer verifies");
+        }
+        if !coverage_ok {
+            log_error!(
+                "coverage {coverage:.4} is below the aggregate's min_coverage {:.4}",
+                agg.min_coverage.unwrap_or_default()
+            );
+        }
+        if !root_ok {
+            log_error!(
+                "pod_root mismatch: aggregate {}, recomputed {}",
+                agg.pod_root_hex,
+                computed_root_hex
+            );
+        }
+        if status == "ok" {
+            println!("pod-aggregate-verify: OK (pod_root matches on-disk PoDs)");
+        }
+    }
+    if status != "ok" {
+        std::process::exit(2);
+    }
+}
+
 fn pod_aggregate_cmd(args: &[String]) {
     if args.is_empty() {
         usage();
@@ -749,13 +4253,23 @@ fn pod_aggregate_cmd(args: &[String]) {
     let out_path = arg_flag(args, "out")
         .map(PathBuf::from)
         .unwrap_or_else(|| in_dir.join("pod_aggregate.json"));
+    let json = has_flag(args, "json");
+    let quorum: usize = arg_flag_default(args, "quorum", 1usize).max(1);
+    let min_coverage = arg_flag(args, "min-coverage").map(|s| {
+        parse_fraction(&s).unwrap_or_else(|| {
+            eprintln!("error: --min-coverage expects a fraction in 0..=1 (0.67 or 2/3), got '{s}'");
+            std::process::exit(2);
+        })
+    });
 
     // манифест
     let mf_bytes = read_all(&in_dir.join("manifest.json"));
     let mf: Manifest = serde_json::from_slice(&mf_bytes).expect("manifest parse");
+    check_scid("manifest", &mf.commit, &mf.scid);
     let total = mf.data_shards + mf.parity_shards;
+    let expected = pod_expected(&in_dir, &mf);
 
-    // собираем PoD
+    // собираем PoD (в листья — каждый валидный подписант шарда)
     let mut leaves = Vec::<[u8; 32]>::new();
     let mut included_indexes = Vec::<usize>::new();
     let mut ok = 0usize;
@@ -763,42 +4277,72 @@ fn pod_aggregate_cmd(args: &[String]) {
     let mut missing = 0usize;
     let mut present = 0usize;
 
-    for i in 0..total {
-        let p = in_dir.join(format!("pod_{:03}.json", i));
-        if !p.exists() {
-            missing += 1;
+    for (i, files) in list_pod_files(&in_dir, total).iter().enumerate() {
+        if files.is_empty() {
+            // неподписанный по --data-only паритет не в счёт
+            if i < expected {
+                missing += 1;
+            }
             continue;
         }
         present += 1;
-        let pod_bytes = read_all(&p);
-        let pod: ProofOfDelivery = serde_json::from_slice(&pod_bytes).expect("pod parse");
-        if pod.scid != mf.scid {
-            eprintln!("pod_{:03}.json: scid mismatch", i);
+        let pods = collect_shard_pods(files, i, &mf.scid, None);
+        for (file_name, reason) in &pods.rejected {
+            log_warn!("{file_name}: {reason}");
+        }
+        if !pods.rejected.is_empty() {
             bad += 1;
             continue;
         }
-        if !pod.verify() {
-            eprintln!("pod_{:03}.json: signature invalid", i);
+        if pods.valid.len() < quorum {
+            log_warn!(
+                "shard {i}: {} valid signer(s), quorum {quorum}",
+                pods.valid.len()
+            );
             bad += 1;
             continue;
         }
         ok += 1;
         included_indexes.push(i);
-        leaves.push(pod_leaf_hash(&pod));
+        leaves.extend(pods.valid.iter().map(pod_leaf_hash));
     }
 
-    if leaves.is_empty() {
-        eprintln!("no valid PoD to aggregate");
+    // доля подтверждённых шардов из тех, что должны иметь PoD (PoD паритета при --data-only
+    // в числитель не идут, иначе coverage > 1); ниже --min-coverage агрегат не выпускается
+    let ok_in_scope = included_indexes.iter().filter(|&&i| i < expected).count();
+    let coverage = ok_in_scope as f64 / expected as f64;
+    let below = min_coverage.is_some_and(|m| coverage < m);
+    if leaves.is_empty() || below {
+        if leaves.is_empty() {
+            log_error!("no valid PoD to aggregate");
+        } else {
+            log_error!(
+                "coverage {coverage:.4} ({ok_in_scope}/{expected}) is below --min-coverage {:.4}",
+                min_coverage.unwrap_or_default()
+            );
+        }
+        if json {
+            let report = serde_json::json!({
+                "status": "fail",
+                "scid": mf.scid,
+                "total_shards": total,
+                "present_pods": present,
+                "ok": ok,
+                "bad": bad,
+                "missing": missing,
+                "quorum": quorum,
+                "coverage": coverage,
+                "min_coverage": min_coverage,
+            });
+            println!("{report}");
+        }
         std::process::exit(2);
     }
 
     let root = merkle_root(leaves).expect("pod merkle root");
     let pod_root_hex = hex_encode(&root);
 
-    let now_ms = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as u64;
+    let now_ms = artifact_ts_ms(args);
 
     let agg = PodAggregate {
         version: 1,
@@ -808,14 +4352,27 @@ fn pod_aggregate_cmd(args: &[String]) {
         ok,
         bad,
         missing,
+        quorum,
         pod_root_hex,
         included_indexes,
         ts_unix_ms: now_ms,
+        coverage,
+        min_coverage,
+        data_only: expected < total,
     };
 
-    let json = serde_json::to_vec_pretty(&agg).unwrap();
-    write_all(&out_path, &json);
-    println!("PoD aggregate → {}", out_path.display());
+    let agg_json = serde_json::to_vec_pretty(&agg).unwrap();
+    write_atomic(&out_path, &agg_json);
+    if json {
+        let report = PodAggregateReport {
+            status: "ok",
+            out: out_path.display().to_string(),
+            aggregate: &agg,
+        };
+        println!("{}", serde_json::to_string(&report).expect("report json"));
+    } else {
+        println!("PoD aggregate → {}", out_path.display());
+    }
 }
 
 //==================== Fountain-профиль: pack/unpack ====================//
@@ -834,6 +4391,55 @@ struct FountainMeta {
     seed: u64,
     c: f64,
     delta: f64,
+    #[serde(default = "default_true")]
+    systematic: bool, // первые k пакетов — исходные блоки (нет в старых meta → true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    systematic_count: Option<usize>, // сколько исходных блоков в префиксе; нет — k или 0 по systematic
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stats: Option<FountainStats>, // только с --stats
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kdf: Option<KdfParams>, // растяжение ikm (--kdf=argon2id); нет — HKDF по ikm напрямую
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// Статистика по сгенерированному набору пакетов (pack-fountain)
+#[derive(Serialize, Deserialize)]
+struct FountainStats {
+    degree_histogram: BTreeMap<usize, usize>, // степень → число пакетов
+    min_coverage: usize,                      // минимум пакетов, покрывающих один исходный блок
+    degree1_fraction: f64,
+    degree1_beyond_systematic: usize, // пакетов степени 1 после systematic-префикса
+}
+
+fn fountain_stats(pkts: &[Packet], k: usize, systematic: usize) -> FountainStats {
+    let mut degree_histogram = BTreeMap::new();
+    let mut coverage = vec![0usize; k];
+    let mut degree1_beyond_systematic = 0usize;
+    for (n, p) in pkts.iter().enumerate() {
+        *degree_histogram.entry(p.ids.len()).or_insert(0) += 1;
+        for &i in &p.ids {
+            if i < k {
+                coverage[i] += 1;
+            }
+        }
+        if n >= systematic && p.ids.len() == 1 {
+            degree1_beyond_systematic += 1;
+        }
+    }
+    let degree1 = degree_histogram.get(&1).copied().unwrap_or(0);
+    FountainStats {
+        degree_histogram,
+        min_coverage: coverage.iter().copied().min().unwrap_or(0),
+        degree1_fraction: if pkts.is_empty() {
+            0.0
+        } else {
+            degree1 as f64 / pkts.len() as f64
+        },
+        degree1_beyond_systematic,
+    }
 }
 
 // JSON-представление пакета для jsonl (основная текущая схема)
@@ -853,77 +4459,196 @@ enum WirePacketAny {
     Raw { ids: Vec<usize>, body: String },
 }
 
-fn decode_wire_packet(wp: WirePacketAny) -> Packet {
-    match wp {
-        WirePacketAny::Hex { ids, body_hex } => Packet {
-            ids,
-            body: hex_decode(&body_hex),
-        },
+/// Строка jsonl → Packet; None, если тело не декодируется (битый hex/base64) —
+/// такую строку unpack-fountain считает отброшенной, а не падает на ней
+fn decode_wire_packet(wp: WirePacketAny) -> Option<Packet> {
+    let (ids, body) = match wp {
+        WirePacketAny::Hex { ids, body_hex } => (ids, hex::decode(body_hex.trim()).ok()?),
         WirePacketAny::B64 { ids, body_b64 } => {
-            let body = general_purpose::STANDARD
-                .decode(&body_b64)
-                .expect("bad base64 in body_b64");
-            Packet { ids, body }
-        }
-        WirePacketAny::Raw { ids, body } => Packet {
-            ids,
-            body: hex_decode(&body),
-        },
-    }
+            (ids, general_purpose::STANDARD.decode(&body_b64).ok()?)
+        }
+        WirePacketAny::Raw { ids, body } => (ids, hex::decode(body.trim()).ok()?),
+    };
+    Some(Packet { ids, body })
+}
+
+//==================== Fountain: калибровка overhead ====================//
+
+#[derive(Serialize)]
+struct CalibrateReport {
+    k: usize,
+    c: f64,
+    delta: f64,
+    systematic: bool,
+    loss: f64,
+    trials: usize,
+    failures: usize,
+    // отправленных пакетов (включая потерянные) до первого успешного peel
+    median_packets: usize,
+    p95_packets: usize,
+    max_packets: usize,
+    median_overhead: f64,
+    p95_overhead: f64,
 }
 
-// robust-soliton: μ = (ρ + τ) / Z
-fn robust_soliton(k: usize, c: f64, delta: f64) -> Vec<(usize, f32)> {
-    assert!(k >= 2, "k must be >= 2");
-    let kf = k as f64;
+/// Один прогон: сколько пакетов надо отправить, чтобы peel сошёлся (None — не уложились в limit)
+fn calibrate_trial(
+    k: usize,
+    probs: &'static [(usize, f32)],
+    seed: u64,
+    systematic: bool,
+    loss: f64,
+    limit: usize,
+) -> Option<usize> {
+    const BLOCK_LEN: usize = 32;
+    let mut rng = StdRng::seed_from_u64(seed ^ 0xCA1B_0000_0000_0000);
+    let blocks: Vec<Vec<u8>> = (0..k)
+        .map(|_| {
+            let mut b = vec![0u8; BLOCK_LEN];
+            rng.fill_bytes(&mut b);
+            b
+        })
+        .collect();
+    let mut enc = FountainEncoder::new(
+        k,
+        BLOCK_LEN,
+        FountainParams {
+            degree_probs: probs,
+            seed,
+        },
+    );
 
-    // ρ(d)
-    let mut rho = vec![0.0f64; k + 1];
-    rho[1] = 1.0 / kf;
-    for (d, r) in rho.iter_mut().enumerate().take(k + 1).skip(2) {
-        *r = 1.0 / ((d as f64) * ((d as f64) - 1.0));
+    // sent_at[j] — сколько пакетов было отправлено к моменту приёма received[j]
+    let mut received: Vec<Packet> = Vec::new();
+    let mut sent_at: Vec<usize> = Vec::new();
+    let step = (k / 16).max(1);
+    let mut tried = 0usize;
+    let mut next_try = k;
+    for sent in 1..=limit {
+        let pkt = if systematic && sent <= k {
+            Packet {
+                ids: vec![sent - 1],
+                body: blocks[sent - 1].clone(),
+            }
+        } else {
+            enc.next_packet(&blocks)
+        };
+        if rng.gen::<f64>() < loss {
+            continue;
+        }
+        received.push(pkt);
+        sent_at.push(sent);
+        let n = received.len();
+        if n < next_try && sent != limit {
+            continue;
+        }
+        next_try = n + step;
+        if peel_decode(k, BLOCK_LEN, copy_packets(&received)).is_none() {
+            tried = n;
+            continue;
+        }
+        // peel монотонен по добавлению пакетов — уточняем бинпоиском в (tried, n]
+        let (mut lo, mut hi) = (tried.max(k - 1), n);
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+            if peel_decode(k, BLOCK_LEN, copy_packets(&received[..mid])).is_some() {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        return Some(sent_at[hi - 1]);
     }
+    None
+}
 
-    // τ(d)
-    let r = c * ((kf / delta).ln()) * kf.sqrt();
-    let mut s = (kf / r).floor() as usize;
-    if s < 1 {
-        s = 1;
+fn fountain_calibrate_cmd(args: &[String]) {
+    let k: usize = require_flag(args, "k").parse().expect("invalid --k");
+    let c: f64 = arg_flag_default(args, "c", 0.1f64);
+    let delta: f64 = arg_flag_default(args, "delta", 0.05f64);
+    let trials: usize = arg_flag_default(args, "trials", 100usize);
+    let seed: u64 = arg_flag_default(args, "seed", 42u64);
+    let loss: f64 = arg_flag_default(args, "loss", 0.0f64);
+    let systematic = match arg_flag(args, "systematic").as_deref() {
+        None | Some("true") => true,
+        Some("false") => false,
+        Some(other) => {
+            eprintln!("error: --systematic expects true|false, got '{other}'");
+            std::process::exit(2);
+        }
+    };
+    if k < 2 || trials == 0 || !(0.0..1.0).contains(&loss) {
+        eprintln!("error: need --k >= 2, --trials >= 1 and --loss in [0, 1)");
+        std::process::exit(2);
     }
-    let mut tau = vec![0.0f64; k + 1];
-    for (d, t) in tau.iter_mut().enumerate().take(k + 1).skip(1) {
-        if d < s {
-            *t = r / ((d as f64) * kf);
-        } else if d == s {
-            *t = (r * (r / delta).ln()) / kf;
+    // потолок на прогон: с запасом на потери, дальше считаем провалом
+    let limit = ((10 * k) as f64 / (1.0 - loss)).ceil() as usize;
+
+    let probs: &'static [(usize, f32)] = Box::leak(robust_soliton(k, c, delta).into_boxed_slice());
+    let mut needed = Vec::with_capacity(trials);
+    let mut failures = 0usize;
+    for t in 0..trials {
+        match calibrate_trial(
+            k,
+            probs,
+            seed.wrapping_add(t as u64),
+            systematic,
+            loss,
+            limit,
+        ) {
+            Some(n) => needed.push(n),
+            None => failures += 1,
         }
+        log_trace!("trial {t}: {:?}", needed.last());
     }
-
-    // μ(d) и нормировка
-    let mut mu = vec![0.0f64; k + 1];
-    let mut z = 0.0f64;
-    for (m, (&rv, &tv)) in mu
-        .iter_mut()
-        .zip(rho.iter().zip(tau.iter()))
-        .take(k + 1)
-        .skip(1)
-    {
-        *m = rv + tv;
-        z += *m;
+    if needed.is_empty() {
+        eprintln!("error: no trial decoded within {limit} packets (check --c/--delta)");
+        std::process::exit(2);
     }
-    for m in mu.iter_mut().take(k + 1).skip(1) {
-        *m /= z;
+    needed.sort_unstable();
+    let pick =
+        |q: f64| needed[((q * needed.len() as f64).ceil() as usize).clamp(1, needed.len()) - 1];
+    let (median, p95) = (pick(0.5), pick(0.95));
+    let report = CalibrateReport {
+        k,
+        c,
+        delta,
+        systematic,
+        loss,
+        trials,
+        failures,
+        median_packets: median,
+        p95_packets: p95,
+        max_packets: needed[needed.len() - 1],
+        median_overhead: median as f64 / k as f64,
+        p95_overhead: p95 as f64 / k as f64,
+    };
+    if has_flag(args, "json") {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("report json")
+        );
+    } else {
+        println!(
+            "fountain-calibrate k={k}: median {} packets (overhead {:.3}), p95 {} ({:.3}), max {}, failures {}/{}",
+            report.median_packets,
+            report.median_overhead,
+            report.p95_packets,
+            report.p95_overhead,
+            report.max_packets,
+            failures,
+            trials
+        );
     }
+}
 
-    // в (degree, prob)
-    let mut out = Vec::with_capacity(k);
-    for (d, &p) in mu.iter().enumerate().take(k + 1).skip(1) {
-        let p32 = p as f32;
-        if p32 > 0.0 {
-            out.push((d, p32));
-        }
-    }
-    out
+/// Верхняя оценка кадра s3p-fountain-serve ('P\n' + строка jsonl) для k блоков по block_len байт:
+/// в ids не больше k номеров, каждый — не длиннее digits(k - 1) символов плюс запятая. Энкодер
+/// не гоняем: оценка нужна только для подсказки --k
+fn fountain_frame_bound(k: usize, block_len: usize) -> usize {
+    let digits = k.saturating_sub(1).to_string().len();
+    let ids = 1 + k * (digits + 1); // "[" + "n," × k, последняя запятая — это "]"
+    2 + r#"{"ids":,"body_hex":""}"#.len() + ids + 2 * block_len
 }
 
 fn pack_fountain_cmd(args: &[String]) {
@@ -933,7 +4658,8 @@ fn pack_fountain_cmd(args: &[String]) {
     let input = PathBuf::from(&args[0]);
     let out_dir = PathBuf::from(&args[1]);
 
-    let ikm_hex = require_flag(args, "ikm-hex");
+    let kdf = parse_kdf_args(args);
+    let ikm = read_ikm(args);
     let salt_hex = require_flag(args, "salt-hex");
     let aad = arg_flag(args, "aad").unwrap_or_else(|| "s3p-fountain".to_string());
 
@@ -947,16 +4673,32 @@ fn pack_fountain_cmd(args: &[String]) {
     if packets_opt.is_some() && overhead_opt.is_some() {
         panic!("use either --packets or --overhead, not both");
     }
+    let max_packet_bytes: usize = arg_flag_default(args, "max-packet-bytes", 1400usize);
+    let strict_mtu = has_flag(args, "strict-mtu");
+    let systematic = match arg_flag(args, "systematic").as_deref() {
+        None | Some("true") => true,
+        Some("false") => false,
+        Some(other) => {
+            eprintln!("error: --systematic expects true|false, got '{other}'");
+            std::process::exit(2);
+        }
+    };
 
-    let ikm = hex_decode(&ikm_hex);
     let salt = hex_decode(&salt_hex);
-    let ks = KeySchedule::derive(&ikm, &salt).expect("ks derive");
+    let ks = derive_key_schedule(&ikm, &salt, kdf.as_ref());
 
     let plain = read_all(&input);
     let (ciphertext, nonce) = ks.seal(aad.as_bytes(), &plain).expect("seal");
     let ct_len = ciphertext.len();
 
+    // partition_into_blocks в s3p-core не проверяет k: k=0 — деление на ноль, k>ct_len — пустые блоки;
+    // robust_soliton требует k>=2
+    if k < 2 || k > ct_len {
+        eprintln!("error: --k must be in 2..={ct_len} (ciphertext length), got {k}");
+        std::process::exit(2);
+    }
     let (blocks, block_len) = partition_into_blocks(&ciphertext, k);
+    log_debug!("ct_len={ct_len}, k={k}, block_len={block_len}");
 
     // robust-soliton → FountainEncoder
     let probs_vec = robust_soliton(k, c, delta);
@@ -972,9 +4714,26 @@ fn pack_fountain_cmd(args: &[String]) {
         ((ov * k as f64).ceil() as usize).max(k)
     });
 
-    // Systematic-допинг: первые k пакетов — исходные блоки степени 1
+    // Systematic-допинг: первые k пакетов — исходные блоки степени 1.
+    // --systematic=false: все пакеты из энкодера (сырые блоки не идут в предсказуемых позициях);
+    // --systematic-count=N: только первые N блоков, дальше энкодер
     let mut pkts: Vec<Packet> = Vec::with_capacity(total_packets);
-    for (i, b) in blocks.iter().enumerate().take(k) {
+    let systematic_count = match arg_flag(args, "systematic-count") {
+        Some(s) => match s.parse::<usize>() {
+            Ok(n) if n <= k && (systematic || n == 0) => n,
+            Ok(n) if n <= k => {
+                eprintln!("error: --systematic-count={n} contradicts --systematic=false");
+                std::process::exit(2);
+            }
+            _ => {
+                eprintln!("error: --systematic-count must be in 0..={k}, got '{s}'");
+                std::process::exit(2);
+            }
+        },
+        None if systematic => k,
+        None => 0,
+    };
+    for (i, b) in blocks.iter().enumerate().take(systematic_count) {
         pkts.push(Packet {
             ids: vec![i],
             body: b.clone(),
@@ -987,6 +4746,115 @@ fn pack_fountain_cmd(args: &[String]) {
         pkts.push(enc.next_packet(&blocks));
     }
 
+    // Статистика распределения — чтобы заметить патологию до отправки
+    let stats = fountain_stats(&pkts, k, systematic_count);
+    log_info!(
+        "fountain stats: packets={}, max_degree={}, degree1={:.3} ({} beyond systematic), min_coverage={}",
+        pkts.len(),
+        stats.degree_histogram.keys().next_back().copied().unwrap_or(0),
+        stats.degree1_fraction,
+        stats.degree1_beyond_systematic,
+        stats.min_coverage
+    );
+    if stats.degree1_beyond_systematic == 0 && pkts.len() > systematic_count {
+        log_warn!("no degree-1 packets beyond the systematic prefix (check --c/--delta)");
+    }
+    let stats = if has_flag(args, "stats") {
+        println!("{}", serde_json::to_string(&stats).expect("stats json"));
+        Some(stats)
+    } else {
+        None
+    };
+
+    let lines: Vec<String> = pkts
+        .iter()
+        .map(|p| {
+            let jp = JsonPkt {
+                ids: p.ids.clone(),
+                body_hex: hex_encode(&p.body),
+            };
+            serde_json::to_string(&jp).unwrap()
+        })
+        .collect();
+
+    // MTU: s3p-fountain-serve шлёт строку jsonl как есть ('P\n' + JSON, тело в hex — ×2)
+    let max_frame = 2 + lines.iter().map(|l| l.len()).max().unwrap_or(0);
+    if max_frame > max_packet_bytes {
+        // с ростом k растут и списки ids, поэтому подсказку сверяем с оценкой кадра при новом k
+        let frame_at = |kk: usize| fountain_frame_bound(kk, ct_len.div_ceil(kk));
+        let mut overhead = max_frame.saturating_sub(2 * block_len);
+        let mut try_k = k;
+        let mut hint = String::new();
+        for _ in 0..32 {
+            let fit_block = max_packet_bytes.saturating_sub(overhead) / 2;
+            if fit_block == 0 {
+                break;
+            }
+            try_k = ct_len.div_ceil(fit_block).max(try_k + 1);
+            if try_k > ct_len {
+                break;
+            }
+            let frame = frame_at(try_k);
+            if frame <= max_packet_bytes {
+                hint = format!(
+                    "; try --k={try_k} (block_len={}B, frame ≤ {frame}B)",
+                    ct_len.div_ceil(try_k)
+                );
+                break;
+            }
+            overhead = frame - 2 * ct_len.div_ceil(try_k);
+        }
+        if strict_mtu {
+            eprintln!(
+                "error: fountain frame is {max_frame} bytes (block_len={block_len}), exceeds --max-packet-bytes={max_packet_bytes}{hint}"
+            );
+            std::process::exit(2);
+        }
+        log_warn!(
+            "fountain frame is {max_frame} bytes (block_len={block_len}), exceeds --max-packet-bytes={max_packet_bytes}: datagrams will fragment or drop{hint}"
+        );
+    }
+
+    // Пробный декод до записи файлов: негодная комбинация параметров всплывёт здесь, а не у получателя
+    let self_test_loss: Option<f64> = arg_flag(args, "self-test-loss")
+        .map(|s| s.parse().expect("invalid --self-test-loss"))
+        .or(has_flag(args, "self-test").then_some(0.0));
+    if let Some(loss) = self_test_loss {
+        if !(0.0..1.0).contains(&loss) {
+            eprintln!("error: --self-test-loss must be in [0, 1), got {loss}");
+            std::process::exit(2);
+        }
+        let Some(how) = fountain_trial_decode(k, block_len, &pkts, &ciphertext) else {
+            eprintln!(
+                "error: self-test: {} packets do not decode (k={k}); raise --packets/--overhead or check --c/--delta",
+                pkts.len()
+            );
+            std::process::exit(2);
+        };
+        log_info!("self-test: all {} packets decode ({how})", pkts.len());
+        if loss > 0.0 {
+            // имитация потерь: случайное подмножество, воспроизводимое от --seed
+            let mut rng = StdRng::seed_from_u64(seed ^ 0x5E1F_7E57);
+            let kept: Vec<Packet> = copy_packets(&pkts)
+                .into_iter()
+                .filter(|_| rng.gen::<f64>() >= loss)
+                .collect();
+            let Some(how) = fountain_trial_decode(k, block_len, &kept, &ciphertext) else {
+                eprintln!(
+                    "error: self-test: {}/{} packets left after {loss} loss do not decode (k={k}); raise --overhead",
+                    kept.len(),
+                    pkts.len()
+                );
+                std::process::exit(2);
+            };
+            log_info!(
+                "self-test: {}/{} packets after {loss} loss decode ({how})",
+                kept.len(),
+                pkts.len()
+            );
+        }
+    }
+
     // Запись файлов
     fs::create_dir_all(&out_dir).expect("mkdir out_dir");
     // meta
@@ -1007,24 +4875,58 @@ fn pack_fountain_cmd(args: &[String]) {
         seed,
         c,
         delta,
+        systematic: systematic_count > 0,
+        systematic_count: Some(systematic_count),
+        stats,
+        kdf,
     };
     let meta_json = serde_json::to_vec_pretty(&meta).unwrap();
-    write_all(&out_dir.join("fountain_meta.json"), &meta_json);
+    write_atomic(&out_dir.join("fountain_meta.json"), &meta_json);
+
+    // --ct-only: вместо jsonl — шифртекст для 's3p-fountain-serve --generate' (и unpack-fountain)
+    if has_flag(args, "ct-only") {
+        write_all(&out_dir.join("recovered_ct.bin"), &ciphertext);
+        println!("Fountain ciphertext → {}", out_dir.display());
+        return;
+    }
 
     // packets.jsonl
     let mut f = fs::File::create(out_dir.join("fountain_packets.jsonl")).expect("create jsonl");
-    for p in pkts {
-        let jp = JsonPkt {
-            ids: p.ids,
-            body_hex: hex_encode(&p.body),
-        };
-        let line = serde_json::to_string(&jp).unwrap();
+    for line in lines {
         writeln!(f, "{}", line).unwrap();
     }
 
     println!("Fountain packed → {}", out_dir.display());
 }
 
+/// Ключ дедупликации пакета: sha256 от (ids по возрастанию, body)
+fn packet_key(p: &Packet) -> [u8; 32] {
+    let mut ids = p.ids.clone();
+    ids.sort_unstable();
+    let mut h = Sha256::new();
+    h.update((ids.len() as u64).to_le_bytes());
+    for id in ids {
+        h.update((id as u64).to_le_bytes());
+    }
+    h.update(&p.body);
+    h.finalize().into()
+}
+
+/// --self-test: декодируются ли пакеты обратно в шифртекст (peel, затем Гаусс).
+/// Some(имя сработавшего декодера) или None
+fn fountain_trial_decode(
+    k: usize,
+    block_len: usize,
+    packets: &[Packet],
+    ciphertext: &[u8],
+) -> Option<&'static str> {
+    let (blocks, how) = match peel_decode(k, block_len, copy_packets(packets)) {
+        Some(b) => (b, "peel"),
+        None => (gauss_decode(k, block_len, packets)?, "gauss"),
+    };
+    (join_blocks(&blocks, ciphertext.len()) == ciphertext).then_some(how)
+}
+
 fn unpack_fountain_cmd(args: &[String]) {
     if args.len() < 2 {
         usage();
@@ -1032,49 +4934,142 @@ fn unpack_fountain_cmd(args: &[String]) {
     let in_dir = PathBuf::from(&args[0]);
     let output = PathBuf::from(&args[1]);
 
-    let ikm_hex = require_flag(args, "ikm-hex");
-    let salt_hex = require_flag(args, "salt-hex");
-
-    let ikm = hex_decode(&ikm_hex);
-    let salt = hex_decode(&salt_hex);
-    let ks = KeySchedule::derive(&ikm, &salt).expect("ks derive");
+    let ikm = read_ikm(args);
+    let salt = hex_decode(&require_flag(args, "salt-hex"));
 
     // meta
     let meta_bytes = read_all(&in_dir.join("fountain_meta.json"));
     let meta: FountainMeta = serde_json::from_slice(&meta_bytes).expect("meta parse");
+    // KDF — как при упаковке (из манифеста)
+    let ks = derive_key_schedule(&ikm, &salt, meta.kdf.as_ref());
 
     // Если fetch уже собрал recovered_ct.bin — используем его напрямую
+    // (--from-packets: не доверять ему, например после упавшего fetch, и декодировать jsonl)
     let recovered_ct_path = in_dir.join("recovered_ct.bin");
-    let recovered_ct = if recovered_ct_path.exists() {
-        read_all(&recovered_ct_path)
+    let recovered_ct = if recovered_ct_path.exists() && !has_flag(args, "from-packets") {
+        let ct = read_all(&recovered_ct_path);
+        if ct.len() != meta.ct_len {
+            eprintln!(
+                "error: {} is {} bytes, fountain_meta ct_len={} (stale or partial? use --from-packets)",
+                recovered_ct_path.display(),
+                ct.len(),
+                meta.ct_len
+            );
+            std::process::exit(2);
+        }
+        ct
     } else {
-        // читаем строки jsonl → Packet (устойчиво к разным вариантам)
+        // читаем строки jsonl → Packet (устойчиво к разным вариантам) и пробуем декодировать
+        // по ходу чтения: обычно решение находится задолго до конца файла, дальше не читаем
+        let max_packets: Option<usize> = arg_flag(args, "max-packets").map(|s| {
+            s.parse().unwrap_or_else(|_| {
+                eprintln!("error: invalid --max-packets={s} (number)");
+                std::process::exit(2);
+            })
+        });
         let file = fs::File::open(in_dir.join("fountain_packets.jsonl")).expect("open jsonl");
         let reader = std::io::BufReader::new(file);
         let mut packets: Vec<Packet> = Vec::new();
-        for line in reader.lines() {
-            let l = line.unwrap();
+        // serve --loop / повторы по NACK дают точные дубли — отбрасываем их до декодирования
+        let mut seen: HashSet<[u8; 32]> = HashSet::new();
+        let mut duplicates = 0usize;
+        // битая строка (JSON, hex/base64) или пакет не под эту мету (тело ≠ block_len, пустые,
+        // повторные или вне 0..k ids) сорвали бы декод целиком — пропускаем с подсчётом
+        let mut malformed = 0usize;
+        // peel_decode пакетный (съедает Vec), поэтому попытки — с шагом k/16, а не на каждый пакет
+        let try_step = (meta.k / 16).max(1);
+        let mut next_try = meta.k;
+        let mut decoded: Option<Vec<Vec<u8>>> = None;
+        let mut hit_cap = false;
+        for (lineno, line) in reader.lines().enumerate() {
+            let l = match line {
+                Ok(l) => l,
+                // не UTF-8 — такая же битая строка; прочие ошибки чтения к пакетам не относятся
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    malformed += 1;
+                    log_debug!("skipping non-UTF-8 jsonl line {}", lineno + 1);
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("error: read fountain_packets.jsonl: {e}");
+                    std::process::exit(2);
+                }
+            };
             if l.trim().is_empty() {
                 continue;
             }
-            let parsed: WirePacketAny =
-                serde_json::from_str(&l).expect("jsonl parse (WirePacketAny)");
-            packets.push(decode_wire_packet(parsed));
+            let Some(pkt) = serde_json::from_str::<WirePacketAny>(&l)
+                .ok()
+                .and_then(decode_wire_packet)
+            else {
+                malformed += 1;
+                log_debug!("skipping unparsable jsonl line {}", lineno + 1);
+                continue;
+            };
+            if !packet_fits(&pkt.ids, pkt.body.len(), meta.k, meta.block_len) {
+                malformed += 1;
+                log_debug!(
+                    "skipping packet ids={:?} body={}B (k={}, block_len={})",
+                    pkt.ids,
+                    pkt.body.len(),
+                    meta.k,
+                    meta.block_len
+                );
+                continue;
+            }
+            if !seen.insert(packet_key(&pkt)) {
+                duplicates += 1;
+                continue;
+            }
+            packets.push(pkt);
+            if packets.len() >= next_try {
+                next_try += try_step;
+                decoded = peel_decode(meta.k, meta.block_len, copy_packets(&packets));
+                if decoded.is_some() {
+                    break;
+                }
+            }
+            if max_packets.is_some_and(|cap| packets.len() >= cap) {
+                hit_cap = true;
+                break;
+            }
         }
-
-        if packets.len() < meta.k {
-            panic!(
-                "insufficient packets: have {}, need at least {}",
-                packets.len(),
-                meta.k
+        if malformed > 0 {
+            log_warn!(
+                "skipped {malformed} malformed line(s) or packet(s) not matching fountain_meta (k={}, block_len={})",
+                meta.k,
+                meta.block_len
             );
         }
-
-        // peel decode
-        let decoded = peel_decode(meta.k, meta.block_len, packets)
-            .expect("peel decode failed (need more packets)");
+        if duplicates > 0 {
+            log_info!(
+                "dropped {duplicates} duplicate packets ({} unique)",
+                packets.len()
+            );
+        }
+        // последняя попытка — по всему прочитанному (EOF или --max-packets): peel, а если он
+        // застрял — Гаусс; набор, который принимает --self-test, распаковывается и здесь
+        let unique = packets.len();
+        if decoded.is_none() && unique >= meta.k {
+            decoded = decode(meta.k, meta.block_len, &packets);
+        }
+        let Some(decoded) = decoded else {
+            eprintln!(
+                "error: decode failed with {unique} unique packets (k={}){}",
+                meta.k,
+                if hit_cap {
+                    "; --max-packets reached"
+                } else {
+                    "; need more packets"
+                }
+            );
+            std::process::exit(2);
+        };
+        log_debug!("decoded after {unique} unique packets (k={})", meta.k);
         join_blocks(&decoded, meta.ct_len)
     };
+    // join_blocks/recovered_ct.bin не удлиняют данные: короче ct_len — значит, мета подделана или файл обрезан
+    check_ct_len("fountain_meta", meta.ct_len, recovered_ct.len());
 
     // AEAD open
     let mut nonce = [0u8; 24];
@@ -1093,28 +5088,105 @@ fn unpack_fountain_cmd(args: &[String]) {
 
 //==================== Сервисные: keygen ====================//
 
+// DER-префиксы Ed25519 (RFC 8410): SubjectPublicKeyInfo и PKCS#8 v1 — длины фиксированы,
+// поэтому ключ просто дописывается в конец
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+const ED25519_PKCS8_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+fn pem_encode(label: &str, der: &[u8]) -> String {
+    let b64 = general_purpose::STANDARD.encode(der);
+    let mut out = format!("-----BEGIN {label}-----\n");
+    for line in b64.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(line).unwrap());
+        out.push('\n');
+    }
+    out.push_str(&format!("-----END {label}-----\n"));
+    out
+}
+
 fn keygen_cmd(args: &[String]) {
     let out_dir = PathBuf::from(require_flag(args, "out-dir"));
+    let format = arg_flag(args, "format").unwrap_or_else(|| "hex".to_string());
+    if !matches!(format.as_str(), "hex" | "pem" | "jwk") {
+        eprintln!("error: unknown --format={format} (expected hex|pem|jwk)");
+        std::process::exit(2);
+    }
+    let encoding = arg_flag(args, "encoding").unwrap_or_else(|| "hex".to_string());
+    let (ext, encode): (&str, fn(&[u8]) -> String) = match encoding.as_str() {
+        "hex" => ("hex", |b| hex_encode(b)),
+        "base64" => ("b64", |b| general_purpose::STANDARD.encode(b)),
+        other => {
+            eprintln!("error: unknown --encoding={other} (expected hex|base64)");
+            std::process::exit(2);
+        }
+    };
     fs::create_dir_all(&out_dir).expect("mkdir out-dir");
 
-    // генерируем случайный секрет (32 байта), делаем из него ключ
-    let mut sk_bytes = [0u8; 32];
-    OsRng.fill_bytes(&mut sk_bytes);
+    // секрет (32 байта): из --seed-hex (восстановление из бэкапа) или случайный
+    let sk_bytes = match arg_flag(args, "seed-hex") {
+        Some(seed_hex) => parse_secret32_hex(&seed_hex, "seed-hex"),
+        None => {
+            let mut b = [0u8; 32];
+            OsRng.fill_bytes(&mut b);
+            b
+        }
+    };
     let sk = SigningKey::from_bytes(&sk_bytes);
     let pk = sk.verifying_key();
 
-    write_all(&out_dir.join("sk.hex"), hex_encode(&sk_bytes).as_bytes());
     write_all(
-        &out_dir.join("pk.hex"),
-        hex_encode(pk.as_bytes()).as_bytes(),
+        &out_dir.join(format!("sk.{ext}")),
+        encode(&sk_bytes).as_bytes(),
+    );
+    write_all(
+        &out_dir.join(format!("pk.{ext}")),
+        encode(pk.as_bytes()).as_bytes(),
+    );
+    // sk/pk в hex или base64 пишутся всегда (их читают pod-sign и остальные команды), формат — дополнительно
+    match format.as_str() {
+        "pem" => {
+            let spki = [&ED25519_SPKI_PREFIX[..], pk.as_bytes()].concat();
+            let pkcs8 = [&ED25519_PKCS8_PREFIX[..], &sk_bytes[..]].concat();
+            write_all(
+                &out_dir.join("pk.pem"),
+                pem_encode("PUBLIC KEY", &spki).as_bytes(),
+            );
+            write_all(
+                &out_dir.join("sk.pem"),
+                pem_encode("PRIVATE KEY", &pkcs8).as_bytes(),
+            );
+        }
+        "jwk" => {
+            let b64url = |b: &[u8]| general_purpose::URL_SAFE_NO_PAD.encode(b);
+            let x = b64url(pk.as_bytes());
+            let pk_jwk = serde_json::json!({ "kty": "OKP", "crv": "Ed25519", "x": x });
+            let sk_jwk = serde_json::json!({ "kty": "OKP", "crv": "Ed25519", "x": x, "d": b64url(&sk_bytes) });
+            write_all(
+                &out_dir.join("pk.jwk"),
+                &serde_json::to_vec_pretty(&pk_jwk).unwrap(),
+            );
+            write_all(
+                &out_dir.join("sk.jwk"),
+                &serde_json::to_vec_pretty(&sk_jwk).unwrap(),
+            );
+        }
+        _ => {}
+    }
+    println!(
+        "keypair written → {} (format={format}, encoding={encoding})",
+        out_dir.display()
     );
-    println!("keypair written → {}", out_dir.display());
 }
 
 //==================== main ====================//
 
 fn main() {
     let mut args: Vec<String> = env::args().skip(1).collect();
+    take_verbosity(&mut args);
     if args.is_empty() {
         usage();
     }
@@ -1125,16 +5197,177 @@ fn main() {
     match cmd.as_str() {
         "pack" => pack_cmd(&args),
         "unpack" => unpack_cmd(&args),
+        "rekey" => rekey_cmd(&args),
+        "rebuild-manifest" => rebuild_manifest_cmd(&args),
+        "pack-batch" => pack_batch_cmd(&args),
+        "unpack-batch" => unpack_batch_cmd(&args),
         "pack-fountain" => pack_fountain_cmd(&args),
         "unpack-fountain" => unpack_fountain_cmd(&args),
+        "fountain-calibrate" => fountain_calibrate_cmd(&args),
         "pack-stream" => pack_stream_cmd(&args),
         "unpack-stream" => unpack_stream_cmd(&args),
+        "extract-stream" => extract_stream_cmd(&args),
         "verify-pack" => verify_pack_cmd(&args),
         "verify-pack-stream" => verify_pack_stream_cmd(&args),
+        "chunk-proof" => chunk_proof_cmd(&args),
+        "scrub" => scrub_cmd(&args),
+        "chunk-verify" => chunk_verify_cmd(&args),
+        "merkle-proof" => merkle_proof_cmd(&args),
+        "merkle-verify" => merkle_verify_cmd(&args),
         "keygen" => keygen_cmd(&args),
         "pod-sign" => pod_sign_cmd(&args),
         "pod-verify" => pod_verify_cmd(&args),
         "pod-aggregate" => pod_aggregate_cmd(&args),
+        "pod-aggregate-verify" => pod_aggregate_verify_cmd(&args),
         _ => usage(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ct_eq_agrees_with_eq() {
+        let a = [7u8; 32];
+        let mut b = a;
+        assert!(ct_eq(&a, &b));
+        b[31] ^= 1;
+        assert!(!ct_eq(&a, &b));
+        assert!(!ct_eq(&a, &a[..31]));
+        assert!(ct_eq(b"", b""));
+    }
+
+    #[test]
+    fn nonces_from_a_user_base_never_collide() {
+        // младшие байты базы ненулевые — как у произвольного --nonce-base-hex
+        let mut base = [0u8; 24];
+        for (i, b) in base.iter_mut().enumerate() {
+            *b = 0xA5 ^ (i as u8).wrapping_mul(37);
+        }
+        let idxs = (0..4096u64).chain([1 << 32, (1 << 63) - 1, 1 << 63, u64::MAX]);
+        let mut seen = HashSet::new();
+        for idx in idxs {
+            let n = derive_nonce_from_base(&base, idx);
+            assert_eq!(n[..16], base[..16]);
+            assert!(seen.insert(n), "idx {idx} repeats a nonce");
+        }
+    }
+
+    // хранилище шардов в памяти: pack/unpack не завязаны на fs
+    #[derive(Default)]
+    struct MemShardStore(BTreeMap<usize, Vec<u8>>);
+
+    impl ShardStore for MemShardStore {
+        fn get(&self, index: usize) -> Option<Vec<u8>> {
+            self.0.get(&index).cloned()
+        }
+        fn put(&mut self, index: usize, bytes: &[u8]) {
+            self.0.insert(index, bytes.to_vec());
+        }
+        fn list(&self) -> Vec<usize> {
+            self.0.keys().copied().collect()
+        }
+        fn describe(&self, index: usize) -> String {
+            format!("mem:{index}")
+        }
+    }
+
+    #[test]
+    fn in_memory_store_round_trips_a_small_pack() {
+        let layout = ShardLayout {
+            data_shards: 3,
+            parity_shards: 2,
+            cas: false,
+            shard_trailer: true,
+        };
+        let ks = derive_key_schedule(&[7u8; 32], b"mem-salt", None);
+        let plain: Vec<u8> = (0..5000u32).map(|i| (i * 31 % 251) as u8).collect();
+        let (sealed, shards) = seal_shards(&ks, b"aad", &plain, &layout);
+        let mut store = MemShardStore::default();
+        put_shards(&mut store, &shards, layout.shard_trailer);
+        assert_eq!(store.list(), [0, 1, 2, 3, 4]);
+
+        let mf = Manifest {
+            version: 1,
+            scid: sealed.scid,
+            commit: sealed.commit,
+            aad: "aad".to_string(),
+            nonce_hex: hex_encode(&sealed.nonce),
+            ct_len: sealed.ct_len,
+            data_shards: 3,
+            parity_shards: 2,
+            file_name: "mem.bin".to_string(),
+            aad_hex: None,
+            kdf: None,
+            shard_digests: sealed.shard_digests,
+            salt_hex: None,
+            bind_context: None,
+            placement: None,
+            shard_trailer: true,
+            shard_leaves: Some(sealed.shard_leaves),
+            batch: None,
+        };
+        // минус один data-шард: восстанавливает RS
+        store.0.remove(&1);
+        let ct = recover_ciphertext(&mf, &store);
+        assert_eq!(ct.len(), mf.ct_len);
+        let opened = ks.open(b"aad", &sealed.nonce, &ct).expect("open");
+        assert_eq!(opened[..plain.len()], plain[..]);
+    }
+
+    #[test]
+    fn auto_chunk_size_stays_in_range_for_representative_files() {
+        for (size, want) in [
+            (0usize, AUTO_CHUNK_MIN),
+            (10 << 20, AUTO_CHUNK_MIN),
+            (200 << 20, 212_992),
+            (1 << 30, 1_077_248),
+            (100 << 30, AUTO_CHUNK_MAX),
+        ] {
+            let c = auto_chunk_size(size);
+            assert_eq!(c, want, "size {size}");
+            assert!((AUTO_CHUNK_MIN..=AUTO_CHUNK_MAX).contains(&c));
+            assert_eq!(c % 4096, 0);
+            // без упора в пределы — не больше AUTO_CHUNKS чанков
+            if c > AUTO_CHUNK_MIN && c < AUTO_CHUNK_MAX {
+                assert!(size.div_ceil(c) <= AUTO_CHUNKS, "size {size}");
+            }
+        }
+    }
+
+    #[test]
+    fn fountain_frame_bound_covers_a_packet_of_every_block() {
+        // худший случай — пакет степени k: все номера 0..k
+        for k in [2usize, 9, 10, 100, 1000] {
+            let jp = JsonPkt {
+                ids: (0..k).collect(),
+                body_hex: hex_encode(&[0u8; 7]),
+            };
+            let frame = 2 + serde_json::to_string(&jp).unwrap().len();
+            let bound = fountain_frame_bound(k, 7);
+            assert!(frame <= bound, "k={k}: {frame} > {bound}");
+            assert!(bound - frame <= k, "k={k}: bound too loose");
+        }
+    }
+
+    #[test]
+    fn write_atomic_failure_before_rename_keeps_the_old_file() {
+        let dir = std::env::temp_dir().join(format!("s3p-write-atomic-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let p = dir.join("manifest.json");
+        fs::write(&p, b"old").unwrap();
+
+        // <path>.tmp занят каталогом — запись срывается до rename
+        fs::create_dir(dir.join("manifest.json.tmp")).unwrap();
+        assert!(std::panic::catch_unwind(|| write_atomic(&p, b"new")).is_err());
+        assert_eq!(fs::read(&p).unwrap(), b"old");
+
+        fs::remove_dir(dir.join("manifest.json.tmp")).unwrap();
+        write_atomic(&p, b"new");
+        assert_eq!(fs::read(&p).unwrap(), b"new");
+        assert!(!dir.join("manifest.json.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
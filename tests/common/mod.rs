@@ -0,0 +1,142 @@
+//! Общие помощники CLI-тестов: временные каталоги, запуск бинарей, типовые паки.
+#![allow(dead_code)] // каждый tests/*.rs берёт своё подмножество
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+pub const IKM: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+pub const IKM2: &str = "2222222222222222222222222222222222222222222222222222222222222222";
+pub const SALT: &str = "0102030405060708";
+pub const SK: &str = "3333333333333333333333333333333333333333333333333333333333333333";
+
+/// Временный каталог, удаляется при drop
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(tag: &str) -> Self {
+        static N: AtomicUsize = AtomicUsize::new(0);
+        let p = std::env::temp_dir().join(format!(
+            "s3p-cli-test-{tag}-{}-{}",
+            std::process::id(),
+            N.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&p);
+        fs::create_dir_all(&p).expect("create temp dir");
+        Self(p)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Путь внутри каталога (строкой — для аргументов CLI)
+    pub fn join(&self, name: &str) -> String {
+        self.0.join(name).display().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn cli() -> &'static str {
+    env!("CARGO_BIN_EXE_s3p-cli")
+}
+
+pub fn run_bin(bin: &str, args: &[&str]) -> Output {
+    Command::new(bin).args(args).output().expect("spawn")
+}
+
+pub fn run(args: &[&str]) -> Output {
+    run_bin(cli(), args)
+}
+
+pub fn stderr(o: &Output) -> String {
+    String::from_utf8_lossy(&o.stderr).into_owned()
+}
+
+pub fn stdout(o: &Output) -> String {
+    String::from_utf8_lossy(&o.stdout).into_owned()
+}
+
+/// Успешный запуск; stdout
+pub fn ok(args: &[&str]) -> String {
+    let o = run(args);
+    assert!(
+        o.status.success(),
+        "{args:?} failed ({:?}):\n{}",
+        o.status.code(),
+        stderr(&o)
+    );
+    stdout(&o)
+}
+
+/// Запуск, обязанный завершиться с кодом `code`; stderr
+pub fn fails(args: &[&str], code: i32) -> String {
+    let o = run(args);
+    assert_eq!(
+        o.status.code(),
+        Some(code),
+        "{args:?}: expected exit {code}\nstdout:\n{}\nstderr:\n{}",
+        stdout(&o),
+        stderr(&o)
+    );
+    stderr(&o)
+}
+
+/// Детерминированные «случайные» байты
+pub fn sample(len: usize, seed: u8) -> Vec<u8> {
+    let mut x = 0x9E37_79B9u32 ^ seed as u32;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect()
+}
+
+pub fn write_sample(dir: &TempDir, name: &str, len: usize) -> String {
+    let p = dir.join(name);
+    fs::write(&p, sample(len, len as u8)).expect("write sample");
+    p
+}
+
+/// RS-пак 4+2 из `input` в `out` с типовым ключом
+pub fn pack(input: &str, out: &str, extra: &[&str]) {
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let mut args = vec!["pack", input, out, "--data=4", "--parity=2", &ikm, &salt];
+    args.extend_from_slice(extra);
+    ok(&args);
+}
+
+pub fn unpack(dir: &str, out: &str, extra: &[&str]) -> Output {
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let mut args = vec!["unpack", dir, out, &ikm, &salt];
+    args.extend_from_slice(extra);
+    run(&args)
+}
+
+pub fn read_json(path: &str) -> serde_json::Value {
+    serde_json::from_slice(&fs::read(path).expect("read json")).expect("parse json")
+}
+
+pub fn write_json(path: &str, v: &serde_json::Value) {
+    fs::write(path, serde_json::to_vec_pretty(v).unwrap()).expect("write json");
+}
+
+/// Порча одного байта файла
+pub fn flip_byte(path: &str, at: usize) {
+    let mut b = fs::read(path).expect("read");
+    b[at] ^= 0x5A;
+    fs::write(path, b).expect("write");
+}
@@ -0,0 +1,686 @@
+//! Fountain-профиль: pack-fountain / unpack-fountain, serve + fetch по UDP на localhost
+
+mod common;
+
+use common::*;
+use std::{
+    fs,
+    net::UdpSocket,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+fn serve_bin() -> &'static str {
+    env!("CARGO_BIN_EXE_s3p-fountain-serve")
+}
+
+fn fetch_bin() -> &'static str {
+    env!("CARGO_BIN_EXE_s3p-fountain-fetch")
+}
+
+/// Свободный UDP-порт на localhost (сокет сразу закрывается)
+fn free_addr() -> String {
+    let s = UdpSocket::bind("127.0.0.1:0").unwrap();
+    s.local_addr().unwrap().to_string()
+}
+
+fn spawn(bin: &str, args: &[&str]) -> Child {
+    Command::new(bin)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn")
+}
+
+/// Дождаться выхода fetch (с запасом по времени) и вернуть (код, stderr)
+fn wait_fetch(mut fetch: Child, limit: Duration) -> (Option<i32>, String) {
+    let deadline = Instant::now() + limit;
+    loop {
+        if let Some(status) = fetch.try_wait().unwrap() {
+            let out = fetch.wait_with_output().unwrap();
+            return (
+                status.code(),
+                String::from_utf8_lossy(&out.stderr).into_owned(),
+            );
+        }
+        assert!(Instant::now() < deadline, "fetch did not finish");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// pack-fountain входа `len` байт в `<t>/f`; доп. флаги — например --ct-only
+fn pack_fountain(t: &TempDir, len: usize, k: usize, extra: &[&str]) -> (String, String) {
+    let input = write_sample(t, "in.bin", len);
+    let out = t.join("f");
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let k = format!("--k={k}");
+    let mut args = vec!["pack-fountain", &input, &out, &ikm, &salt, &k];
+    args.extend_from_slice(extra);
+    ok(&args);
+    (input, out)
+}
+
+#[test]
+fn generated_packets_decode_on_a_fetch_instance() {
+    let t = TempDir::new("serve-generate");
+    let (_, dir) = pack_fountain(&t, 40_000, 24, &["--ct-only", "--overhead=1.6"]);
+    let addr = free_addr();
+    let got = t.join("got");
+    let bind = format!("--bind={addr}");
+    let fetch = spawn(fetch_bin(), &[&got, &bind, "--timeout-ms=15000"]);
+    std::thread::sleep(Duration::from_millis(300));
+
+    let to = format!("--to={addr}");
+    let mut serve = spawn(
+        serve_bin(),
+        &[&dir, &to, "--generate", "--loop", "--pps=2000"],
+    );
+    let (code, err) = wait_fetch(fetch, Duration::from_secs(20));
+    serve.kill().ok();
+    serve.wait().ok();
+    assert_eq!(code, Some(0), "{err}");
+    assert!(err.contains("DECODED"), "{err}");
+    assert_eq!(
+        fs::read(t.join("got/recovered_ct.bin")).unwrap(),
+        fs::read(t.join("f/recovered_ct.bin")).unwrap()
+    );
+}
+
+/// Кадры M/P в fetch напрямую, без serve
+fn send_frames(addr: &str, frames: &[(u8, String)]) {
+    let s = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for (kind, json) in frames {
+        let mut f = vec![*kind, b'\n'];
+        f.extend_from_slice(json.as_bytes());
+        s.send_to(&f, addr).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn meta(k: usize, block_len: usize, ct_len: usize) -> (u8, String) {
+    (
+        b'M',
+        format!(r#"{{"k":{k},"block_len":{block_len},"ct_len":{ct_len}}}"#),
+    )
+}
+
+/// P-кадр: XOR блоков `ids`
+fn packet(blocks: &[&[u8]], ids: &[usize]) -> (u8, String) {
+    let mut body = vec![0u8; blocks[0].len()];
+    for &i in ids {
+        for (a, b) in body.iter_mut().zip(blocks[i]) {
+            *a ^= b;
+        }
+    }
+    let ids: Vec<String> = ids.iter().map(|i| i.to_string()).collect();
+    (
+        b'P',
+        format!(
+            r#"{{"ids":[{}],"body_hex":"{}"}}"#,
+            ids.join(","),
+            hex(&body)
+        ),
+    )
+}
+
+fn hex(b: &[u8]) -> String {
+    b.iter().map(|x| format!("{x:02x}")).collect()
+}
+
+fn fetch_frames(t: &TempDir, frames: &[(u8, String)]) -> (Option<i32>, String) {
+    let addr = free_addr();
+    let bind = format!("--bind={addr}");
+    let fetch = spawn(fetch_bin(), &[&t.join("got"), &bind, "--timeout-ms=3000"]);
+    std::thread::sleep(Duration::from_millis(300));
+    send_frames(&addr, frames);
+    wait_fetch(fetch, Duration::from_secs(10))
+}
+
+#[test]
+fn fetch_drops_packets_of_a_previous_meta() {
+    let t = TempDir::new("fetch-meta-change");
+    let old: [&[u8]; 2] = [b"OLD0", b"OLD1"];
+    let new: [&[u8]; 2] = [b"new0", b"new1"];
+    // пакет старой меты по форме подходит и новой; без сброса ранг 2 набрался бы
+    // на new[1] и декод склеил бы OLD0 + new1
+    let (code, err) = fetch_frames(
+        &t,
+        &[
+            meta(2, 4, 8),
+            packet(&old, &[0]),
+            meta(2, 4, 7),
+            packet(&new, &[1]),
+            packet(&new, &[0, 1]),
+        ],
+    );
+    assert_eq!(code, Some(0), "{err}");
+    assert!(err.contains("dropping 1 packet(s)"), "{err}");
+    assert_eq!(
+        fs::read(t.join("got/recovered_ct.bin")).unwrap(),
+        b"new0new"
+    );
+    let jsonl = fs::read_to_string(t.join("got/fountain_packets.jsonl")).unwrap();
+    assert_eq!(jsonl.lines().count(), 2, "{jsonl}");
+}
+
+#[test]
+fn fetch_attempts_decode_once_at_full_rank() {
+    let t = TempDir::new("fetch-rank");
+    let b: [&[u8]; 4] = [b"aaaa", b"bbbb", b"cccc", b"dddd"];
+    let (code, err) = fetch_frames(
+        &t,
+        &[
+            meta(4, 4, 16),
+            packet(&b, &[0]),
+            packet(&b, &[0]), // повтор
+            packet(&b, &[0, 1]),
+            packet(&b, &[1]), // = [0] ^ [0, 1]
+            packet(&b, &[1, 2, 3]),
+            packet(&b, &[2, 3]),
+            packet(&b, &[2]), // ранг 4 только здесь
+        ],
+    );
+    assert_eq!(code, Some(0), "{err}");
+    assert!(
+        err.contains("dependent=3, inconsistent=0, decode_attempts=1"),
+        "{err}"
+    );
+    assert_eq!(
+        fs::read(t.join("got/recovered_ct.bin")).unwrap(),
+        b"aaaabbbbccccdddd"
+    );
+}
+
+/// Блоки шифртекста из `<dir>/recovered_ct.bin` (--ct-only), добитые нулями до k·block_len
+fn ct_blocks(dir: &str) -> Vec<Vec<u8>> {
+    let meta = read_json(&format!("{dir}/fountain_meta.json"));
+    let k = meta["k"].as_u64().unwrap() as usize;
+    let bl = meta["block_len"].as_u64().unwrap() as usize;
+    let mut ct = fs::read(format!("{dir}/recovered_ct.bin")).unwrap();
+    ct.resize(k * bl, 0);
+    ct.chunks(bl).map(|c| c.to_vec()).collect()
+}
+
+/// Строка jsonl: XOR блоков `ids`
+fn jsonl_line(blocks: &[Vec<u8>], ids: &[usize]) -> String {
+    let refs: Vec<&[u8]> = blocks.iter().map(|b| b.as_slice()).collect();
+    packet(&refs, ids).1
+}
+
+/// fountain_packets.jsonl из `lines`; recovered_ct.bin убирается, чтобы unpack читал пакеты
+fn write_packets(dir: &str, lines: &[String]) {
+    let _ = fs::remove_file(format!("{dir}/recovered_ct.bin"));
+    fs::write(
+        format!("{dir}/fountain_packets.jsonl"),
+        lines.join("\n") + "\n",
+    )
+    .unwrap();
+}
+
+fn unpack_fountain(dir: &str, out: &str) -> std::process::Output {
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    run(&["unpack-fountain", dir, out, &ikm, &salt])
+}
+
+#[test]
+fn unpack_fountain_falls_back_to_gauss_when_peel_stalls() {
+    let t = TempDir::new("unpack-gauss");
+    let (input, dir) = pack_fountain(&t, 3000, 5, &["--ct-only"]);
+    let b = ct_blocks(&dir);
+    // ни одного пакета степени 1 — peel не стартует; пары + «все пять» при нечётном k дают ранг 5
+    let mut lines: Vec<String> = (0..4).map(|i| jsonl_line(&b, &[i, i + 1])).collect();
+    lines.push(jsonl_line(&b, &[0, 1, 2, 3, 4]));
+    write_packets(&dir, &lines);
+
+    let out = t.join("out.bin");
+    let o = unpack_fountain(&dir, &out);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(fs::read(&out).unwrap(), fs::read(&input).unwrap());
+
+    // ранг 4 — не решается и Гауссом: код 2, а не мусор на выходе
+    write_packets(&dir, &lines[..4]);
+    let o = unpack_fountain(&dir, &t.join("out2.bin"));
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+}
+
+#[test]
+fn unpack_fountain_skips_malformed_lines_mis_sized_and_duplicate_ids() {
+    let t = TempDir::new("unpack-malformed");
+    let (input, dir) = pack_fountain(&t, 3000, 5, &["--ct-only"]);
+    let b = ct_blocks(&dir);
+    let bl = b[0].len();
+    let mut lines = vec![
+        "not json".to_string(),
+        format!(r#"{{"ids":[0],"body_hex":"{}"}}"#, "zz".repeat(bl)),
+        r#"{"ids":[0],"body_b64":"!!!"}"#.to_string(),
+        // [1, 1] — XOR блока с самим собой: нулевое тело, которое сорвало бы peel
+        format!(r#"{{"ids":[1,1],"body_hex":"{}"}}"#, "00".repeat(bl)),
+        format!(r#"{{"ids":[2],"body_hex":"{}"}}"#, "00".repeat(bl - 1)),
+        format!(r#"{{"ids":[5],"body_hex":"{}"}}"#, "00".repeat(bl)),
+    ];
+    lines.extend((0..5).map(|i| jsonl_line(&b, &[i])));
+    write_packets(&dir, &lines);
+    // и строка не в UTF-8
+    let jsonl = format!("{dir}/fountain_packets.jsonl");
+    let mut raw = fs::read(&jsonl).unwrap();
+    raw.splice(0..0, b"\xff\xfe{\n".iter().copied());
+    fs::write(&jsonl, raw).unwrap();
+
+    let out = t.join("out.bin");
+    let o = unpack_fountain(&dir, &out);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert!(stderr(&o).contains("skipped 7 malformed"), "{}", stderr(&o));
+    assert_eq!(fs::read(&out).unwrap(), fs::read(&input).unwrap());
+}
+
+#[test]
+fn fully_non_systematic_stream_decodes() {
+    let t = TempDir::new("non-systematic");
+    let (input, dir) = pack_fountain(&t, 20_000, 16, &["--systematic=false", "--overhead=2.0"]);
+    let meta = read_json(&format!("{dir}/fountain_meta.json"));
+    assert_eq!(meta["systematic"], false);
+
+    // префикса [0], [1], … [k-1] нет
+    let jsonl = fs::read_to_string(format!("{dir}/fountain_packets.jsonl")).unwrap();
+    let prefix: Vec<Vec<u64>> = jsonl
+        .lines()
+        .take(16)
+        .map(|l| {
+            let v: serde_json::Value = serde_json::from_str(l).unwrap();
+            v["ids"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|x| x.as_u64().unwrap())
+                .collect()
+        })
+        .collect();
+    assert!(
+        prefix.iter().enumerate().any(|(i, ids)| ids != &[i as u64]),
+        "{prefix:?}"
+    );
+
+    let out = t.join("out.bin");
+    let o = unpack_fountain(&dir, &out);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(fs::read(&out).unwrap(), fs::read(&input).unwrap());
+}
+
+#[test]
+fn unpack_fountain_drops_exact_duplicates_before_decoding() {
+    let t = TempDir::new("unpack-dedup");
+    let (input, dir) = pack_fountain(&t, 3000, 5, &["--ct-only"]);
+    let b = ct_blocks(&dir);
+    // набор только для Гаусса (peel не стартует) — читается до EOF; каждая строка дважды
+    let mut unique: Vec<String> = (0..4).map(|i| jsonl_line(&b, &[i, i + 1])).collect();
+    unique.push(jsonl_line(&b, &[0, 1, 2, 3, 4]));
+    let lines: Vec<String> = unique.iter().flat_map(|l| [l.clone(), l.clone()]).collect();
+    write_packets(&dir, &lines);
+
+    let out = t.join("out.bin");
+    let o = unpack_fountain(&dir, &out);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert!(
+        stderr(&o).contains("dropped 5 duplicate packets (5 unique)"),
+        "{}",
+        stderr(&o)
+    );
+    assert_eq!(fs::read(&out).unwrap(), fs::read(&input).unwrap());
+}
+
+fn pack_fountain_run(input: &str, out: &str, k: &str, extra: &[&str]) -> std::process::Output {
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let k = format!("--k={k}");
+    let mut args = vec!["pack-fountain", input, out, &ikm, &salt, &k];
+    args.extend_from_slice(extra);
+    run(&args)
+}
+
+#[test]
+fn mtu_guard_warns_for_undersized_k_and_its_hint_fits() {
+    let t = TempDir::new("fountain-mtu");
+    let input = write_sample(&t, "in.bin", 40_000);
+
+    let o = pack_fountain_run(&input, &t.join("a"), "4", &[]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    let err = stderr(&o);
+    assert!(err.contains("exceeds --max-packet-bytes=1400"), "{err}");
+    let hint = err.split("try --k=").nth(1).expect("hint");
+    let k: String = hint.chars().take_while(char::is_ascii_digit).collect();
+
+    // подсказанный --k укладывается в кадр
+    let o = pack_fountain_run(&input, &t.join("b"), &k, &["--strict-mtu"]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert!(!stderr(&o).contains("exceeds"), "{}", stderr(&o));
+
+    // --strict-mtu: ошибка и ничего не записано
+    let o = pack_fountain_run(&input, &t.join("c"), "4", &["--strict-mtu"]);
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    assert!(!t.path().join("c/fountain_meta.json").exists());
+}
+
+#[test]
+fn k_is_validated_against_the_ciphertext_length() {
+    let t = TempDir::new("fountain-k");
+    let input = write_sample(&t, "in.bin", 100);
+    let ct_len = 100 + 16;
+    for k in ["0".to_string(), "1".to_string(), (ct_len + 1).to_string()] {
+        let o = pack_fountain_run(&input, &t.join(&format!("k{k}")), &k, &[]);
+        assert_eq!(o.status.code(), Some(2), "k={k}: {}", stderr(&o));
+        assert!(
+            stderr(&o).contains(&format!("--k must be in 2..={ct_len}")),
+            "{}",
+            stderr(&o)
+        );
+        assert!(!t.path().join(format!("k{k}/fountain_meta.json")).exists());
+    }
+
+    // граница сверху: блок в один байт
+    let dir = t.join("kmax");
+    let o = pack_fountain_run(&input, &dir, &ct_len.to_string(), &[]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(
+        read_json(&format!("{dir}/fountain_meta.json"))["block_len"],
+        1
+    );
+    let out = t.join("out.bin");
+    let o = unpack_fountain(&dir, &out);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(fs::read(&out).unwrap(), fs::read(&input).unwrap());
+}
+
+#[test]
+fn unpack_fountain_stops_reading_once_decoded() {
+    let t = TempDir::new("unpack-early");
+    let (input, dir) = pack_fountain(&t, 3000, 5, &["--ct-only"]);
+    let b = ct_blocks(&dir);
+    // решение — в первых k строках; хвост из 20000 строк, прочитай его декодер, дал бы «malformed»
+    let mut lines: Vec<String> = (0..5).map(|i| jsonl_line(&b, &[i])).collect();
+    lines.extend((0..20_000).map(|_| "not json".to_string()));
+    write_packets(&dir, &lines);
+
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let out = t.join("out.bin");
+    let o = run(&["unpack-fountain", &dir, &out, &ikm, &salt, "-v"]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    let err = stderr(&o);
+    assert!(err.contains("decoded after 5 unique packets"), "{err}");
+    assert!(!err.contains("malformed"), "{err}");
+    assert_eq!(fs::read(&out).unwrap(), fs::read(&input).unwrap());
+
+    // --max-packets ниже k: остановка с понятной ошибкой
+    let o = run(&[
+        "unpack-fountain",
+        &dir,
+        &t.join("o2.bin"),
+        &ikm,
+        &salt,
+        "--max-packets=3",
+    ]);
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    assert!(
+        stderr(&o).contains("--max-packets reached"),
+        "{}",
+        stderr(&o)
+    );
+
+    // не число — код 2, а не паника
+    let err = fails(
+        &[
+            "unpack-fountain",
+            &dir,
+            &t.join("o3.bin"),
+            &ikm,
+            &salt,
+            "--max-packets=1O",
+        ],
+        2,
+    );
+    assert!(err.contains("invalid --max-packets=1O"), "{err}");
+    assert!(!err.contains("panicked"), "{err}");
+}
+
+#[test]
+fn empty_input_round_trips_to_an_empty_file() {
+    let t = TempDir::new("fountain-empty");
+    // шифртекст — один 16-байтный тег
+    let (_, dir) = pack_fountain(&t, 0, 2, &[]);
+    let meta = read_json(&format!("{dir}/fountain_meta.json"));
+    assert_eq!(
+        (meta["size_bytes"].as_u64(), meta["ct_len"].as_u64()),
+        (Some(0), Some(16))
+    );
+    let _ = fs::remove_file(format!("{dir}/recovered_ct.bin"));
+
+    let out = t.join("out.bin");
+    let o = unpack_fountain(&dir, &out);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(fs::read(&out).unwrap(), b"");
+}
+
+#[test]
+fn inflated_ct_len_exits_2_on_both_unpack_paths() {
+    // --ct-only — путь через recovered_ct.bin, без него — через пакеты
+    for extra in [&["--ct-only"][..], &[]] {
+        let t = TempDir::new("fountain-ct-len");
+        let (_, dir) = pack_fountain(&t, 3000, 5, extra);
+        let meta_path = format!("{dir}/fountain_meta.json");
+        let mut meta = read_json(&meta_path);
+        meta["ct_len"] = (3016 + 5000).into();
+        write_json(&meta_path, &meta);
+
+        let o = unpack_fountain(&dir, &t.join("out.bin"));
+        assert_eq!(o.status.code(), Some(2), "{extra:?}: {}", stderr(&o));
+        let err = stderr(&o);
+        assert!(
+            err.contains("fountain_meta ct_len=8016"),
+            "{extra:?}: {err}"
+        );
+        assert!(!err.contains("panicked"), "{extra:?}: {err}");
+    }
+}
+
+#[test]
+fn fetch_survives_corrupt_packets_mid_stream() {
+    let t = TempDir::new("fetch-corrupt");
+    let b: [&[u8]; 3] = [b"aaaa", b"bbbb", b"cccc"];
+    let (code, err) = fetch_frames(
+        &t,
+        &[
+            meta(3, 4, 12),
+            packet(&b, &[0]),
+            (b'P', "{not json".to_string()),
+            (b'P', r#"{"ids":[0,0],"body_hex":"00000000"}"#.to_string()),
+            (b'P', r#"{"ids":[7],"body_hex":"00000000"}"#.to_string()),
+            (b'P', r#"{"ids":[1],"body_hex":"0000"}"#.to_string()),
+            packet(&b, &[1, 2]),
+            packet(&b, &[1]),
+        ],
+    );
+    assert_eq!(code, Some(0), "{err}");
+    assert!(err.contains("rejected=4"), "{err}");
+    assert_eq!(
+        fs::read(t.join("got/recovered_ct.bin")).unwrap(),
+        b"aaaabbbbcccc"
+    );
+    let jsonl = fs::read_to_string(t.join("got/fountain_packets.jsonl")).unwrap();
+    assert!(
+        !jsonl.contains("not json") && !jsonl.contains("[7]"),
+        "{jsonl}"
+    );
+}
+
+#[test]
+fn fetch_refuses_a_stream_with_contradicting_packets_in_either_order() {
+    let b: [&[u8]; 3] = [b"aaaa", b"bbbb", b"cccc"];
+    // по форме годен, но тело не то — неважно, до или после настоящего [0]
+    let bad = (
+        b'P',
+        format!(r#"{{"ids":[0],"body_hex":"{}"}}"#, hex(b"XXXX")),
+    );
+    for bad_first in [true, false] {
+        let t = TempDir::new("fetch-inconsistent");
+        let pair = if bad_first {
+            [bad.clone(), packet(&b, &[0])]
+        } else {
+            [packet(&b, &[0]), bad.clone()]
+        };
+        let mut frames = vec![meta(3, 4, 12)];
+        frames.extend(pair);
+        frames.extend([packet(&b, &[1, 2]), packet(&b, &[1])]);
+        let (code, err) = fetch_frames(&t, &frames);
+        assert_eq!(code, Some(2), "bad_first={bad_first}: {err}");
+        assert!(err.contains("inconsistent packet ids=[0]"), "{err}");
+        assert!(err.contains("inconsistent=1"), "{err}");
+        assert!(!t.path().join("got/recovered_ct.bin").exists());
+    }
+}
+
+#[test]
+fn calibrate_reports_a_sane_overhead_band_for_small_k() {
+    let report = |extra: &[&str]| -> serde_json::Value {
+        let mut args = vec!["fountain-calibrate", "--k=20", "--trials=50", "--json"];
+        args.extend_from_slice(extra);
+        serde_json::from_str(&ok(&args)).unwrap()
+    };
+    // систематический префикс без потерь: ровно k пакетов
+    let r = report(&[]);
+    assert_eq!(r["median_packets"], 20);
+    assert_eq!(r["failures"], 0);
+
+    let r = report(&["--systematic=false"]);
+    let median = r["median_overhead"].as_f64().unwrap();
+    assert!((1.0..=3.0).contains(&median), "{r}");
+    assert!(
+        r["p95_packets"].as_u64() >= r["median_packets"].as_u64(),
+        "{r}"
+    );
+    assert!(r["failures"].as_u64().unwrap() <= 2, "{r}");
+    // тот же --seed — тот же отчёт
+    assert_eq!(r, report(&["--systematic=false"]));
+}
+
+#[test]
+fn truncated_recovered_ct_fails_unless_from_packets() {
+    let t = TempDir::new("fountain-from-packets");
+    let (input, dir) = pack_fountain(&t, 3000, 5, &[]);
+    // недописанный recovered_ct.bin упавшего fetch
+    fs::write(format!("{dir}/recovered_ct.bin"), sample(1000, 1)).unwrap();
+
+    let o = unpack_fountain(&dir, &t.join("bad.bin"));
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    assert!(stderr(&o).contains("use --from-packets"), "{}", stderr(&o));
+    assert!(!t.path().join("bad.bin").exists());
+
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let out = t.join("out.bin");
+    ok(&["unpack-fountain", &dir, &out, &ikm, &salt, "--from-packets"]);
+    assert_eq!(fs::read(&out).unwrap(), fs::read(&input).unwrap());
+}
+
+#[test]
+fn systematic_count_zero_and_full_k_both_decode() {
+    for n in [0usize, 5, 16] {
+        let t = TempDir::new("fountain-sys-count");
+        let count = format!("--systematic-count={n}");
+        let (input, dir) = pack_fountain(&t, 20_000, 16, &[&count, "--overhead=2.0"]);
+        let meta = read_json(&format!("{dir}/fountain_meta.json"));
+        assert_eq!(meta["systematic_count"], n);
+
+        // первые n пакетов — сырые блоки по порядку
+        let jsonl = fs::read_to_string(format!("{dir}/fountain_packets.jsonl")).unwrap();
+        let ids: Vec<serde_json::Value> = jsonl
+            .lines()
+            .take(n)
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap()["ids"].clone())
+            .collect();
+        let raw: Vec<serde_json::Value> = (0..n).map(|i| serde_json::json!([i])).collect();
+        assert_eq!(ids, raw);
+
+        let out = t.join("out.bin");
+        let o = unpack_fountain(&dir, &out);
+        assert!(o.status.success(), "n={n}: {}", stderr(&o));
+        assert_eq!(fs::read(&out).unwrap(), fs::read(&input).unwrap());
+    }
+}
+
+/// Эхо-пир для `serve --probe`: R-кадры возвращаются с задержкой `delay`, из них — только
+/// каждый `every`-й; прочие кадры (M/P) игнорируются. Завершается после 2 с тишины
+fn echo_peer(delay: Duration, every: usize) -> (String, std::thread::JoinHandle<()>) {
+    let s = UdpSocket::bind("127.0.0.1:0").unwrap();
+    s.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let addr = s.local_addr().unwrap().to_string();
+    let h = std::thread::spawn(move || {
+        let mut buf = [0u8; 2048];
+        let mut probes = 0;
+        while let Ok((n, from)) = s.recv_from(&mut buf) {
+            if n < 2 || &buf[..2] != b"R\n" {
+                continue;
+            }
+            probes += 1;
+            if probes % every == 0 {
+                std::thread::sleep(delay);
+                s.send_to(&buf[..n], from).unwrap();
+            }
+        }
+    });
+    (addr, h)
+}
+
+/// pps, на котором остановилась проба (из строки «probe: … → pps=N»)
+fn probed_pps(dir: &str, peer: &str) -> (u64, String) {
+    let to = format!("--to={peer}");
+    let o = Command::new(serve_bin())
+        .args([dir, &to, "--probe"])
+        .output()
+        .unwrap();
+    let err = String::from_utf8_lossy(&o.stderr).into_owned();
+    assert!(o.status.success(), "{err}");
+    let line = err.lines().find(|l| l.starts_with("probe:")).unwrap();
+    let pps = line.rsplit("pps=").next().unwrap().parse().unwrap();
+    (pps, err)
+}
+
+#[test]
+fn probe_settles_on_a_rate_from_the_echoed_rtt() {
+    let t = TempDir::new("serve-probe");
+    let (_, dir) = pack_fountain(&t, 4000, 8, &[]);
+
+    // RTT ≥ 10 мс, все 8 проб вернулись: pps = 32 / RTT ≤ 3200
+    let (peer, h) = echo_peer(Duration::from_millis(10), 1);
+    let (pps, err) = probed_pps(&dir, &peer);
+    h.join().unwrap();
+    assert!((1000..=3200).contains(&pps), "{err}");
+    assert!(err.contains("8/8 echoed"), "{err}");
+    assert!(err.contains(&format!("pps={pps}, loss=")), "{err}");
+
+    // вернулась половина проб — скорость урезана вчетверо (доля²)
+    let (peer, h) = echo_peer(Duration::from_millis(10), 2);
+    let (half, err) = probed_pps(&dir, &peer);
+    h.join().unwrap();
+    assert!(err.contains("4/8 echoed"), "{err}");
+    assert!((250..=800).contains(&half), "{err}");
+}
+
+#[test]
+fn probe_without_echo_keeps_the_static_rate() {
+    let t = TempDir::new("serve-probe-silent");
+    let (_, dir) = pack_fountain(&t, 4000, 8, &[]);
+    let (peer, h) = echo_peer(Duration::ZERO, usize::MAX);
+    let to = format!("--to={peer}");
+    let o = Command::new(serve_bin())
+        .args([&dir, &to, "--probe", "--pps=700"])
+        .output()
+        .unwrap();
+    h.join().unwrap();
+    let err = String::from_utf8_lossy(&o.stderr);
+    assert!(o.status.success(), "{err}");
+    assert!(err.contains("probe: no echo"), "{err}");
+    assert!(err.contains("pps=700, loss="), "{err}");
+}
@@ -0,0 +1,1011 @@
+//! RS-профиль: pack/unpack и производные (cas, trailer, rekey, verify, scrub)
+
+mod common;
+
+use common::*;
+use std::fs;
+
+#[test]
+fn cas_layout_round_trips_with_files_named_by_digest() {
+    let t = TempDir::new("cas-roundtrip");
+    let input = write_sample(&t, "in.bin", 30_000);
+    let out = t.join("p");
+    pack(&input, &out, &["--layout=cas"]);
+
+    let mf = read_json(&t.join("p/manifest.json"));
+    let digests = mf["shard_digests"].as_array().unwrap();
+    assert_eq!(digests.len(), 6);
+    for d in digests {
+        let d = d.as_str().unwrap();
+        let body = fs::read(t.join(&format!("p/shards/{d}.bin"))).unwrap();
+        assert_eq!(blake3::hash(&body).to_hex().as_str(), d);
+    }
+    assert!(!t.path().join("p/shard_000.bin").exists());
+
+    ok(&["verify-pack", &out]);
+    let o = unpack(&out, &t.join("o.bin"), &[]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(
+        fs::read(t.join("o.bin")).unwrap(),
+        fs::read(&input).unwrap()
+    );
+}
+
+#[test]
+fn cas_tampered_shard_fails_its_digest_and_is_treated_as_missing() {
+    let t = TempDir::new("cas-digest");
+    let input = write_sample(&t, "in.bin", 30_000);
+    let out = t.join("p");
+    pack(&input, &out, &["--layout=cas"]);
+
+    let mf = read_json(&t.join("p/manifest.json"));
+    let digest = mf["shard_digests"][1].as_str().unwrap().to_string();
+    flip_byte(&t.join(&format!("p/shards/{digest}.bin")), 7);
+
+    let o = unpack(&out, &t.join("o.bin"), &[]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert!(stderr(&o).contains("does not match its digest"));
+    assert_eq!(
+        fs::read(t.join("o.bin")).unwrap(),
+        fs::read(&input).unwrap()
+    );
+}
+
+#[test]
+fn rekey_password_pack_old_key_fails_new_key_opens() {
+    let t = TempDir::new("rekey-pw");
+    let input = write_sample(&t, "in.bin", 25_000);
+    let src = t.join("src");
+    let salt = format!("--salt-hex={SALT}");
+    ok(&[
+        "pack",
+        &input,
+        &src,
+        "--data=4",
+        "--parity=2",
+        "--password=old secret",
+        "--kdf-params=m=1MiB,t=1,p=1",
+        &salt,
+    ]);
+
+    // в новый ключ — тоже паролем
+    let dst = t.join("dst");
+    let new_salt = "--new-salt-hex=a1a2a3a4a5a6a7a8";
+    ok(&[
+        "rekey",
+        &src,
+        &dst,
+        "--old-password=old secret",
+        &format!("--old-salt-hex={SALT}"),
+        "--new-password=new secret",
+        "--kdf-params=m=1MiB,t=1,p=1",
+        new_salt,
+    ]);
+    let o = t.join("o.bin");
+    let opened = |dir: &str, pw: &str, salt: &str| {
+        let pw = format!("--password={pw}");
+        let salt = format!("--salt-hex={salt}");
+        run(&["unpack", dir, &o, &pw, &salt]).status.success()
+    };
+    assert!(!opened(&dst, "old secret", "a1a2a3a4a5a6a7a8"));
+    assert!(opened(&dst, "new secret", "a1a2a3a4a5a6a7a8"));
+    assert_eq!(fs::read(&o).unwrap(), fs::read(&input).unwrap());
+
+    // и обратно на hex-ikm (hkdf)
+    let dst2 = t.join("dst2");
+    let new_ikm = format!("--new-ikm-hex={IKM2}");
+    ok(&[
+        "rekey",
+        &dst,
+        &dst2,
+        "--old-password=new secret",
+        "--old-salt-hex=a1a2a3a4a5a6a7a8",
+        &new_ikm,
+        &format!("--new-salt-hex={SALT}"),
+    ]);
+    let ikm = format!("--ikm-hex={IKM2}");
+    ok(&["unpack", &dst2, &o, &ikm, &salt]);
+    assert_eq!(fs::read(&o).unwrap(), fs::read(&input).unwrap());
+    assert!(!run(&["unpack", &dst2, &o, "--password=new secret", &salt])
+        .status
+        .success());
+}
+
+#[test]
+fn rekey_rejects_ambiguous_or_malformed_flags() {
+    let t = TempDir::new("rekey-ambig");
+    let input = write_sample(&t, "in.bin", 5_000);
+    let src = t.join("src");
+    pack(&input, &src, &[]);
+    let old_ikm = format!("--old-ikm-hex={IKM}");
+    let new_ikm = format!("--new-ikm-hex={IKM2}");
+    let old_salt = format!("--old-salt-hex={SALT}");
+    let dst = t.join("dst");
+    let cases: [&[&str]; 4] = [
+        &[&old_ikm, "--old-password=x", &new_ikm],
+        &[&old_ikm, &new_ikm, "--new-password=y"],
+        &[&old_ikm, &new_ikm, "--password=y"],
+        &[&old_ikm, "--password=y", "--new-password=y"],
+    ];
+    for extra in cases {
+        let mut args = vec![
+            "rekey",
+            &src,
+            &dst,
+            &old_salt,
+            "--new-salt-hex=a1a2a3a4a5a6a7a8",
+        ];
+        args.extend_from_slice(extra);
+        let err = fails(&args, 2);
+        assert!(err.contains("mutually exclusive"), "{extra:?}: {err}");
+    }
+    // пак без пароля: --old-password не к чему применить
+    let err = fails(
+        &[
+            "rekey",
+            &src,
+            &dst,
+            "--old-password=x",
+            &old_salt,
+            &new_ikm,
+            "--new-salt-hex=a1a2a3a4a5a6a7a8",
+        ],
+        2,
+    );
+    assert!(err.contains("packed without a password"), "{err}");
+
+    // опечатка в --data/--parity — код 2, а не перепаковка в старой раскладке
+    for bad in ["--data=1O", "--parity=x"] {
+        let args = [
+            "rekey",
+            &src,
+            &dst,
+            &old_ikm,
+            &old_salt,
+            &new_ikm,
+            "--new-salt-hex=a1a2a3a4a5a6a7a8",
+            bad,
+        ];
+        let err = fails(&args, 2);
+        assert!(err.contains(&format!("invalid {bad}")), "{err}");
+        assert!(!std::path::Path::new(&format!("{dst}/manifest.json")).exists());
+    }
+}
+
+#[test]
+fn merkle_proof_round_trip_and_tamper() {
+    let t = TempDir::new("merkle-proof");
+    let input = write_sample(&t, "in.bin", 12_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+    let proof = t.join("proof.json");
+    let out_flag = format!("--out={proof}");
+    let proof_flag = format!("--proof={proof}");
+    for i in 0..6 {
+        let index = format!("--index={i}");
+        ok(&["merkle-proof", &out, &index, &out_flag]);
+        ok(&["merkle-verify", &out, &proof_flag, &index]);
+    }
+
+    // доказательство шарда 5 не подходит под индекс 4
+    let err = fails(&["merkle-verify", &out, &proof_flag, "--index=4"], 2);
+    assert!(err.contains("proof index mismatch"), "{err}");
+
+    // подменённый лист в доказательстве не даёт корень коммита
+    let mut p = read_json(&proof);
+    p["leaves"][5] = "11".repeat(32).into();
+    write_json(&proof, &p);
+    let err = fails(&["merkle-verify", &out, &proof_flag, "--index=5"], 2);
+    assert!(err.contains("proof root mismatch"), "{err}");
+
+    // испорченный шард: честное доказательство, но шард — не из коммита
+    ok(&["merkle-proof", &out, "--index=2", &out_flag]);
+    flip_byte(&t.join("p/shard_002.bin"), 0);
+    let err = fails(&["merkle-verify", &out, &proof_flag, "--index=2"], 2);
+    assert!(err.contains("shard not in commit"), "{err}");
+    let err = fails(&["merkle-proof", &out, "--index=2"], 2);
+    assert!(err.contains("not the committed shard"), "{err}");
+
+    let err = fails(&["merkle-proof", &out, "--index=6"], 2);
+    assert!(err.contains("out of range"), "{err}");
+    let err = fails(&["merkle-verify", &out, &proof_flag, "--index=99"], 2);
+    assert!(err.contains("out of range"), "{err}");
+}
+
+#[test]
+fn more_than_256_shards_is_rejected_before_writing() {
+    let t = TempDir::new("rs-limit");
+    let input = write_sample(&t, "in.bin", 1000);
+    let out = t.join("p");
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let args = [
+        "pack",
+        &input,
+        &out,
+        "--data=250",
+        "--parity=7",
+        &ikm,
+        &salt,
+    ];
+    let err = fails(&args, 2);
+    assert!(err.contains("257 exceeds 256"), "{err}");
+    assert!(err.contains("GF(2^16) is not available"), "{err}");
+    assert!(!t.path().join("p/manifest.json").exists());
+}
+
+#[test]
+fn binary_aad_round_trips_and_is_bound() {
+    let t = TempDir::new("aad-hex");
+    let input = write_sample(&t, "in.bin", 4000);
+    let out = t.join("p");
+    // не UTF-8: 0xff 0xfe 0x00 0x80
+    pack(&input, &out, &["--aad-hex=fffe0080"]);
+    let mf = format!("{out}/manifest.json");
+    let m = read_json(&mf);
+    assert_eq!(m["aad_hex"], "fffe0080");
+
+    let got = t.join("out.bin");
+    let o = unpack(&out, &got, &[]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(std::fs::read(&got).unwrap(), std::fs::read(&input).unwrap());
+
+    // другие байты AAD в манифесте — AEAD не откроется
+    let mut forged = m.clone();
+    forged["aad_hex"] = "fffe0081".into();
+    write_json(&mf, &forged);
+    assert!(!unpack(&out, &t.join("out2.bin"), &[]).status.success());
+
+    let err = fails(
+        &[
+            "pack",
+            &input,
+            &t.join("q"),
+            "--data=4",
+            "--parity=2",
+            &format!("--ikm-hex={IKM}"),
+            &format!("--salt-hex={SALT}"),
+            "--aad=x",
+            "--aad-hex=00",
+        ],
+        1, // ошибка использования: сообщение + usage
+    );
+    assert!(err.contains("mutually exclusive"), "{err}");
+}
+
+#[test]
+fn argon2id_password_reproduces_the_key_and_a_wrong_one_fails() {
+    let t = TempDir::new("argon2");
+    let input = write_sample(&t, "in.bin", 6000);
+    let salt = format!("--salt-hex={SALT}");
+    let params = "--kdf-params=m=1MiB,t=1,p=1";
+
+    // тот же пароль + соль + параметры → тот же ключ: детерминированный nonce даёт
+    // побайтно одинаковые шарды у двух независимых упаковок
+    let stream = |out: &str, pw: &str| {
+        let pw = format!("--password={pw}");
+        ok(&[
+            "pack-stream",
+            &input,
+            out,
+            "--data=2",
+            "--parity=1",
+            "--chunk=4096",
+            &pw,
+            params,
+            &salt,
+            &format!("--nonce-base-hex={}", "07".repeat(24)),
+        ]);
+    };
+    let (a, b, c) = (t.join("a"), t.join("b"), t.join("c"));
+    stream(&a, "correct horse");
+    stream(&b, "correct horse");
+    stream(&c, "correct horse!");
+    let shard = |dir: &str| std::fs::read(format!("{dir}/shard_000.bin")).unwrap();
+    assert_eq!(shard(&a), shard(&b));
+    assert_ne!(shard(&a), shard(&c));
+
+    let out = t.join("p");
+    ok(&[
+        "pack",
+        &input,
+        &out,
+        "--data=4",
+        "--parity=2",
+        "--password=correct horse",
+        params,
+        &salt,
+    ]);
+    let mf = format!("{out}/manifest.json");
+    let m = read_json(&mf);
+    assert_eq!(m["kdf"]["alg"], "argon2id");
+    assert_eq!(
+        (m["kdf"]["m_kib"].as_u64(), m["kdf"]["t"].as_u64()),
+        (Some(1024), Some(1))
+    );
+
+    let open = |pw: &str| {
+        let o = t.join("o.bin");
+        let pw = format!("--password={pw}");
+        let r = run(&["unpack", &out, &o, &pw, &salt]);
+        r.status.success() && std::fs::read(&o).unwrap() == std::fs::read(&input).unwrap()
+    };
+    assert!(open("correct horse"));
+    assert!(!open("correct horse!"));
+
+    // unpack берёт параметры из манифеста: другие t — другой ключ
+    let mut m2 = m.clone();
+    m2["kdf"]["t"] = 2.into();
+    write_json(&mf, &m2);
+    assert!(!open("correct horse"));
+}
+
+#[test]
+fn check_recoverable_up_to_and_beyond_the_parity_budget() {
+    let t = TempDir::new("recoverable");
+    let input = write_sample(&t, "in.bin", 30_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+
+    let report = |code: i32| -> serde_json::Value {
+        let o = run(&["verify-pack", &out, "--check-recoverable", "--json"]);
+        assert_eq!(o.status.code(), Some(code), "{}", stderr(&o));
+        serde_json::from_str(&stdout(&o)).unwrap()
+    };
+
+    // 4+2: без двух шардов (данные и паритет) — ещё восстановимо
+    for (i, gone) in [0usize, 5].iter().enumerate() {
+        fs::remove_file(t.join(&format!("p/shard_{gone:03}.bin"))).unwrap();
+        let r = report(0);
+        assert_eq!(r["status"], "recoverable");
+        assert_eq!(r["trial"], "ok");
+        assert_eq!(r["missing"].as_array().unwrap().len(), i + 1);
+    }
+
+    // третий — за пределом паритета
+    fs::remove_file(t.join("p/shard_002.bin")).unwrap();
+    let r = report(2);
+    assert_eq!(r["status"], "unrecoverable");
+    assert_eq!(r["trial"], "skipped");
+    assert_eq!(r["missing"], serde_json::json!([0, 2, 5]));
+
+    // unpack с тем же набором действительно не восстанавливает
+    assert!(!unpack(&out, &t.join("o.bin"), &[]).status.success());
+}
+
+#[test]
+fn salt_random_is_embedded_and_unpack_needs_only_the_ikm() {
+    let t = TempDir::new("salt-random");
+    let input = write_sample(&t, "in.bin", 30_000);
+    let ikm = format!("--ikm-hex={IKM}");
+    let packed: Vec<String> = ["p1", "p2"]
+        .iter()
+        .map(|d| {
+            let out = t.join(d);
+            ok(&[
+                "pack",
+                &input,
+                &out,
+                "--data=4",
+                "--parity=2",
+                &ikm,
+                "--salt-random",
+            ]);
+            out
+        })
+        .collect();
+    let salts: Vec<String> = packed
+        .iter()
+        .map(|d| {
+            let s = read_json(&format!("{d}/manifest.json"))["salt_hex"]
+                .as_str()
+                .unwrap()
+                .to_string();
+            assert_eq!(s.len(), 32, "{s}");
+            s
+        })
+        .collect();
+    assert_ne!(salts[0], salts[1]);
+
+    let o = t.join("o.bin");
+    ok(&["unpack", &packed[0], &o, &ikm]);
+    assert_eq!(fs::read(&o).unwrap(), fs::read(&input).unwrap());
+
+    // явная соль — только совпадающая со встроенной
+    let same = format!("--salt-hex={}", salts[0]);
+    ok(&["unpack", &packed[0], &t.join("o2.bin"), &ikm, &same]);
+    let other = format!("--salt-hex={}", salts[1]);
+    let err = fails(&["unpack", &packed[0], &t.join("o3.bin"), &ikm, &other], 2);
+    assert!(err.contains("does not match salt_hex"), "{err}");
+}
+
+#[test]
+fn mis_sized_present_shard_is_named_not_a_library_panic() {
+    let t = TempDir::new("shard-len");
+    let input = write_sample(&t, "in.bin", 30_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+    let victim = t.join("p/shard_002.bin");
+    let mut body = fs::read(&victim).unwrap();
+    let len = body.len();
+    body.pop();
+    fs::write(&victim, body).unwrap();
+
+    let o = unpack(&out, &t.join("o.bin"), &[]);
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    let err = stderr(&o);
+    assert!(
+        err.contains(&format!("shard_002 is {} bytes, expected {len}", len - 1)),
+        "{err}"
+    );
+    assert!(!err.contains("panicked"), "{err}");
+
+    let r = run(&["verify-pack", &out, "--check-recoverable", "--json"]);
+    assert_eq!(r.status.code(), Some(2));
+    let r: serde_json::Value = serde_json::from_str(&stdout(&r)).unwrap();
+    assert_eq!(r["trial"], "shard_len_mismatch");
+}
+
+#[test]
+fn empty_input_round_trips_to_an_empty_file() {
+    let t = TempDir::new("empty-rs");
+    let input = write_sample(&t, "in.bin", 0);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+    let mf = read_json(&t.join("p/manifest.json"));
+    assert_eq!(
+        (mf["commit"]["size_bytes"].as_u64(), mf["ct_len"].as_u64()),
+        (Some(0), Some(16))
+    );
+    ok(&["verify-pack", &out]);
+
+    let got = t.join("o.bin");
+    let o = unpack(&out, &got, &[]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(fs::read(&got).unwrap(), b"");
+}
+
+#[test]
+fn placement_round_trips_and_names_the_missing_provider() {
+    let t = TempDir::new("placement");
+    let input = write_sample(&t, "in.bin", 30_000);
+    let placement = serde_json::json!([
+        {"provider": "A", "from": 0, "to": 1},
+        {"provider": "B", "from": 2, "to": 3},
+        {"provider": "C", "from": 4, "to": 5},
+    ]);
+    let pl_path = t.join("placement.json");
+    write_json(&pl_path, &placement);
+    let out = t.join("p");
+    pack(&input, &out, &[&format!("--placement={pl_path}")]);
+    assert_eq!(
+        read_json(&t.join("p/manifest.json"))["placement"],
+        placement
+    );
+
+    // провайдер B недоступен: его шарды 2–3 пропали, паритета хватает
+    for i in [2, 3] {
+        fs::remove_file(t.join(&format!("p/shard_{i:03}.bin"))).unwrap();
+    }
+    let o = run(&["verify-pack", &out, "--check-recoverable", "--json"]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    let r: serde_json::Value = serde_json::from_str(&stdout(&o)).unwrap();
+    assert_eq!(r["missing_by_provider"], serde_json::json!({"B": [2, 3]}));
+
+    let got = t.join("o.bin");
+    let o = unpack(&out, &got, &[]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert!(
+        stderr(&o).contains("provider 'B': 2 missing shard(s): 2–3"),
+        "{}",
+        stderr(&o)
+    );
+    assert_eq!(fs::read(&got).unwrap(), fs::read(&input).unwrap());
+
+    // каждый индекс — ровно у одного провайдера
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    for (bad, msg) in [
+        (
+            serde_json::json!([{"provider": "A", "from": 0, "to": 3}, {"provider": "B", "from": 3, "to": 5}]),
+            "shard 3 assigned to both 'A' and 'B'",
+        ),
+        (
+            serde_json::json!([{"provider": "A", "from": 0, "to": 4}]),
+            "shard 5 is not assigned",
+        ),
+    ] {
+        write_json(&pl_path, &bad);
+        let pl = format!("--placement={pl_path}");
+        let args = [
+            "pack",
+            &input,
+            &t.join("q"),
+            "--data=4",
+            "--parity=2",
+            &ikm,
+            &salt,
+            &pl,
+        ];
+        let err = fails(&args, 2);
+        assert!(err.contains(msg), "{err}");
+    }
+}
+
+#[test]
+fn shard_trailer_round_trips_and_catches_tampering_in_isolation() {
+    let t = TempDir::new("trailer");
+    let input = write_sample(&t, "in.bin", 30_000);
+    let out = t.join("p");
+    pack(&input, &out, &["--shard-trailer"]);
+    assert_eq!(read_json(&t.join("p/manifest.json"))["shard_trailer"], true);
+
+    // файл = тело || blake3(тело): проверяется без манифеста
+    let file = fs::read(t.join("p/shard_000.bin")).unwrap();
+    let (body, trailer) = file.split_at(file.len() - 32);
+    assert_eq!(blake3::hash(body).as_bytes(), trailer);
+    ok(&["verify-pack", &out]);
+    ok(&["scrub", &out]);
+
+    // порча тела и порча самого trailer — оба шарда считаются отсутствующими
+    flip_byte(&t.join("p/shard_001.bin"), 3);
+    let last = fs::metadata(t.join("p/shard_004.bin")).unwrap().len() as usize - 1;
+    flip_byte(&t.join("p/shard_004.bin"), last);
+
+    let o = run(&["scrub", &out, "--json"]);
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    let r: serde_json::Value = serde_json::from_str(stdout(&o).trim()).unwrap();
+    let idx: Vec<u64> = r["corrupt"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["index"].as_u64().unwrap())
+        .collect();
+    assert_eq!(idx, [1, 4], "{r}");
+    fails(&["verify-pack", &out], 2);
+
+    let got = t.join("o.bin");
+    let o = unpack(&out, &got, &[]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert!(
+        stderr(&o).contains("fails its trailer check"),
+        "{}",
+        stderr(&o)
+    );
+    assert_eq!(fs::read(&got).unwrap(), fs::read(&input).unwrap());
+}
+
+#[test]
+fn inflated_ct_len_exits_2_instead_of_panicking() {
+    let t = TempDir::new("ct-len");
+    let input = write_sample(&t, "in.bin", 30_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+    let mf_path = t.join("p/manifest.json");
+    let mut mf = read_json(&mf_path);
+    mf["ct_len"] = (30_016 + 10_000).into();
+    write_json(&mf_path, &mf);
+
+    let o = unpack(&out, &t.join("o.bin"), &[]);
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    assert!(
+        stderr(&o).contains("manifest ct_len=40016 exceeds reconstructed data"),
+        "{}",
+        stderr(&o)
+    );
+    assert!(!t.path().join("o.bin").exists());
+}
+
+#[test]
+fn pack_batch_round_trips_mixed_sizes_and_extracts_by_name() {
+    let t = TempDir::new("batch");
+    let src = t.path().join("in");
+    fs::create_dir_all(src.join("sub")).unwrap();
+    let files: [(&str, usize); 4] = [
+        ("a.bin", 5000),
+        ("empty.txt", 0),
+        ("one", 1),
+        ("sub/b.bin", 70_000),
+    ];
+    for (name, len) in files {
+        fs::write(src.join(name), sample(len, len as u8)).unwrap();
+    }
+    let src = src.display().to_string();
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let out = t.join("p");
+    ok(&[
+        "pack-batch",
+        &src,
+        &out,
+        "--data=4",
+        "--parity=2",
+        &ikm,
+        &salt,
+    ]);
+    assert_eq!(
+        read_json(&t.join("p/manifest.json"))["batch"]
+            .as_array()
+            .unwrap()
+            .len(),
+        4
+    );
+
+    let all = t.join("all");
+    ok(&["unpack-batch", &out, &all, &ikm, &salt]);
+    for (name, len) in files {
+        assert_eq!(
+            fs::read(format!("{all}/{name}")).unwrap(),
+            sample(len, len as u8),
+            "{name}"
+        );
+    }
+
+    let one = t.join("one");
+    let (name, len) = files[3];
+    ok(&[
+        "unpack-batch",
+        &out,
+        &one,
+        &ikm,
+        &salt,
+        &format!("--file={name}"),
+    ]);
+    assert_eq!(
+        fs::read(format!("{one}/{name}")).unwrap(),
+        sample(len, len as u8)
+    );
+    assert!(!t.path().join("one/a.bin").exists());
+    let err = fails(
+        &[
+            "unpack-batch",
+            &out,
+            &t.join("x"),
+            &ikm,
+            &salt,
+            "--file=nope",
+        ],
+        2,
+    );
+    assert!(err.contains("'nope' is not in the batch"), "{err}");
+
+    // имена, совпадающие без учёта регистра, не упаковываются
+    fs::write(format!("{src}/A.BIN"), b"x").unwrap();
+    let err = fails(
+        &[
+            "pack-batch",
+            &src,
+            &t.join("q"),
+            "--data=4",
+            "--parity=2",
+            &ikm,
+            &salt,
+        ],
+        2,
+    );
+    assert!(
+        err.contains("collides with another file name ignoring case"),
+        "{err}"
+    );
+}
+
+#[test]
+fn tampered_scid_string_stops_unpack() {
+    let t = TempDir::new("scid-tamper");
+    let input = write_sample(&t, "in.bin", 30_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+    let mf_path = t.join("p/manifest.json");
+    let mut mf = read_json(&mf_path);
+    mf["scid"] = "00".repeat(32).into();
+    write_json(&mf_path, &mf);
+
+    let o = unpack(&out, &t.join("o.bin"), &[]);
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    assert!(
+        stderr(&o).contains("does not match commit"),
+        "{}",
+        stderr(&o)
+    );
+    assert!(!t.path().join("o.bin").exists());
+}
+
+#[test]
+fn leftover_high_index_shard_is_refused_and_strays_are_warned() {
+    let t = TempDir::new("stray-shards");
+    let input = write_sample(&t, "in.bin", 30_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+
+    // мусор рядом с паком — только предупреждение
+    fs::write(t.join("p/shard_2.bin"), b"x").unwrap();
+    let o = unpack(&out, &t.join("o.bin"), &[]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert!(
+        stderr(&o).contains("unexpected shard file shard_2.bin"),
+        "{}",
+        stderr(&o)
+    );
+
+    // shard_006.bin от прежнего пака 5+2 в том же каталоге: индекс ≥ 4+2
+    fs::copy(t.join("p/shard_000.bin"), t.join("p/shard_006.bin")).unwrap();
+    let err = fails(&["verify-pack", &out], 2);
+    assert!(
+        err.contains("beyond the manifest's 6 shards: shard_006.bin"),
+        "{err}"
+    );
+    let o = unpack(&out, &t.join("o2.bin"), &[]);
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    assert!(!t.path().join("o2.bin").exists());
+}
+
+#[test]
+fn force_clears_a_previous_ten_shard_pack() {
+    let t = TempDir::new("reuse-dir");
+    let old_input = write_sample(&t, "old.bin", 40_000);
+    let input = write_sample(&t, "in.bin", 30_000);
+    let out = t.join("p");
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    ok(&[
+        "pack",
+        &old_input,
+        &out,
+        "--data=7",
+        "--parity=3",
+        &ikm,
+        &salt,
+    ]);
+    ok(&["pod-sign", &out, &format!("--sk-hex={SK}")]);
+
+    // без --force — отказ, каталог нетронут
+    let args = ["pack", &input, &out, "--data=4", "--parity=2", &ikm, &salt];
+    let err = fails(&args, 2);
+    assert!(err.contains("already holds a pack"), "{err}");
+    assert_eq!(read_json(&t.join("p/manifest.json"))["data_shards"], 7);
+
+    let mut forced = args.to_vec();
+    forced.push("--force");
+    ok(&forced);
+    for i in 6..10 {
+        assert!(
+            !t.path().join(format!("p/shard_{i:03}.bin")).exists(),
+            "shard {i}"
+        );
+    }
+    assert!(!t.path().join("p/pod_000.json").exists());
+    ok(&["verify-pack", &out]);
+    let got = t.join("o.bin");
+    let o = unpack(&out, &got, &[]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(fs::read(&got).unwrap(), fs::read(&input).unwrap());
+
+    // pack-stream в тот же каталог — тот же отказ
+    let stream = [
+        "pack-stream",
+        &input,
+        &out,
+        "--data=3",
+        "--parity=2",
+        &ikm,
+        &salt,
+        "--chunk=4096",
+    ];
+    let err = fails(&stream, 2);
+    assert!(err.contains("already holds a pack"), "{err}");
+}
+
+#[test]
+fn rebuild_manifest_from_surviving_shards_then_unpack() {
+    let t = TempDir::new("rebuild-manifest");
+    let input = write_sample(&t, "in.bin", 30_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+    let mf_path = t.join("p/manifest.json");
+    let old = read_json(&mf_path);
+    // то, что оператор записал отдельно: nonce, aad, размер, имя
+    let nonce = format!("--nonce-hex={}", old["nonce_hex"].as_str().unwrap());
+    let aad = format!("--aad={}", old["aad"].as_str().unwrap());
+    let name = format!("--file-name={}", old["file_name"].as_str().unwrap());
+    fs::remove_file(&mf_path).unwrap();
+    fs::remove_file(t.join("p/shard_001.bin")).unwrap();
+
+    let args = [
+        "rebuild-manifest",
+        &out,
+        "--data=4",
+        "--parity=2",
+        "--size=30000",
+        &nonce,
+        &aad,
+        &name,
+    ];
+    ok(&args);
+    let new = read_json(&mf_path);
+    assert_eq!(new["scid"], old["scid"]);
+    assert_eq!(new["commit"], old["commit"]);
+
+    let got = t.join("o.bin");
+    let o = unpack(&out, &got, &[]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(fs::read(&got).unwrap(), fs::read(&input).unwrap());
+
+    // неверный размер: перекодированные шарды не совпадут с диском — манифест не пишется
+    fs::remove_file(&mf_path).unwrap();
+    let mut wrong = args.to_vec();
+    wrong[4] = "--size=29999";
+    assert!(fails(&wrong, 2).contains("re-encoded shards differ"));
+    assert!(!t.path().join("p/manifest.json").exists());
+}
+
+#[test]
+fn key_file_round_trip_and_flag_override() {
+    let t = TempDir::new("key-file");
+    let input = write_sample(&t, "in.bin", 20_000);
+    let out = t.join("p");
+    let kf = t.join("profile.json");
+    write_json(
+        &kf,
+        &serde_json::json!({ "ikm_hex": IKM, "salt_hex": SALT, "aad": "team-profile" }),
+    );
+    let key_file = format!("--key-file={kf}");
+    ok(&["pack", &input, &out, "--data=4", "--parity=2", &key_file]);
+    assert_eq!(read_json(&t.join("p/manifest.json"))["aad"], "team-profile");
+
+    let got = t.join("o.bin");
+    ok(&["unpack", &out, &got, &key_file]);
+    assert_eq!(fs::read(&got).unwrap(), fs::read(&input).unwrap());
+
+    // те же значения флагами — тот же ключ
+    let o = unpack(&out, &t.join("o2.bin"), &[]);
+    assert!(o.status.success(), "{}", stderr(&o));
+
+    // явный флаг перекрывает поле файла: чужой IKM не откроет пакет
+    let ikm2 = format!("--ikm-hex={IKM2}");
+    let o = run(&["unpack", &out, &t.join("o3.bin"), &key_file, &ikm2]);
+    assert!(!o.status.success());
+}
+
+#[test]
+fn key_file_rejects_unknown_fields_and_bad_hex() {
+    let t = TempDir::new("key-file-bad");
+    let input = write_sample(&t, "in.bin", 1000);
+    let out = t.join("p");
+    let kf = t.join("profile.json");
+    let key_file = format!("--key-file={kf}");
+    let args = ["pack", &input, &out, "--data=4", "--parity=2", &key_file];
+
+    write_json(
+        &kf,
+        &serde_json::json!({ "ikm_hex": IKM, "salt_hex": SALT, "ikm": IKM }),
+    );
+    assert!(fails(&args, 2).contains("unknown field `ikm`"));
+
+    write_json(
+        &kf,
+        &serde_json::json!({ "ikm_hex": "zz", "salt_hex": SALT }),
+    );
+    assert!(fails(&args, 2).contains("ikm_hex is not valid hex"));
+    assert!(!t.path().join("p/manifest.json").exists());
+}
+
+#[test]
+fn rebuild_manifest_keeps_argon2id_and_refuses_cas() {
+    let t = TempDir::new("rebuild-kdf");
+    let input = write_sample(&t, "in.bin", 6000);
+    let out = t.join("p");
+    let salt = format!("--salt-hex={SALT}");
+    let params = "--kdf-params=m=1MiB,t=1,p=1";
+    let pw = "--password=correct horse";
+    ok(&[
+        "pack",
+        &input,
+        &out,
+        "--data=4",
+        "--parity=2",
+        pw,
+        params,
+        &salt,
+    ]);
+    let mf_path = t.join("p/manifest.json");
+    let old = read_json(&mf_path);
+    let nonce = format!("--nonce-hex={}", old["nonce_hex"].as_str().unwrap());
+    let rebuild = |extra: &[&str]| {
+        fs::remove_file(&mf_path).ok();
+        let mut args = vec![
+            "rebuild-manifest",
+            &out,
+            "--data=4",
+            "--parity=2",
+            "--size=6000",
+            &nonce,
+            "--file-name=in.bin",
+        ];
+        args.extend_from_slice(extra);
+        ok(&args);
+    };
+    let open = |name: &str| {
+        let got = t.join(name);
+        let o = run(&["unpack", &out, &got, pw, &salt]);
+        (o.status.success(), got)
+    };
+
+    // без --kdf манифест выводит ключ через HKDF — пароль не подходит
+    rebuild(&[]);
+    assert!(!open("o1.bin").0);
+
+    rebuild(&["--kdf=argon2id", params]);
+    assert_eq!(read_json(&mf_path)["kdf"], old["kdf"]);
+    let (opened, got) = open("o2.bin");
+    assert!(opened);
+    assert_eq!(fs::read(&got).unwrap(), fs::read(&input).unwrap());
+
+    // cas-пул: ни с --layout=cas, ни без него
+    let cas = t.join("c");
+    pack(&input, &cas, &["--layout=cas"]);
+    let cas_mf = read_json(&t.join("c/manifest.json"));
+    fs::remove_file(t.join("c/manifest.json")).unwrap();
+    let nonce = format!("--nonce-hex={}", cas_mf["nonce_hex"].as_str().unwrap());
+    let args = [
+        "rebuild-manifest",
+        &cas,
+        "--data=4",
+        "--parity=2",
+        "--size=6000",
+        &nonce,
+    ];
+    assert!(fails(&args, 2).contains("--layout=cas packs cannot be rebuilt"));
+    let mut with_flag = args.to_vec();
+    with_flag.push("--layout=cas");
+    assert!(fails(&with_flag, 2).contains("--layout=cas packs cannot be rebuilt"));
+    assert!(!t.path().join("c/manifest.json").exists());
+}
+
+#[test]
+fn bind_context_rejects_shards_replayed_under_another_manifest() {
+    let t = TempDir::new("bind-context");
+    let a_in = write_sample(&t, "a.bin", 8000);
+    let b_in = t.join("b.bin");
+    fs::write(&b_in, sample(8000, 7)).unwrap();
+
+    // шарды и nonce пака A под манифестом пака B (тот же ключ, та же раскладка)
+    let replay = |bind: &[&str], tag: &str| {
+        let (a, b) = (t.join(&format!("a{tag}")), t.join(&format!("b{tag}")));
+        pack(&a_in, &a, bind);
+        pack(&b_in, &b, bind);
+        for i in 0..6 {
+            let name = format!("shard_{i:03}.bin");
+            fs::copy(format!("{a}/{name}"), format!("{b}/{name}")).unwrap();
+        }
+        let ma = read_json(&format!("{a}/manifest.json"));
+        let mut mb = read_json(&format!("{b}/manifest.json"));
+        for k in ["scid", "commit", "nonce_hex", "ct_len", "shard_leaves"] {
+            mb[k] = ma[k].clone();
+        }
+        write_json(&format!("{b}/manifest.json"), &mb);
+        (a, b)
+    };
+
+    // без привязки AAD одинаков — чужие шарды молча открываются под именем b.bin
+    let (_, b) = replay(&[], "0");
+    let got = t.join("o0.bin");
+    assert!(unpack(&b, &got, &[]).status.success());
+    assert_eq!(fs::read(&got).unwrap(), fs::read(&a_in).unwrap());
+
+    let (a, b) = replay(&["--bind-context=tenant-1"], "1");
+    let o = unpack(&b, &t.join("o1.bin"), &[]);
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    assert!(!t.path().join("o1.bin").exists());
+
+    // свой пак открывается; подмена file_name или контекста в манифесте — нет
+    assert!(unpack(&a, &t.join("o2.bin"), &[]).status.success());
+    let mf = format!("{a}/manifest.json");
+    let orig = read_json(&mf);
+    for (k, v) in [("file_name", "b.bin"), ("bind_context", "tenant-2")] {
+        let mut m = orig.clone();
+        m[k] = v.into();
+        write_json(&mf, &m);
+        let o = unpack(&a, &t.join("o3.bin"), &[]);
+        assert_eq!(o.status.code(), Some(2), "{k}: {}", stderr(&o));
+    }
+}
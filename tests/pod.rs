@@ -0,0 +1,545 @@
+//! PoD: pod-sign / pod-verify / pod-aggregate*
+
+mod common;
+
+use common::*;
+
+fn signed_pack(t: &TempDir, extra_sign: &[&str]) -> String {
+    let input = write_sample(t, "in.bin", 20_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+    let sk = format!("--sk-hex={SK}");
+    let mut args = vec!["pod-sign", &out, &sk];
+    args.extend_from_slice(extra_sign);
+    ok(&args);
+    out
+}
+
+#[test]
+fn tampered_shard_leaf_is_rejected_and_resigned_on_append() {
+    let t = TempDir::new("pod-leaf");
+    let out = signed_pack(&t, &[]);
+    flip_byte(&t.join("p/shard_001.bin"), 3);
+
+    let err = fails(&["pod-verify", &out], 2);
+    assert!(err.contains("leaf hash mismatch"), "{err}");
+
+    // действующий PoD — только у нетронутых шардов
+    let sk = format!("--sk-hex={SK}");
+    let msg = ok(&["pod-sign", &out, &sk, "--append"]);
+    assert!(msg.contains("1 written, 5 kept"), "{msg}");
+    // новая подпись сходится с диском, но испорченный шард — не тот, что в коммите
+    let err = fails(&["pod-verify", &out], 2);
+    assert!(err.contains("shard 1: shard not in commit"), "{err}");
+    assert!(!err.contains("leaf hash mismatch"), "{err}");
+}
+
+#[test]
+fn shards_swapped_between_indexes_fail_membership() {
+    let t = TempDir::new("pod-swap");
+    let input = write_sample(&t, "in.bin", 20_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+    let (a, b) = (t.join("p/shard_001.bin"), t.join("p/shard_002.bin"));
+    let (sa, sb) = (std::fs::read(&a).unwrap(), std::fs::read(&b).unwrap());
+    std::fs::write(&a, sb).unwrap();
+    std::fs::write(&b, sa).unwrap();
+    // подписи честно подтверждают то, что лежит на диске
+    let sk = format!("--sk-hex={SK}");
+    ok(&["pod-sign", &out, &sk]);
+
+    let o = run(&["pod-verify", &out, "--json"]);
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    let r: serde_json::Value = serde_json::from_str(&stdout(&o)).unwrap();
+    for i in [1, 2] {
+        assert_eq!(r["results"][i]["status"], "bad", "{r}");
+        assert_eq!(r["results"][i]["reason"], "shard not in commit", "{r}");
+    }
+    assert_eq!(r["ok"], 4);
+
+    // без pod_###.proof.json подмена ловится по листьям манифеста
+    for i in 0..6 {
+        std::fs::remove_file(t.join(&format!("p/pod_{i:03}.proof.json"))).unwrap();
+    }
+    let err = fails(&["pod-verify", &out], 2);
+    assert!(err.contains("shard 1: shard not in commit"), "{err}");
+    assert!(err.contains("shard 2: shard not in commit"), "{err}");
+}
+
+#[test]
+fn inclusion_proof_is_checked_against_the_commit() {
+    let t = TempDir::new("pod-proof");
+    let out = signed_pack(&t, &[]);
+    let proof = t.join("p/pod_003.proof.json");
+    let p = read_json(&proof);
+    assert_eq!(p["index"], 3);
+    assert_eq!(p["leaves"].as_array().unwrap().len(), 6);
+
+    // подмена листа в доказательстве: корень уже не commit.merkle_root
+    let mut forged = p.clone();
+    forged["leaves"][0] = "00".repeat(32).into();
+    write_json(&proof, &forged);
+    let err = fails(&["pod-verify", &out], 2);
+    assert!(err.contains("shard 3: proof root mismatch"), "{err}");
+
+    // доказательство чужого индекса
+    let mut moved = p.clone();
+    moved["index"] = 4.into();
+    write_json(&proof, &moved);
+    let err = fails(&["pod-verify", &out], 2);
+    assert!(err.contains("shard 3: proof index mismatch"), "{err}");
+
+    // нет файла доказательства — членство проверяется по листьям коммита
+    std::fs::remove_file(&proof).unwrap();
+    ok(&["pod-verify", &out]);
+
+    write_json(&proof, &p);
+    ok(&["pod-verify", &out]);
+}
+
+#[test]
+fn pods_without_proof_files_still_verify() {
+    // PoD, подписанные до доказательств включения: манифест без shard_leaves, шарда 5 нет —
+    // pod-sign не может собрать листья и пишет только pod_###.json
+    let t = TempDir::new("pod-no-proofs");
+    let input = write_sample(&t, "in.bin", 20_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+    let mf_path = t.join("p/manifest.json");
+    let mut mf = read_json(&mf_path);
+    mf.as_object_mut().unwrap().remove("shard_leaves");
+    write_json(&mf_path, &mf);
+    std::fs::remove_file(t.join("p/shard_005.bin")).unwrap();
+    let sk = format!("--sk-hex={SK}");
+    let o = run(&["pod-sign", &out, &sk]);
+    assert!(o.status.success());
+    assert!(
+        stderr(&o).contains("no inclusion proofs written"),
+        "{}",
+        stderr(&o)
+    );
+    assert!(!std::path::Path::new(&t.join("p/pod_000.proof.json")).exists());
+
+    let o = run(&["pod-verify", &out, "--json"]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    let r: serde_json::Value = serde_json::from_str(&stdout(&o)).unwrap();
+    assert_eq!((r["ok"].as_u64(), r["bad"].as_u64()), (Some(5), Some(0)));
+    assert!(stderr(&o).contains("membership not checked"));
+}
+
+#[test]
+fn batch_verify_survives_a_corrupt_pod_file() {
+    let good = TempDir::new("pod-batch-good");
+    let bad = TempDir::new("pod-batch-bad");
+    let good_dir = signed_pack(&good, &[]);
+    let bad_dir = signed_pack(&bad, &[]);
+    std::fs::write(bad.join("p/pod_002.json"), b"{\"scid\": \"trunc").unwrap();
+    let nowhere = good.join("no-such-pack");
+
+    let o = run(&["pod-verify", &good_dir, &bad_dir, &nowhere, "--json"]);
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    let report: serde_json::Value = serde_json::from_str(&stdout(&o)).unwrap();
+    assert_eq!(report["dirs"], 3);
+    assert_eq!(report["failed_dirs"], 2);
+    let results = report["results"].as_array().unwrap();
+    assert_eq!(results[0]["status"], "ok");
+    assert_eq!(results[1]["status"], "fail");
+    assert_eq!(results[1]["results"][2]["status"], "bad");
+    assert_eq!(results[1]["results"][2]["reason"], "malformed PoD json");
+    assert_eq!(results[1]["ok"], 5);
+    assert!(results[2]["error"]
+        .as_str()
+        .unwrap()
+        .contains("manifest.json"));
+}
+
+#[test]
+fn data_only_signing_does_not_count_parity_as_missing() {
+    let t = TempDir::new("pod-data-only");
+    let out = signed_pack(&t, &["--data-only"]);
+    assert!(!std::path::Path::new(&t.join("p/pod_004.json")).exists());
+
+    let o = run(&["pod-verify", &out, "--json"]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    let r: serde_json::Value = serde_json::from_str(&stdout(&o)).unwrap();
+    assert_eq!(
+        (r["ok"].as_u64(), r["missing"].as_u64()),
+        (Some(4), Some(0))
+    );
+    assert_eq!(r["unsigned"], 2);
+    assert_eq!(r["results"][5]["status"], "unsigned");
+
+    let agg = read_json(&ok_agg(&out, &["--min-coverage=1"]));
+    assert_eq!(agg["coverage"], 1.0);
+    assert_eq!(agg["data_only"], true);
+    ok(&["pod-aggregate-verify", &out]);
+}
+
+#[test]
+fn data_only_coverage_counts_data_shards_only_and_never_exceeds_one() {
+    let t = TempDir::new("pod-scope-forge");
+    let out = signed_pack(&t, &[]);
+    // чужой pod_scope.json при полном наборе PoD: паритет в числитель не идёт
+    std::fs::write(t.join("p/pod_scope.json"), b"{\"data_only\": true}").unwrap();
+    let agg = read_json(&ok_agg(&out, &[]));
+    assert_eq!(agg["coverage"], 1.0);
+
+    // без одного data-PoD паритет не добирает покрытие: 3/4
+    std::fs::remove_file(t.join("p/pod_001.json")).unwrap();
+    let err = fails(&["pod-aggregate", &out, "--min-coverage=0.8"], 2);
+    assert!(err.contains("(3/4)"), "{err}");
+
+    // знаменатель — из commit: правка data_shards в манифесте ломает scid
+    let mf_path = t.join("p/manifest.json");
+    let mut mf = read_json(&mf_path);
+    mf["commit"]["erasure_data"] = 2.into();
+    write_json(&mf_path, &mf);
+    let err = fails(&["pod-aggregate", &out], 2);
+    assert!(err.contains("scid"), "{err}");
+    let err = fails(&["pod-verify", &out], 2);
+    assert!(err.contains("scid does not match commit"), "{err}");
+}
+
+/// pod-aggregate → путь к агрегату
+fn ok_agg(dir: &str, extra: &[&str]) -> String {
+    let mut args = vec!["pod-aggregate", dir];
+    args.extend_from_slice(extra);
+    ok(&args);
+    format!("{dir}/pod_aggregate.json")
+}
+
+#[test]
+fn two_signers_over_a_three_shard_pack_meet_quorum() {
+    let t = TempDir::new("pod-committee");
+    let input = write_sample(&t, "in.bin", 3000);
+    let out = t.join("p");
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    ok(&["pack", &input, &out, "--data=2", "--parity=1", &ikm, &salt]);
+    let keys = t.path().join("keys");
+    std::fs::create_dir(&keys).unwrap();
+    std::fs::write(keys.join("a.hex"), SK).unwrap();
+    std::fs::write(keys.join("b.hex"), "44".repeat(32)).unwrap();
+    let sk_dir = format!("--sk-dir={}", keys.display());
+    ok(&["pod-sign", &out, &sk_dir]);
+
+    let signed: Vec<_> = std::fs::read_dir(t.path().join("p"))
+        .unwrap()
+        .filter_map(|e| e.ok()?.file_name().into_string().ok())
+        .filter(|n| n.starts_with("pod_") && n.ends_with(".json") && n.matches('_').count() == 2)
+        .collect();
+    assert_eq!(signed.len(), 6, "{signed:?}");
+
+    let o = run(&["pod-verify", &out, "--quorum=2", "--json"]);
+    assert!(o.status.success(), "{}", stderr(&o));
+    let r: serde_json::Value = serde_json::from_str(&stdout(&o)).unwrap();
+    assert_eq!(r["ok"], 3);
+    assert!(r["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|s| s["signers"] == 2));
+    let agg = read_json(&ok_agg(&out, &["--quorum=2"]));
+    assert_eq!(agg["ok"], 3);
+
+    // один подписант шарда 1 пропал: кворум 2 не набран, кворум 1 — да
+    let gone = signed.iter().find(|n| n.starts_with("pod_001_")).unwrap();
+    std::fs::remove_file(t.path().join("p").join(gone)).unwrap();
+    let err = fails(&["pod-verify", &out, "--quorum=2"], 2);
+    assert!(
+        err.contains("shard 1: 1 valid signer(s), quorum 2"),
+        "{err}"
+    );
+    ok(&["pod-verify", &out, "--quorum=1"]);
+
+    // копия PoD того же подписанта — не второй голос
+    let other = signed
+        .iter()
+        .find(|n| n.starts_with("pod_001_") && *n != gone)
+        .unwrap();
+    std::fs::copy(
+        t.path().join("p").join(other),
+        t.path().join("p").join("pod_001_copy.json"),
+    )
+    .unwrap();
+    fails(&["pod-verify", &out, "--quorum=2"], 2);
+}
+
+/// pod-sign с меткой времени SOURCE_DATE_EPOCH (секунды)
+fn sign_at(out: &str, epoch_secs: u64) {
+    let sk = format!("--sk-hex={SK}");
+    let o = std::process::Command::new(cli())
+        .args(["pod-sign", out, &sk])
+        .env("SOURCE_DATE_EPOCH", epoch_secs.to_string())
+        .output()
+        .unwrap();
+    assert!(o.status.success(), "{}", stderr(&o));
+}
+
+fn verify_json(out: &str, extra: &[&str], code: i32) -> serde_json::Value {
+    let mut args = vec!["pod-verify", out, "--json"];
+    args.extend_from_slice(extra);
+    let o = run(&args);
+    assert_eq!(o.status.code(), Some(code), "{}", stderr(&o));
+    serde_json::from_str(&stdout(&o)).unwrap()
+}
+
+#[test]
+fn freshness_window_accepts_fresh_and_rejects_stale_and_future_pods() {
+    let t = TempDir::new("pod-fresh");
+    let out = signed_pack(&t, &[]);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let r = verify_json(&out, &["--max-age-ms=600000"], 0);
+    assert_eq!((r["ok"].as_u64(), r["stale"].as_u64()), (Some(6), Some(0)));
+
+    // сутки назад: без флага проверка по-прежнему не смотрит на возраст
+    sign_at(&out, now - 86_400);
+    verify_json(&out, &[], 0);
+    let r = verify_json(&out, &["--max-age-ms=600000"], 2);
+    assert_eq!((r["ok"].as_u64(), r["stale"].as_u64()), (Some(0), Some(6)));
+    assert_eq!(r["results"][0]["status"], "stale");
+
+    // час вперёд: дальше допуска 60 с по умолчанию, но в пределах --max-skew-ms=2h
+    sign_at(&out, now + 3_600);
+    let r = verify_json(&out, &["--max-age-ms=600000"], 2);
+    assert_eq!(r["stale"].as_u64(), Some(6));
+    let r = verify_json(&out, &["--max-age-ms=600000", "--max-skew-ms=7200000"], 0);
+    assert_eq!(r["ok"].as_u64(), Some(6));
+}
+
+#[test]
+fn aggregate_verify_catches_a_pod_swapped_after_aggregation() {
+    let t = TempDir::new("pod-agg-swap");
+    let out = signed_pack(&t, &[]);
+    ok_agg(&out, &[]);
+    ok(&["pod-aggregate-verify", &out]);
+
+    // валидный PoD другого подписанта на месте pod_002: подписи сходятся, корень — нет
+    let pod = |i: usize| t.join(&format!("p/pod_{i:03}.json"));
+    let originals: Vec<Vec<u8>> = (0..6).map(|i| std::fs::read(pod(i)).unwrap()).collect();
+    let sk2 = format!("--sk-hex={IKM2}");
+    ok(&["pod-sign", &out, &sk2]);
+    for (i, body) in originals.iter().enumerate() {
+        if i != 2 {
+            std::fs::write(pod(i), body).unwrap();
+        }
+    }
+    ok(&["pod-verify", &out]);
+    let err = fails(&["pod-aggregate-verify", &out], 2);
+    assert!(err.contains("pod_root mismatch"), "{err}");
+    assert!(!err.contains("no longer verifies"), "{err}");
+
+    // PoD, переложенные под чужой индекс, называются поимённо
+    std::fs::write(pod(2), &originals[2]).unwrap();
+    std::fs::write(pod(1), &originals[3]).unwrap();
+    std::fs::write(pod(3), &originals[1]).unwrap();
+    let o = run(&["pod-aggregate-verify", &out, "--json"]);
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    let r: serde_json::Value = serde_json::from_str(&stdout(&o)).unwrap();
+    assert_eq!(r["bad_indexes"], serde_json::json!([1, 3]));
+}
+
+/// Тело PEM-блока с меткой `label` (DER)
+fn pem_der(text: &str, label: &str) -> Vec<u8> {
+    use base64::Engine as _;
+    let body: String = text
+        .lines()
+        .skip_while(|l| *l != format!("-----BEGIN {label}-----"))
+        .skip(1)
+        .take_while(|l| *l != format!("-----END {label}-----"))
+        .collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(body)
+        .unwrap()
+}
+
+#[test]
+fn keygen_pem_and_jwk_parse_back_to_the_same_keys() {
+    use base64::Engine as _;
+    let t = TempDir::new("keygen-formats");
+    let seed: [u8; 32] = hex::decode(SK).unwrap().try_into().unwrap();
+    let pk = ed25519_dalek::SigningKey::from_bytes(&seed)
+        .verifying_key()
+        .to_bytes();
+
+    let dir = t.join("pem");
+    let seed_flag = format!("--seed-hex={SK}");
+    ok(&[
+        "keygen",
+        &format!("--out-dir={dir}"),
+        &seed_flag,
+        "--format=pem",
+    ]);
+    assert_eq!(
+        std::fs::read_to_string(format!("{dir}/pk.hex")).unwrap(),
+        hex::encode(pk)
+    );
+    // SubjectPublicKeyInfo: SEQ { SEQ { OID 1.3.101.112 }, BIT STRING 00 || pk }
+    let spki = pem_der(
+        &std::fs::read_to_string(format!("{dir}/pk.pem")).unwrap(),
+        "PUBLIC KEY",
+    );
+    assert_eq!(spki.len(), 44);
+    assert_eq!(spki[4..9], [0x06, 0x03, 0x2b, 0x65, 0x70]);
+    let parsed: [u8; 32] = spki[12..].try_into().unwrap();
+    assert_eq!(
+        ed25519_dalek::VerifyingKey::from_bytes(&parsed)
+            .unwrap()
+            .to_bytes(),
+        pk
+    );
+    // PKCS#8: OCTET STRING с 32-байтным seed в конце
+    let pkcs8 = pem_der(
+        &std::fs::read_to_string(format!("{dir}/sk.pem")).unwrap(),
+        "PRIVATE KEY",
+    );
+    assert_eq!(pkcs8[pkcs8.len() - 32..], seed);
+
+    let dir = t.join("jwk");
+    ok(&[
+        "keygen",
+        &format!("--out-dir={dir}"),
+        &seed_flag,
+        "--format=jwk",
+    ]);
+    let jwk = read_json(&format!("{dir}/pk.jwk"));
+    assert_eq!(
+        (jwk["kty"].as_str(), jwk["crv"].as_str()),
+        (Some("OKP"), Some("Ed25519"))
+    );
+    let x = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(jwk["x"].as_str().unwrap())
+        .unwrap();
+    assert_eq!(x, pk);
+    assert!(jwk.get("d").is_none());
+}
+
+#[test]
+fn min_coverage_gates_just_below_and_just_above() {
+    let t = TempDir::new("pod-min-coverage");
+    let out = signed_pack(&t, &[]);
+    std::fs::remove_file(t.join("p/pod_000.json")).unwrap();
+    let agg_path = t.join("p/pod_aggregate.json");
+
+    // 5/6 ≈ 0.8333: порог чуть выше — отказ без записи агрегата
+    let err = fails(&["pod-aggregate", &out, "--min-coverage=0.84"], 2);
+    assert!(err.contains("(5/6)"), "{err}");
+    assert!(!std::path::Path::new(&agg_path).exists());
+
+    let agg = read_json(&ok_agg(&out, &["--min-coverage=0.83"]));
+    assert_eq!(agg["min_coverage"], 0.83);
+    assert!(
+        (agg["coverage"].as_f64().unwrap() - 5.0 / 6.0).abs() < 1e-9,
+        "{agg}"
+    );
+    assert_eq!(agg["included_indexes"], serde_json::json!([1, 2, 3, 4, 5]));
+}
+
+#[test]
+fn deterministic_runs_produce_identical_bytes() {
+    let t = TempDir::new("pod-deterministic");
+    let out = signed_pack(&t, &[]);
+    let sk = format!("--sk-hex={SK}");
+    let snapshot = || {
+        ok(&["pod-sign", &out, &sk, "--deterministic"]);
+        ok(&["pod-aggregate", &out, "--deterministic"]);
+        ["pod_000.json", "pod_005.json", "pod_aggregate.json"]
+            .map(|f| std::fs::read(t.join(&format!("p/{f}"))).unwrap())
+    };
+    let first = snapshot();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    assert_eq!(first, snapshot());
+    let agg: serde_json::Value = serde_json::from_slice(&first[2]).unwrap();
+    assert_eq!(agg["ts_unix_ms"], 0);
+
+    // SOURCE_DATE_EPOCH задаёт метку вместо нуля
+    let o = std::process::Command::new(cli())
+        .args(["pod-aggregate", &out, "--deterministic"])
+        .env("SOURCE_DATE_EPOCH", "1700000000")
+        .output()
+        .unwrap();
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(
+        read_json(&t.join("p/pod_aggregate.json"))["ts_unix_ms"],
+        1_700_000_000_000u64
+    );
+}
+
+#[test]
+fn append_signs_only_the_newly_arrived_shard() {
+    let t = TempDir::new("pod-append");
+    let input = write_sample(&t, "in.bin", 20_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+    // shard_003 ещё не доехал до узла
+    let late = t.join("shard_003.late");
+    std::fs::rename(t.join("p/shard_003.bin"), &late).unwrap();
+    let sk = format!("--sk-hex={SK}");
+    ok(&["pod-sign", &out, &sk]);
+    assert!(!std::path::Path::new(&t.join("p/pod_003.json")).exists());
+    let before: Vec<Vec<u8>> = [0, 1, 2, 4, 5]
+        .iter()
+        .map(|i| std::fs::read(t.join(&format!("p/pod_{i:03}.json"))).unwrap())
+        .collect();
+
+    std::fs::rename(&late, t.join("p/shard_003.bin")).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let msg = ok(&["pod-sign", &out, &sk, "--append"]);
+    assert!(msg.contains("1 written, 5 kept"), "{msg}");
+    let after: Vec<Vec<u8>> = [0, 1, 2, 4, 5]
+        .iter()
+        .map(|i| std::fs::read(t.join(&format!("p/pod_{i:03}.json"))).unwrap())
+        .collect();
+    assert_eq!(before, after);
+    ok(&["pod-verify", &out]);
+}
+
+#[test]
+fn base64_keys_round_trip_through_keygen_and_pod_sign() {
+    use base64::Engine as _;
+    let t = TempDir::new("keygen-b64");
+    let keys = t.join("keys");
+    let seed_flag = format!("--seed-hex={SK}");
+    ok(&[
+        "keygen",
+        &format!("--out-dir={keys}"),
+        &seed_flag,
+        "--encoding=base64",
+    ]);
+    assert!(!std::path::Path::new(&format!("{keys}/sk.hex")).exists());
+    let sk_b64 = std::fs::read_to_string(format!("{keys}/sk.b64")).unwrap();
+    let b64 = base64::engine::general_purpose::STANDARD;
+    assert_eq!(b64.decode(sk_b64.trim()).unwrap(), hex::decode(SK).unwrap());
+
+    // подпись base64-ключом побайтно совпадает с подписью тем же ключом в hex
+    let input = write_sample(&t, "in.bin", 20_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+    let pod0 = t.join("p/pod_000.json");
+    ok(&[
+        "pod-sign",
+        &out,
+        &format!("--sk-hex={SK}"),
+        "--deterministic",
+    ]);
+    let from_hex = std::fs::read(&pod0).unwrap();
+    ok(&[
+        "pod-sign",
+        &out,
+        &format!("--sk-hex={}", sk_b64.trim()),
+        "--deterministic",
+    ]);
+    assert_eq!(std::fs::read(&pod0).unwrap(), from_hex);
+
+    // --sk-dir подхватывает *.b64 (в каталоге комитета — только секреты, pk.b64 убираем)
+    for i in 0..6 {
+        std::fs::remove_file(t.join(&format!("p/pod_{i:03}.json"))).unwrap();
+    }
+    std::fs::remove_file(format!("{keys}/pk.b64")).unwrap();
+    ok(&["pod-sign", &out, &format!("--sk-dir={keys}")]);
+    ok(&["pod-verify", &out]);
+}
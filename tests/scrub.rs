@@ -0,0 +1,81 @@
+//! scrub: поимённая порча шардов и недоверие к несвязанному манифесту
+
+mod common;
+
+use common::*;
+
+fn packed(t: &TempDir) -> String {
+    let input = write_sample(t, "in.bin", 10_000);
+    let out = t.join("p");
+    pack(&input, &out, &[]);
+    out
+}
+
+#[test]
+fn scrub_names_exactly_the_flipped_shard() {
+    let t = TempDir::new("scrub-flip");
+    let out = packed(&t);
+    ok(&["scrub", &out]);
+    flip_byte(&format!("{out}/shard_003.bin"), 5);
+    fails(&["scrub", &out], 2);
+    let o = run(&["scrub", &out, "--json"]);
+    let report: serde_json::Value = serde_json::from_str(stdout(&o).trim()).unwrap();
+    assert_eq!(report["corrupt"][0]["index"], 3, "{report}");
+    assert_eq!(report["corrupt"].as_array().unwrap().len(), 1, "{report}");
+}
+
+#[test]
+fn scrub_names_the_flipped_shard_of_a_stream() {
+    let t = TempDir::new("scrub-stream");
+    let input = write_sample(&t, "in.bin", 10_000);
+    let out = t.join("s");
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    ok(&[
+        "pack-stream",
+        &input,
+        &out,
+        "--data=3",
+        "--parity=2",
+        &ikm,
+        &salt,
+        "--chunk=4096",
+    ]);
+    ok(&["scrub", &out]);
+    flip_byte(&format!("{out}/shard_002.bin"), 4100);
+    let o = run(&["scrub", &out, "--json"]);
+    assert_eq!(o.status.code(), Some(2));
+    let report: serde_json::Value = serde_json::from_str(stdout(&o).trim()).unwrap();
+    assert_eq!(
+        report["corrupt"],
+        serde_json::json!([{"index": 2, "reason": "leaf_hash"}])
+    );
+}
+
+#[test]
+fn scrub_rejects_short_leaf_list_without_panicking() {
+    let t = TempDir::new("scrub-short");
+    let out = packed(&t);
+    let mf = format!("{out}/manifest.json");
+    let mut m = read_json(&mf);
+    m["shard_leaves"].as_array_mut().unwrap().pop();
+    write_json(&mf, &m);
+    let err = fails(&["scrub", &out], 2);
+    assert!(
+        err.contains("shard_leaves has 5 entries, expected 6"),
+        "{err}"
+    );
+}
+
+#[test]
+fn scrub_refuses_leaves_that_do_not_give_the_committed_root() {
+    // по подменённому листу исправный шард 0 был бы назван битым
+    let t = TempDir::new("scrub-forged");
+    let out = packed(&t);
+    let mf = format!("{out}/manifest.json");
+    let mut m = read_json(&mf);
+    m["shard_leaves"][0] = "00".repeat(32).into();
+    write_json(&mf, &m);
+    let err = fails(&["scrub", &out], 2);
+    assert!(err.contains("do not give commit.merkle_root"), "{err}");
+}
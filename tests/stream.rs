@@ -0,0 +1,484 @@
+//! Stream RS: pack-stream / unpack-stream / chunk-proof
+
+mod common;
+
+use common::*;
+use sha2::{Digest, Sha256};
+
+fn stream_pack(t: &TempDir, len: usize, extra: &[&str]) -> (String, String) {
+    let input = write_sample(t, "in.bin", len);
+    let out = t.join("s");
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let mut args = vec![
+        "pack-stream",
+        &input,
+        &out,
+        "--data=3",
+        "--parity=2",
+        &ikm,
+        &salt,
+        "--chunk=4096",
+    ];
+    args.extend_from_slice(extra);
+    ok(&args);
+    (input, out)
+}
+
+#[test]
+fn chunk_verify_rejects_a_tampered_leaf() {
+    let t = TempDir::new("chunk-leaf");
+    let (_, out) = stream_pack(&t, 20_000, &[]);
+    let proof = t.join("proof.json");
+    let out_flag = format!("--out={proof}");
+    ok(&["chunk-proof", &out, "--index=2", &out_flag]);
+    let proof_flag = format!("--proof={proof}");
+    ok(&["chunk-verify", &out, &proof_flag]);
+
+    let mut p = read_json(&proof);
+    let mut leaf = p["leaf_hex"].as_str().unwrap().to_string();
+    let flipped = if leaf.starts_with('0') { "1" } else { "0" };
+    leaf.replace_range(0..1, flipped);
+    p["leaf_hex"] = leaf.into();
+    write_json(&proof, &p);
+    let err = fails(&["chunk-verify", &out, &proof_flag], 2);
+    assert!(err.contains("does not match proof leaf"), "{err}");
+}
+
+#[test]
+fn chunk_proof_round_trip_and_verify_pack_stream_bind_chunk_root() {
+    let t = TempDir::new("chunk-bound");
+    let (_, out) = stream_pack(&t, 20_000, &[]);
+    ok(&["verify-pack-stream", &out]);
+    let proof = t.join("proof.json");
+    let out_flag = format!("--out={proof}");
+    let proof_flag = format!("--proof={proof}");
+    for i in 0..5 {
+        ok(&["chunk-proof", &out, &format!("--index={i}"), &out_flag]);
+        ok(&["chunk-verify", &out, &proof_flag]);
+    }
+
+    // другой chunk_root при том же scid: коммит не сходится
+    let mf = format!("{out}/manifest_stream.json");
+    let mut m = read_json(&mf);
+    m["chunk_root_hex"] = "00".repeat(32).into();
+    write_json(&mf, &m);
+    let err = fails(&["chunk-verify", &out, &proof_flag], 2);
+    assert!(err.contains("not bound by scid"), "{err}");
+    fails(&["verify-pack-stream", &out], 2);
+}
+
+#[test]
+fn rewritten_chunk_with_a_matching_chunk_root_is_rejected() {
+    // один чанк: chunk_root = лист = sha256(tag ‖ idx ‖ ct), ct — первые ct_len байт data-шардов
+    let t = TempDir::new("chunk-forge");
+    let (_, out) = stream_pack(&t, 1000, &[]);
+    let mf = format!("{out}/manifest_stream.json");
+    let mut m = read_json(&mf);
+    assert_eq!(m["chunks"], 1);
+    flip_byte(&format!("{out}/shard_000.bin"), 0);
+    let mut ct = Vec::new();
+    for i in 0..3 {
+        ct.extend(std::fs::read(format!("{out}/shard_{i:03}.bin")).unwrap());
+    }
+    ct.truncate(m["ct_len_per_chunk"].as_u64().unwrap() as usize);
+    let mut h = Sha256::new();
+    h.update(b"s3p-chunk-leaf-v1");
+    h.update(0u64.to_le_bytes());
+    h.update(&ct);
+    let forged: String = h.finalize().iter().map(|b| format!("{b:02x}")).collect();
+    m["chunk_root_hex"] = forged.into();
+    write_json(&mf, &m);
+
+    // шарды и подменённый корень согласованы, но scid его не связывает — доказательство не выдаётся
+    let err = fails(&["chunk-proof", &out, "--index=0"], 2);
+    assert!(err.contains("not bound by scid"), "{err}");
+}
+
+#[test]
+fn dry_run_never_packs_even_with_an_invalid_chunk() {
+    let t = TempDir::new("stream-dry-run");
+    let input = write_sample(&t, "in.bin", 5000);
+    let out = t.join("s");
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let base = [
+        "pack-stream",
+        &input,
+        &out,
+        "--data=3",
+        "--parity=2",
+        &ikm,
+        &salt,
+    ];
+
+    let mut args = base.to_vec();
+    args.extend(["--chunk=0", "--dry-run"]);
+    let err = fails(&args, 2);
+    assert!(err.contains("--chunk must be > 0"), "{err}");
+    assert!(!t.path().join("s").exists());
+
+    let mut args = base.to_vec();
+    args.extend(["--chunk=4096", "--dry-run"]);
+    let report = ok(&args);
+    assert!(report.contains("nothing written"), "{report}");
+    assert!(!t.path().join("s").exists());
+}
+
+/// pack-stream, прерванный после первой записи stream_progress.json (троттлинг даёт время)
+fn interrupted_stream_pack(t: &TempDir, input: &str) -> String {
+    let out = t.join("s");
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let mut child = std::process::Command::new(cli())
+        .args([
+            "pack-stream",
+            input,
+            &out,
+            "--data=2",
+            "--parity=1",
+            &ikm,
+            &salt,
+            "--chunk=1024",
+            "--write-rate=100000",
+        ])
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let progress = t.path().join("s/stream_progress.json");
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(20);
+    while !progress.exists() {
+        assert!(std::time::Instant::now() < deadline, "no progress file");
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(!t.path().join("s/manifest_stream.json").exists());
+    out
+}
+
+fn resume(input: &str, out: &str, ikm: &str, salt: &str) -> std::process::Output {
+    let ikm = format!("--ikm-hex={ikm}");
+    let salt = format!("--salt-hex={salt}");
+    run(&[
+        "pack-stream",
+        input,
+        out,
+        "--data=2",
+        "--parity=1",
+        &ikm,
+        &salt,
+        "--chunk=1024",
+        "--resume",
+    ])
+}
+
+#[test]
+fn resume_refuses_other_key_salt_or_input_and_completes_with_the_same() {
+    let t = TempDir::new("stream-resume");
+    let input = write_sample(&t, "in.bin", 300 * 1024);
+    let out = interrupted_stream_pack(&t, &input);
+
+    let o = resume(&input, &out, IKM2, SALT);
+    assert_eq!(o.status.code(), Some(2));
+    assert!(stderr(&o).contains("key differs"), "{}", stderr(&o));
+    let o = resume(&input, &out, IKM, "a1a2a3a4a5a6a7a8");
+    assert_eq!(o.status.code(), Some(2));
+    assert!(stderr(&o).contains("--salt-hex differs"), "{}", stderr(&o));
+    let other = t.join("other.bin");
+    let mut bytes = std::fs::read(&input).unwrap();
+    bytes[100] ^= 1;
+    std::fs::write(&other, bytes).unwrap();
+    let o = resume(&other, &out, IKM, SALT);
+    assert_eq!(o.status.code(), Some(2));
+    assert!(stderr(&o).contains("input differs"), "{}", stderr(&o));
+
+    let o = resume(&input, &out, IKM, SALT);
+    assert!(o.status.success(), "{}", stderr(&o));
+    let back = t.join("back.bin");
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    ok(&["unpack-stream", &out, &back, &ikm, &salt]);
+    assert_eq!(std::fs::read(back).unwrap(), std::fs::read(input).unwrap());
+}
+
+#[test]
+fn resume_refuses_a_progress_file_without_fingerprints() {
+    let t = TempDir::new("stream-resume-old");
+    let input = write_sample(&t, "in.bin", 300 * 1024);
+    let out = interrupted_stream_pack(&t, &input);
+    let progress = t.join("s/stream_progress.json");
+    let mut pr = read_json(&progress);
+    let obj = pr.as_object_mut().unwrap();
+    for k in ["salt_hex", "key_commit_hex", "input_prefix_sha256"] {
+        obj.remove(k);
+    }
+    write_json(&progress, &pr);
+    let o = resume(&input, &out, IKM, SALT);
+    assert_eq!(o.status.code(), Some(2));
+    assert!(stderr(&o).contains("no key/input fingerprints"));
+}
+
+fn unpack_stream(dir: &str, out: &str) -> std::process::Output {
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    run(&["unpack-stream", dir, out, &ikm, &salt])
+}
+
+#[test]
+fn awkward_chunk_to_data_ratios_round_trip_with_recorded_shard_len() {
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    // ct_len_per_chunk = chunk + 16 не делится на data ни в одной из пар
+    for (chunk, data, parity) in [(1000, 7, 3), (4097, 5, 2), (65, 13, 1)] {
+        let t = TempDir::new("stream-ratio");
+        let input = write_sample(&t, "in.bin", chunk * 3 + 11);
+        let out = t.join("s");
+        let (c, d, p) = (
+            format!("--chunk={chunk}"),
+            format!("--data={data}"),
+            format!("--parity={parity}"),
+        );
+        ok(&["pack-stream", &input, &out, &d, &p, &ikm, &salt, &c]);
+        let m = read_json(&format!("{out}/manifest_stream.json"));
+        let shard_len = m["shard_len"].as_u64().unwrap() as usize;
+        assert_eq!(shard_len, (chunk + 16).div_ceil(data), "{chunk}/{data}");
+        assert_eq!(
+            std::fs::metadata(format!("{out}/shard_000.bin"))
+                .unwrap()
+                .len() as usize,
+            shard_len * 4
+        );
+
+        let got = t.join("out.bin");
+        let o = unpack_stream(&out, &got);
+        assert!(o.status.success(), "{chunk}/{data}: {}", stderr(&o));
+        assert_eq!(std::fs::read(&got).unwrap(), std::fs::read(&input).unwrap());
+    }
+}
+
+#[test]
+fn legacy_manifest_without_shard_len_still_unpacks() {
+    let t = TempDir::new("stream-legacy");
+    let (input, out) = stream_pack(&t, 9000, &[]);
+    let mf = format!("{out}/manifest_stream.json");
+    let mut m = read_json(&mf);
+    m.as_object_mut().unwrap().remove("shard_len");
+    write_json(&mf, &m);
+    let got = t.join("out.bin");
+    let o = unpack_stream(&out, &got);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(std::fs::read(&got).unwrap(), std::fs::read(&input).unwrap());
+}
+
+#[test]
+fn user_nonce_base_with_nonzero_low_bytes_round_trips() {
+    let t = TempDir::new("stream-nonce-base");
+    let base = format!("--nonce-base-hex={}", "ff".repeat(24));
+    let (input, out) = stream_pack(&t, 4096 * 5 + 3, &[&base]);
+    let got = t.join("out.bin");
+    let o = unpack_stream(&out, &got);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(std::fs::read(&got).unwrap(), std::fs::read(&input).unwrap());
+
+    let input = write_sample(&t, "in2.bin", 100);
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let short = format!("--nonce-base-hex={}", "ff".repeat(23));
+    let err = fails(
+        &[
+            "pack-stream",
+            &input,
+            &t.join("s2"),
+            "--data=3",
+            "--parity=2",
+            &ikm,
+            &salt,
+            "--chunk=4096",
+            &short,
+        ],
+        2,
+    );
+    assert!(err.contains("must be 24 bytes"), "{err}");
+}
+
+#[test]
+fn empty_input_is_one_authenticated_chunk_and_unpacks_empty() {
+    let t = TempDir::new("empty-stream");
+    let (_, out) = stream_pack(&t, 0, &[]);
+    let m = read_json(&format!("{out}/manifest_stream.json"));
+    assert_eq!(
+        (m["size_bytes"].as_u64(), m["chunks"].as_u64()),
+        (Some(0), Some(1))
+    );
+    ok(&["verify-pack-stream", &out]);
+
+    let got = t.join("out.bin");
+    let o = unpack_stream(&out, &got);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(std::fs::read(&got).unwrap(), b"");
+
+    // пустой чанк всё равно под AEAD: порча шарда не даёт «успешно пустой» выход
+    flip_byte(&format!("{out}/shard_000.bin"), 0);
+    flip_byte(&format!("{out}/shard_001.bin"), 0);
+    flip_byte(&format!("{out}/shard_002.bin"), 0);
+    assert!(!unpack_stream(&out, &t.join("out2.bin")).status.success());
+}
+
+#[test]
+fn tampered_scid_string_stops_unpack_stream() {
+    let t = TempDir::new("stream-scid");
+    let (_, out) = stream_pack(&t, 10_000, &[]);
+    let mf_path = format!("{out}/manifest_stream.json");
+    let mut m = read_json(&mf_path);
+    m["scid"] = "00".repeat(32).into();
+    write_json(&mf_path, &m);
+
+    let o = unpack_stream(&out, &t.join("out.bin"));
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    assert!(
+        stderr(&o).contains("does not match commit"),
+        "{}",
+        stderr(&o)
+    );
+    assert!(!t.path().join("out.bin").exists());
+}
+
+#[test]
+fn verify_pack_stream_reports_a_truncated_shard_file() {
+    let t = TempDir::new("stream-truncated");
+    let (_, out) = stream_pack(&t, 4096 * 4 + 100, &[]);
+    ok(&["verify-pack-stream", &out]);
+    let m = read_json(&format!("{out}/manifest_stream.json"));
+    let shard_len = m["shard_len"].as_u64().unwrap();
+    let expected = m["chunks"].as_u64().unwrap() * shard_len;
+
+    // последняя полоса шарда 2 потеряна
+    let victim = format!("{out}/shard_002.bin");
+    let mut body = std::fs::read(&victim).unwrap();
+    body.truncate((expected - shard_len) as usize);
+    std::fs::write(&victim, body).unwrap();
+
+    let o = run(&["verify-pack-stream", &out, "--json"]);
+    assert_eq!(o.status.code(), Some(2), "{}", stderr(&o));
+    let r: serde_json::Value = serde_json::from_str(stdout(&o).trim()).unwrap();
+    assert_eq!(
+        r["bad_sizes"],
+        serde_json::json!([{"index": 2, "expected": expected, "actual": expected - shard_len}])
+    );
+    assert!(
+        r["mismatches"]
+            .as_array()
+            .unwrap()
+            .contains(&"shard_size".into()),
+        "{r}"
+    );
+}
+
+fn extract(out: &str, got: &str, offset: usize, len: usize) -> std::process::Output {
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    let (o, l) = (format!("--offset={offset}"), format!("--len={len}"));
+    run(&["extract-stream", out, got, &o, &l, &ikm, &salt])
+}
+
+#[test]
+fn extract_stream_returns_exact_slices_across_chunks_and_clamps_at_eof() {
+    let t = TempDir::new("stream-extract");
+    let size = 4096 * 5 + 123;
+    let (input, out) = stream_pack(&t, size, &[]);
+    let original = std::fs::read(&input).unwrap();
+
+    // внутри чанка, через границу двух чанков, через три чанка, хвост за EOF
+    for (offset, len) in [(100, 50), (4096 - 10, 20), (4000, 8300), (size - 23, 1000)] {
+        let got = t.join("slice.bin");
+        let o = extract(&out, &got, offset, len);
+        assert!(o.status.success(), "{offset}+{len}: {}", stderr(&o));
+        let end = (offset + len).min(size);
+        assert_eq!(
+            std::fs::read(&got).unwrap(),
+            original[offset..end],
+            "{offset}+{len}"
+        );
+    }
+
+    let got = t.join("past.bin");
+    let o = extract(&out, &got, size + 10, 5);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert!(stderr(&o).contains("range clamped"), "{}", stderr(&o));
+    assert_eq!(std::fs::read(&got).unwrap(), b"");
+}
+
+#[test]
+fn write_rate_throttles_aggregate_shard_writes() {
+    let t = TempDir::new("stream-write-rate");
+    let rate = 20_000u64;
+    let started = std::time::Instant::now();
+    let (input, out) = stream_pack(&t, 4096 * 4, &[&format!("--write-rate={rate}")]);
+    let elapsed = started.elapsed().as_secs_f64();
+
+    // все байты во всех shard-файлах (5 шардов × 4 полосы)
+    let written: u64 = (0..5)
+        .map(|i| {
+            std::fs::metadata(format!("{out}/shard_{i:03}.bin"))
+                .unwrap()
+                .len()
+        })
+        .sum();
+    let floor = written as f64 / rate as f64;
+    assert!(
+        elapsed >= 0.9 * floor,
+        "{elapsed:.2}s < {floor:.2}s for {written} B"
+    );
+
+    let got = t.join("out.bin");
+    let o = unpack_stream(&out, &got);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(std::fs::read(&got).unwrap(), std::fs::read(&input).unwrap());
+
+    let err = fails(
+        &[
+            "pack-stream",
+            &input,
+            &t.join("s2"),
+            "--data=3",
+            "--parity=2",
+            &format!("--ikm-hex={IKM}"),
+            &format!("--salt-hex={SALT}"),
+            "--chunk=4096",
+            "--write-rate=0",
+        ],
+        2,
+    );
+    assert!(err.contains("--write-rate expects bytes/s > 0"), "{err}");
+}
+
+#[test]
+fn chunk_auto_is_recorded_and_round_trips() {
+    let t = TempDir::new("stream-chunk-auto");
+    let input = write_sample(&t, "in.bin", 200_000);
+    let out = t.join("s");
+    let ikm = format!("--ikm-hex={IKM}");
+    let salt = format!("--salt-hex={SALT}");
+    ok(&[
+        "pack-stream",
+        &input,
+        &out,
+        "--data=3",
+        "--parity=2",
+        &ikm,
+        &salt,
+        "--chunk=auto",
+    ]);
+    let m = read_json(&format!("{out}/manifest_stream.json"));
+    // 200 КБ / 1000 — меньше нижней границы 64 KiB
+    assert_eq!(
+        (m["chunk_size"].as_u64(), m["chunks"].as_u64()),
+        (Some(65_536), Some(4))
+    );
+
+    let got = t.join("out.bin");
+    let o = unpack_stream(&out, &got);
+    assert!(o.status.success(), "{}", stderr(&o));
+    assert_eq!(std::fs::read(&got).unwrap(), std::fs::read(&input).unwrap());
+}